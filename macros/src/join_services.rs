@@ -13,9 +13,25 @@ struct ShutdownPrefix
 	comma_token: Token! [,]
 }
 
+mod kw
+{
+	syn::custom_keyword! (deadline);
+}
+
+#[allow (dead_code)]
+#[derive (Parse)]
+struct DeadlinePrefix
+{
+	deadline_token: kw::deadline,
+	eq_token: Token! [=],
+	deadline_expr: Expr,
+	comma_token: Token! [,]
+}
+
 struct JoinServicesInput
 {
 	shutdown_prefix: Option <ShutdownPrefix>,
+	deadline_prefix: Option <DeadlinePrefix>,
 	service_exprs: Punctuated <Expr, Token! [,]>
 }
 
@@ -29,15 +45,23 @@ impl Parse for JoinServicesInput
 		}
 		else { None };
 
+		let deadline_prefix =
+			if input . peek (kw::deadline) && input . peek2 (Token! [=])
+		{
+			Some (input . parse ()?)
+		}
+		else { None };
+
 		let service_exprs = Punctuated::parse_terminated (input)?;
 
-		Ok (Self {shutdown_prefix, service_exprs})
+		Ok (Self {shutdown_prefix, deadline_prefix, service_exprs})
 	}
 }
 
 fn join_services_inner
 (
 	shutdown_expr: Option <Expr>,
+	deadline_expr: Option <Expr>,
 	mut service_exprs: Punctuated <Expr, Token! [,]>
 )
 -> proc_macro2::TokenStream
@@ -64,12 +88,71 @@ fn join_services_inner
 		. map (|i| i . into ())
 		. collect ();
 
+	let (outputs, shutdown_outputs) = match deadline_expr
+	{
+		None =>
+		(
+			quote!
+			(
+				#(compute_graph::service_handle::ServiceHandle::take_output (&mut services . #service_idx)
+					. expect ("expected completed service"),)*
+			),
+			quote!
+			{
+				#(compute_graph::service_handle::ServiceHandle::shutdown (&mut services . #service_idx);)*
+
+				tokio::join! (#(services . #service_idx),*)
+			}
+		),
+		// Services that miss the deadline are aborted, so every output is
+		// wrapped in a ShutdownOutcome.
+		Some (deadline_expr) =>
+		(
+			quote!
+			(
+				#(compute_graph::service_handle::ShutdownOutcome::Graceful
+				(
+					compute_graph::service_handle::ServiceHandle::take_output (&mut services . #service_idx)
+						. expect ("expected completed service")
+				),)*
+			),
+			quote!
+			{
+				let deadline: tokio::time::Duration = #deadline_expr;
+
+				#(compute_graph::service_handle::ServiceHandle::shutdown (&mut services . #service_idx);)*
+
+				tokio::join!
+				(
+					#(compute_graph::service_handle::ServiceHandle::shutdown_with_timeout (&mut services . #service_idx, deadline)),*
+				)
+			}
+		)
+	};
+
 	quote!
 	{
 		{
-			let mut services = (#service_exprs);
+			// Services constructed by the macro show up as children of the
+			// join in the introspection registry.
+			let introspection_node = compute_graph::introspection::register
+			(
+				"join_services",
+				compute_graph::introspection::NodeKind::Join
+			);
 
-			match tokio::try_join!
+			// Evaluated in place, so the expressions can still await and use ?.
+			let mut services =
+			{
+				let _scope = compute_graph::introspection::enter
+				(
+					compute_graph::introspection::node_id (&introspection_node)
+				);
+
+				(#service_exprs)
+			};
+
+			let outputs = match tokio::try_join!
 			(
 				#shutdown_branch
 				#(async
@@ -78,21 +161,39 @@ fn join_services_inner
 						. await
 						. expect ("expected service handle that could still produce an output")
 						. into_result ()
+						. map_err (std::mem::drop)
 				}),*
 			)
 			{
-				std::result::Result::Ok (_) =>
-				(
-					#(compute_graph::service_handle::ServiceHandle::take_output (&mut services . #service_idx)
-						. expect ("expected completed service"),)*
-				),
-				std::result::Result::Err (()) =>
-				{
-					#(compute_graph::service_handle::ServiceHandle::shutdown (&mut services . #service_idx);)*
+				std::result::Result::Ok (_) => (#outputs),
+				std::result::Result::Err (()) => {#shutdown_outputs}
+			};
 
-					tokio::join! (#(services . #service_idx),*)
-				}
+			// The join reports the first failure among its services.
+			{
+				#[allow (unused_imports)]
+				use compute_graph::introspection::{
+					ReportExitStatus,
+					ReportNoExitStatus
+				};
+
+				let exit_status =
+				[
+					#((&compute_graph::introspection::ExitReporter (&outputs . #service_idx))
+						. reported_exit_status ()),*
+				]
+					. into_iter ()
+					. flatten ()
+					. reduce (compute_graph::exit_status::ExitStatus::and);
+
+				compute_graph::introspection::finish
+				(
+					introspection_node,
+					exit_status
+				);
 			}
+
+			outputs
 		}
 	}
 }
@@ -100,11 +201,13 @@ fn join_services_inner
 fn try_join_services_impl (input: proc_macro::TokenStream)
 -> Result <proc_macro2::TokenStream>
 {
-	let JoinServicesInput {shutdown_prefix, service_exprs} = parse (input)?;
+	let JoinServicesInput {shutdown_prefix, deadline_prefix, service_exprs} =
+		parse (input)?;
 
 	let shutdown_expr = shutdown_prefix . map (|prefix| prefix . shutdown_expr);
+	let deadline_expr = deadline_prefix . map (|prefix| prefix . deadline_expr);
 
-	Ok (join_services_inner (shutdown_expr, service_exprs))
+	Ok (join_services_inner (shutdown_expr, deadline_expr, service_exprs))
 }
 
 pub fn join_services_impl (input: proc_macro::TokenStream)
@@ -1,5 +1,6 @@
 use darling::{FromMeta, Error};
 use darling::ast::NestedMeta;
+use darling::util::Flag;
 use syn::{
 	FnArg,
 	Ident,
@@ -10,18 +11,31 @@ use syn::{
 use syn::parse::Result;
 use quote::{format_ident, quote};
 
-use crate::util::map_return_type;
+use crate::util::{instrument_body, map_return_type};
 
 #[derive (FromMeta)]
 struct ServiceInput
 {
-	shutdown: Option <Ident>
+	shutdown: Option <Ident>,
+	// Use a shutdown token instead of a oneshot receiver.
+	token: Flag,
+	// Derive the shutdown token from the token passed in this argument.
+	parent: Option <Ident>
+}
+
+enum ShutdownSource
+{
+	Oneshot,
+	Token,
+	Parent (Ident)
 }
 
 fn gen_cancellable_service (function: ItemFn) -> proc_macro2::TokenStream
 {
 	let ItemFn {attrs, vis, mut sig, block} = function;
 
+	let body = instrument_body (&sig . ident, format_ident! ("Service"), &block);
+
 	sig . output = map_return_type
 	(
 		sig . output,
@@ -38,17 +52,24 @@ fn gen_cancellable_service (function: ItemFn) -> proc_macro2::TokenStream
 		{
 			compute_graph::service_handle::CancellableServiceHandle::new
 			(
-				tokio::task::spawn (async move #block)
+				tokio::task::spawn (#body)
 			)
 		}
 	}
 }
 
-fn gen_signallable_service (shutdown_object: Ident, function: ItemFn)
+fn gen_signallable_service
+(
+	shutdown_object: Ident,
+	shutdown_source: ShutdownSource,
+	function: ItemFn
+)
 -> proc_macro2::TokenStream
 {
 	let ItemFn {attrs, vis, mut sig, block} = function;
 
+	let body = instrument_body (&sig . ident, format_ident! ("Service"), &block);
+
 	let shutdown_trigger = format_ident! ("{}_trigger", shutdown_object);
 
 	sig . output = map_return_type
@@ -60,17 +81,32 @@ fn gen_signallable_service (shutdown_object: Ident, function: ItemFn)
 		)
 	);
 
+	let shutdown_channel = match shutdown_source
+	{
+		ShutdownSource::Oneshot => quote! (tokio::sync::oneshot::channel ()),
+		ShutdownSource::Token => quote!
+		({
+			let token = compute_graph::shutdown::ShutdownToken::new ();
+			(token . clone (), token)
+		}),
+		ShutdownSource::Parent (parent) => quote!
+		({
+			let token =
+				compute_graph::shutdown::ShutdownToken::child_token (&#parent);
+			(token . clone (), token)
+		})
+	};
+
 	quote!
 	{
 		#(#attrs)*
 		#vis #sig
 		{
-			let (#shutdown_trigger, mut #shutdown_object) =
-				tokio::sync::oneshot::channel ();
+			let (#shutdown_trigger, mut #shutdown_object) = #shutdown_channel;
 
 			compute_graph::service_handle::SignallableServiceHandle::new
 			(
-				tokio::task::spawn (async move #block),
+				tokio::task::spawn (#body),
 				#shutdown_trigger
 			)
 		}
@@ -98,11 +134,31 @@ fn service_inner (service_input: ServiceInput, mut function: ItemFn)
 			. push (parse_quote! (#arg_type: std::marker::Send + 'static));
 	}
 
-	match service_input . shutdown
+	let ServiceInput {shutdown, token, parent} = service_input;
+
+	let shutdown_source = match (token . is_present (), parent)
+	{
+		(_, Some (parent)) => ShutdownSource::Parent (parent),
+		(true, None) => ShutdownSource::Token,
+		(false, None) => ShutdownSource::Oneshot
+	};
+
+	match (shutdown, shutdown_source)
 	{
-		None => Ok (gen_cancellable_service (function)),
-		Some (shutdown_object) =>
-			Ok (gen_signallable_service (shutdown_object, function))
+		(None, ShutdownSource::Oneshot) =>
+			Ok (gen_cancellable_service (function)),
+		(None, _) => Err
+		(
+			syn::Error::new
+			(
+				function . sig . ident . span (),
+				"shutdown tokens require a shutdown object"
+			)
+		),
+		(Some (shutdown_object), shutdown_source) => Ok
+		(
+			gen_signallable_service (shutdown_object, shutdown_source, function)
+		)
 	}
 }
 
@@ -4,7 +4,7 @@ use darling::util::Flag;
 use syn::{Ident, FnArg, ItemFn, parse, parse_quote};
 use quote::{format_ident, quote};
 
-use crate::util::map_return_type;
+use crate::util::{instrument_body, map_return_type};
 
 #[derive (FromMeta)]
 struct TaskInput
@@ -18,6 +18,8 @@ fn gen_cancellable_task (function: ItemFn, forking: bool)
 {
 	let ItemFn {attrs, vis, mut sig, block} = function;
 
+	let body = instrument_body (&sig . ident, format_ident! ("Task"), &block);
+
 	let (task_handle_type, new_return_type) = match forking
 	{
 		false =>
@@ -65,7 +67,7 @@ fn gen_cancellable_task (function: ItemFn, forking: bool)
 		#(#attrs)*
 		#vis #sig
 		{
-			#task_handle_type::new (async move #block)
+			#task_handle_type::new (#body)
 		}
 	}
 }
@@ -80,6 +82,8 @@ fn gen_signallable_task
 {
 	let ItemFn {attrs, vis, mut sig, block} = function;
 
+	let body = instrument_body (&sig . ident, format_ident! ("Task"), &block);
+
 	let shutdown_trigger = format_ident! ("{}_trigger", shutdown_object);
 
 	let (task_handle_type, new_return_type) = match forking
@@ -132,7 +136,7 @@ fn gen_signallable_task
 			let (#shutdown_trigger, mut #shutdown_object) =
 				tokio::sync::oneshot::channel ();
 
-			#task_handle_type::new (async move #block, #shutdown_trigger)
+			#task_handle_type::new (#body, #shutdown_trigger)
 		}
 	}
 }
@@ -1,5 +1,16 @@
 use proc_macro2::TokenStream;
-use syn::{Ident, Pat, PatIdent, PatType, ReturnType, Type, Token, parse_quote};
+use quote::quote;
+use syn::{
+	Block,
+	Ident,
+	Pat,
+	PatIdent,
+	PatType,
+	ReturnType,
+	Type,
+	Token,
+	parse_quote
+};
 use syn::parse::Result;
 
 pub fn scan_arg <M, T> (arg: &mut PatType, mut matcher: M)
@@ -53,3 +64,49 @@ where F: FnOnce (Type) -> Type
 		}
 	}
 }
+
+// Wraps a function body in a future that records itself in the introspection
+// registry, if it's enabled.
+pub fn instrument_body (name: &Ident, kind: Ident, block: &Block)
+-> TokenStream
+{
+	let name = name . to_string ();
+
+	quote!
+	({
+		let introspection_node = compute_graph::introspection::register
+		(
+			#name,
+			compute_graph::introspection::NodeKind::#kind
+		);
+
+		compute_graph::introspection::scope
+		(
+			compute_graph::introspection::node_id (&introspection_node),
+			async move
+			{
+				let output = async move #block . await;
+
+				{
+					#[allow (unused_imports)]
+					use compute_graph::introspection::{
+						ReportExitStatus,
+						ReportNoExitStatus
+					};
+
+					let exit_status =
+						(&compute_graph::introspection::ExitReporter (&output))
+							. reported_exit_status ();
+
+					compute_graph::introspection::finish
+					(
+						introspection_node,
+						exit_status
+					);
+				}
+
+				output
+			}
+		)
+	})
+}
@@ -0,0 +1,148 @@
+use rand::{Rng, rng};
+use tokio::time::Duration;
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Jitter
+{
+	// Always wait exactly the exponential delay.
+	None,
+	// Wait anywhere between zero and the exponential delay.
+	Full,
+	// Wait at least half of the exponential delay.
+	Equal,
+	// Wait between the initial delay and three times the previous delay.
+	Decorrelated
+}
+
+#[derive (Copy, Clone, Debug)]
+pub struct BackoffPolicy
+{
+	pub initial_delay: Duration,
+	pub max_delay: Duration,
+	pub multiplier: f64,
+	pub jitter: Jitter
+}
+
+impl BackoffPolicy
+{
+	pub fn new (initial_delay: Duration, max_delay: Duration) -> Self
+	{
+		Self
+		{
+			initial_delay,
+			max_delay,
+			multiplier: 2.0,
+			jitter: Jitter::Full
+		}
+	}
+
+	// Multipliers below 1, including NaN, are treated as 1.
+	pub fn with_multiplier (mut self, multiplier: f64) -> Self
+	{
+		self . multiplier = multiplier;
+		self
+	}
+
+	pub fn with_jitter (mut self, jitter: Jitter) -> Self
+	{
+		self . jitter = jitter;
+		self
+	}
+}
+
+#[derive (Clone, Debug)]
+pub struct Backoff
+{
+	policy: BackoffPolicy,
+	attempts: u32,
+	previous_delay: Duration
+}
+
+impl Backoff
+{
+	pub fn new (policy: BackoffPolicy) -> Self
+	{
+		Self
+		{
+			policy,
+			attempts: 0,
+			previous_delay: policy . initial_delay
+		}
+	}
+
+	pub fn policy (&self) -> &BackoffPolicy
+	{
+		&self . policy
+	}
+
+	pub fn attempts (&self) -> u32
+	{
+		self . attempts
+	}
+
+	pub fn reset (&mut self)
+	{
+		self . attempts = 0;
+		self . previous_delay = self . policy . initial_delay;
+	}
+
+	fn exponential_delay (&self) -> Duration
+	{
+		let exponent = self . attempts . min (i32::MAX as u32) as i32;
+
+		// A multiplier below 1 would shrink the delay, and max also turns NaN
+		// into 1.
+		let multiplier = self . policy . multiplier . max (1.0);
+
+		let delay_secs = self . policy . initial_delay . as_secs_f64 ()
+			* multiplier . powi (exponent);
+
+		// Written so that NaN and infinity also end up at the maximum, before
+		// from_secs_f64 can panic on them.
+		if delay_secs < self . policy . max_delay . as_secs_f64 ()
+		{
+			Duration::from_secs_f64 (delay_secs)
+		}
+		else
+		{
+			self . policy . max_delay
+		}
+	}
+
+	pub fn next_delay (&mut self) -> Duration
+	{
+		let base_delay = self . exponential_delay ();
+
+		let delay = match self . policy . jitter
+		{
+			Jitter::None => base_delay,
+			Jitter::Full => random_between (Duration::ZERO, base_delay),
+			Jitter::Equal => base_delay / 2
+				+ random_between (Duration::ZERO, base_delay / 2),
+			Jitter::Decorrelated => random_between
+			(
+				self . policy . initial_delay,
+				self . previous_delay . saturating_mul (3)
+			)
+				. min (self . policy . max_delay)
+		};
+
+		self . attempts = self . attempts . saturating_add (1);
+		self . previous_delay = delay;
+
+		delay
+	}
+}
+
+fn random_between (low: Duration, high: Duration) -> Duration
+{
+	if high <= low
+	{
+		return low;
+	}
+
+	let low_nanos = low . as_nanos () . min (u64::MAX as u128) as u64;
+	let high_nanos = high . as_nanos () . min (u64::MAX as u128) as u64;
+
+	Duration::from_nanos (rng () . random_range (low_nanos..=high_nanos))
+}
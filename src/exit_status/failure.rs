@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FailureKind
+{
+	// A sink rejected an item.
+	Sink,
+	// An input stream ended when the service required it to keep going.
+	StreamEnded,
+	// The underlying connection encountered an error.
+	Connection,
+	// A hostname could not be resolved.
+	Resolution,
+	// The peer closed the connection before we shut down.
+	Closed,
+	// The peer stopped responding in time.
+	Timeout,
+	// The peer violated the protocol.
+	Protocol,
+	// The peer answered a request with an error.
+	Remote,
+	// An item could not be encoded or decoded.
+	Encode,
+	Decode,
+	// A service constructor gave up.
+	Constructor,
+	// A supervisor restarted its services too often.
+	RestartBudget,
+	Other
+}
+
+impl Display for FailureKind
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		let description = match self
+		{
+			Self::Sink => "sink rejected item",
+			Self::StreamEnded => "stream ended",
+			Self::Connection => "connection error",
+			Self::Resolution => "failed to resolve host",
+			Self::Closed => "connection closed",
+			Self::Timeout => "timed out",
+			Self::Protocol => "protocol violation",
+			Self::Remote => "peer returned an error",
+			Self::Encode => "failed to encode item",
+			Self::Decode => "failed to decode item",
+			Self::Constructor => "service constructor gave up",
+			Self::RestartBudget => "restart budget exhausted",
+			Self::Other => "failure"
+		};
+
+		f . write_str (description)
+	}
+}
+
+// Why a service exited spuriously.  Failures compare equal if their kinds and
+// messages match, regardless of their sources.
+#[derive (Clone)]
+pub struct Failure
+{
+	kind: FailureKind,
+	message: String,
+	source: Option <Arc <dyn Error + Send + Sync>>
+}
+
+impl Failure
+{
+	pub fn new (kind: FailureKind, message: impl Into <String>) -> Self
+	{
+		Self {kind, message: message . into (), source: None}
+	}
+
+	pub fn from_error <E> (kind: FailureKind, error: E) -> Self
+	where E: Error + Send + Sync + 'static
+	{
+		Self
+		{
+			kind,
+			message: error . to_string (),
+			source: Some (Arc::new (error))
+		}
+	}
+
+	pub fn with_source <E> (mut self, error: E) -> Self
+	where E: Error + Send + Sync + 'static
+	{
+		self . source = Some (Arc::new (error));
+		self
+	}
+
+	pub fn kind (&self) -> FailureKind
+	{
+		self . kind
+	}
+
+	pub fn message (&self) -> &str
+	{
+		&self . message
+	}
+
+	pub fn error (&self) -> Option <&(dyn Error + Send + Sync + 'static)>
+	{
+		self . source . as_deref ()
+	}
+}
+
+impl Debug for Failure
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		f . debug_struct ("Failure")
+			. field ("kind", &self . kind)
+			. field ("message", &self . message)
+			. field ("source", &self . source . as_ref () . map (|e| e . to_string ()))
+			. finish ()
+	}
+}
+
+impl Display for Failure
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		if self . message . is_empty ()
+		{
+			write! (f, "{}", self . kind)
+		}
+		else
+		{
+			write! (f, "{}: {}", self . kind, self . message)
+		}
+	}
+}
+
+impl Error for Failure
+{
+	fn source (&self) -> Option <&(dyn Error + 'static)>
+	{
+		self . source . as_deref () . map (|e| e as &(dyn Error + 'static))
+	}
+}
+
+impl PartialEq for Failure
+{
+	fn eq (&self, other: &Self) -> bool
+	{
+		self . kind == other . kind && self . message == other . message
+	}
+}
+
+impl Eq for Failure {}
+
+impl Hash for Failure
+{
+	fn hash <H> (&self, state: &mut H)
+	where H: Hasher
+	{
+		self . kind . hash (state);
+		self . message . hash (state);
+	}
+}
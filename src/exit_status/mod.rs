@@ -4,31 +4,60 @@ pub use always_clean::AlwaysClean;
 mod with_status;
 pub use with_status::WithStatus;
 
+mod failure;
+pub use failure::{Failure, FailureKind};
+
 // Normally, we'd use the vocabulary 'clean' and 'dirty'.  Should I be doing
 // that?
-#[derive (Copy, Clone, Hash, PartialEq, Eq)]
+#[derive (Clone, Default, Hash, PartialEq, Eq, Debug)]
 pub enum ExitStatus
 {
+	#[default]
 	Clean,
-	Spurious
+	Spurious (Failure)
 }
 
-impl Default for ExitStatus
+impl From <Failure> for ExitStatus
 {
-	fn default () -> Self
+	fn from (failure: Failure) -> Self
 	{
-		Self::Clean
+		Self::Spurious (failure)
 	}
 }
 
 impl ExitStatus
 {
-	pub fn into_result (self) -> Result <(), ()>
+	pub fn spurious (kind: FailureKind, message: impl Into <String>) -> Self
+	{
+		Self::Spurious (Failure::new (kind, message))
+	}
+
+	pub fn into_result (self) -> Result <(), Failure>
 	{
 		match self
 		{
 			ExitStatus::Clean => Ok (()),
-			ExitStatus::Spurious => Err (())
+			ExitStatus::Spurious (failure) => Err (failure)
+		}
+	}
+
+	pub fn failure (&self) -> Option <&Failure>
+	{
+		match self
+		{
+			ExitStatus::Clean => None,
+			ExitStatus::Spurious (failure) => Some (failure)
+		}
+	}
+
+	// Combines the exit statuses of several cooperating services, keeping the
+	// first failure.
+	pub fn and (self, other: ExitStatus) -> ExitStatus
+	{
+		match self
+		{
+			ExitStatus::Clean => other,
+			ExitStatus::Spurious (failure) => ExitStatus::Spurious (failure)
 		}
 	}
 
@@ -37,7 +66,7 @@ impl ExitStatus
 		match self
 		{
 			ExitStatus::Clean => true,
-			ExitStatus::Spurious => false
+			ExitStatus::Spurious (_) => false
 		}
 	}
 
@@ -46,7 +75,7 @@ impl ExitStatus
 		match self
 		{
 			ExitStatus::Clean => false,
-			ExitStatus::Spurious => true
+			ExitStatus::Spurious (_) => true
 		}
 	}
 }
@@ -83,7 +112,7 @@ impl ServiceExitStatus for ExitStatus
 {
 	type Value = ();
 
-	fn exit_status (&self) -> ExitStatus { *self }
+	fn exit_status (&self) -> ExitStatus { self . clone () }
 
 	fn status_clean (&self) -> bool { self . is_clean () }
 
@@ -34,6 +34,6 @@ impl <T> ServiceExitStatus for WithStatus <T>
 
 	fn exit_status (&self) -> ExitStatus
 	{
-		self . status
+		self . status . clone ()
 	}
 }
@@ -0,0 +1,440 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter, Write};
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use tokio::task::futures::TaskLocalFuture;
+use tokio::time::Instant;
+
+use crate::exit_status::{ExitStatus, ServiceExitStatus};
+
+// Records every service, task and join spawned through the macros while it is
+// enabled, along with which service spawned it.  The registry is disabled by
+// default, in which case the macros skip it entirely.
+
+#[derive (Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct NodeId (u64);
+
+impl NodeId
+{
+	pub fn into_inner (self) -> u64
+	{
+		self . 0
+	}
+}
+
+impl Display for NodeId
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		write! (f, "#{}", self . 0)
+	}
+}
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum NodeKind
+{
+	Service,
+	Task,
+	Join
+}
+
+#[derive (Clone, PartialEq, Eq, Hash, Debug)]
+pub enum NodeState
+{
+	Running,
+	// Only services that return an exit status report one.
+	Exited (Option <ExitStatus>),
+	// The node was dropped or aborted before it finished.
+	Cancelled
+}
+
+impl Display for NodeState
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		match self
+		{
+			Self::Running => write! (f, "running"),
+			Self::Exited (None) => write! (f, "exited"),
+			Self::Exited (Some (ExitStatus::Clean)) =>
+				write! (f, "exited clean"),
+			Self::Exited (Some (ExitStatus::Spurious (failure))) =>
+				write! (f, "exited spurious ({failure})"),
+			Self::Cancelled => write! (f, "cancelled")
+		}
+	}
+}
+
+#[derive (Clone, Debug)]
+pub struct NodeInfo
+{
+	pub id: NodeId,
+	pub name: &'static str,
+	pub kind: NodeKind,
+	pub parent: Option <NodeId>,
+	pub state: NodeState,
+	pub started_at: Instant,
+	pub exited_at: Option <Instant>
+}
+
+struct Registry
+{
+	nodes: BTreeMap <NodeId, NodeInfo>,
+	// Finished nodes, oldest first.
+	finished: VecDeque <NodeId>,
+	retained_finished: usize
+}
+
+static ENABLED: AtomicBool = AtomicBool::new (false);
+static NEXT_ID: AtomicU64 = AtomicU64::new (0);
+static REGISTRY: Mutex <Option <Registry>> = Mutex::new (None);
+
+tokio::task_local!
+{
+	static CURRENT: Cell <Option <NodeId>>;
+}
+
+// Code that isn't running under any node, like a join in main, keeps track of
+// its current node per thread instead.
+thread_local!
+{
+	static UNSCOPED_CURRENT: Cell <Option <NodeId>> =
+		const { Cell::new (None) };
+}
+
+fn lock_registry () -> MutexGuard <'static, Option <Registry>>
+{
+	// The registry is only ever updated in one step, so it can't be left in an
+	// inconsistent state by a panic.
+	REGISTRY . lock () . unwrap_or_else (PoisonError::into_inner)
+}
+
+fn with_registry <F, T> (f: F) -> Option <T>
+where F: FnOnce (&mut Registry) -> T
+{
+	lock_registry () . as_mut () . map (f)
+}
+
+pub fn enable ()
+{
+	enable_with_history (256);
+}
+
+// Keeps up to retained_finished nodes around after they finish, so their exit
+// statuses can be inspected.
+pub fn enable_with_history (retained_finished: usize)
+{
+	*lock_registry () = Some
+	(
+		Registry
+		{
+			nodes: BTreeMap::new (),
+			finished: VecDeque::new (),
+			retained_finished
+		}
+	);
+
+	ENABLED . store (true, Ordering::Release);
+}
+
+// Forgets every recorded node.
+pub fn disable ()
+{
+	ENABLED . store (false, Ordering::Release);
+	*lock_registry () = None;
+}
+
+pub fn is_enabled () -> bool
+{
+	ENABLED . load (Ordering::Acquire)
+}
+
+// The node whose body is currently running, if any.
+pub fn current () -> Option <NodeId>
+{
+	CURRENT
+		. try_with (Cell::get)
+		. unwrap_or_else (|_| UNSCOPED_CURRENT . with (Cell::get))
+}
+
+fn replace_current (node_id: Option <NodeId>) -> Option <NodeId>
+{
+	CURRENT
+		. try_with (|current| current . replace (node_id))
+		. unwrap_or_else
+		(
+			|_| UNSCOPED_CURRENT . with (|current| current . replace (node_id))
+		)
+}
+
+pub fn snapshot () -> Vec <NodeInfo>
+{
+	with_registry
+	(
+		|registry| registry . nodes . values () . cloned () . collect ()
+	)
+		. unwrap_or_default ()
+}
+
+// Nodes whose parents have already been forgotten are listed as roots.
+pub fn dump_tree () -> String
+{
+	let nodes = snapshot ();
+	let mut tree = String::new ();
+
+	for node in nodes . iter () . filter (|node| is_root (node, &nodes))
+	{
+		write_subtree (&mut tree, node, &nodes, 0);
+	}
+
+	tree
+}
+
+pub fn dump_dot () -> String
+{
+	let nodes = snapshot ();
+	let mut dot = String::from ("digraph services {\n");
+
+	for node in &nodes
+	{
+		let shape = match node . kind
+		{
+			NodeKind::Service => "box",
+			NodeKind::Task => "ellipse",
+			NodeKind::Join => "diamond"
+		};
+
+		let color = match &node . state
+		{
+			NodeState::Running => "black",
+			NodeState::Exited (Some (ExitStatus::Spurious (_))) => "red",
+			NodeState::Exited (_) | NodeState::Cancelled => "gray"
+		};
+
+		let label = format! ("{} {}\n{}", node . name, node . id, node . state);
+
+		let _ = writeln!
+		(
+			dot,
+			"\tn{} [label={:?}, shape={}, color={}];",
+			node . id . 0,
+			label,
+			shape,
+			color
+		);
+	}
+
+	for node in &nodes
+	{
+		if let (Some (parent), false) = (node . parent, is_root (node, &nodes))
+		{
+			let _ = writeln! (dot, "\tn{} -> n{};", parent . 0, node . id . 0);
+		}
+	}
+
+	dot . push_str ("}\n");
+	dot
+}
+
+fn is_root (node: &NodeInfo, nodes: &[NodeInfo]) -> bool
+{
+	match node . parent
+	{
+		None => true,
+		Some (parent) => nodes
+			. binary_search_by_key (&parent, |node| node . id)
+			. is_err ()
+	}
+}
+
+fn write_subtree
+(
+	tree: &mut String,
+	node: &NodeInfo,
+	nodes: &[NodeInfo],
+	depth: usize
+)
+{
+	let uptime = node . exited_at . unwrap_or_else (Instant::now)
+		- node . started_at;
+
+	let _ = writeln!
+	(
+		tree,
+		"{:indent$}{} {} [{}] {:?}",
+		"",
+		node . name,
+		node . id,
+		node . state,
+		uptime,
+		indent = depth * 2
+	);
+
+	let children = nodes
+		. iter ()
+		. filter (|child| child . parent == Some (node . id));
+
+	for child in children
+	{
+		write_subtree (tree, child, nodes, depth + 1);
+	}
+}
+
+// Marks its node as cancelled if it is dropped before the node finishes.
+#[doc (hidden)]
+pub struct NodeGuard
+{
+	id: NodeId,
+	finished: bool
+}
+
+impl NodeGuard
+{
+	fn finish (&mut self, state: NodeState)
+	{
+		self . finished = true;
+
+		with_registry
+		(
+			|registry|
+			{
+				if let Some (node) = registry . nodes . get_mut (&self . id)
+				{
+					node . state = state;
+					node . exited_at = Some (Instant::now ());
+				}
+
+				registry . finished . push_back (self . id);
+
+				let retained_finished = registry . retained_finished;
+
+				while registry . finished . len () > retained_finished
+				{
+					if let Some (forgotten) = registry . finished . pop_front ()
+					{
+						registry . nodes . remove (&forgotten);
+					}
+				}
+			}
+		);
+	}
+}
+
+impl Drop for NodeGuard
+{
+	fn drop (&mut self)
+	{
+		if ! self . finished
+		{
+			self . finish (NodeState::Cancelled);
+		}
+	}
+}
+
+#[doc (hidden)]
+pub fn register (name: &'static str, kind: NodeKind) -> Option <NodeGuard>
+{
+	if ! is_enabled ()
+	{
+		return None;
+	}
+
+	let id = NodeId (NEXT_ID . fetch_add (1, Ordering::Relaxed));
+
+	let node = NodeInfo
+	{
+		id,
+		name,
+		kind,
+		parent: current (),
+		state: NodeState::Running,
+		started_at: Instant::now (),
+		exited_at: None
+	};
+
+	with_registry (|registry| registry . nodes . insert (id, node))?;
+
+	Some (NodeGuard {id, finished: false})
+}
+
+#[doc (hidden)]
+pub fn node_id (guard: &Option <NodeGuard>) -> Option <NodeId>
+{
+	guard . as_ref () . map (|guard| guard . id)
+}
+
+#[doc (hidden)]
+pub fn scope <F> (node_id: Option <NodeId>, future: F)
+-> TaskLocalFuture <Cell <Option <NodeId>>, F>
+where F: Future
+{
+	CURRENT . scope (Cell::new (node_id), future)
+}
+
+// Makes node_id the current node until it is dropped, without needing a
+// closure or a future around the code in between.
+#[doc (hidden)]
+pub struct ScopeGuard
+{
+	previous: Option <NodeId>
+}
+
+impl Drop for ScopeGuard
+{
+	fn drop (&mut self)
+	{
+		replace_current (self . previous);
+	}
+}
+
+#[doc (hidden)]
+pub fn enter (node_id: Option <NodeId>) -> ScopeGuard
+{
+	ScopeGuard {previous: replace_current (node_id)}
+}
+
+#[doc (hidden)]
+pub fn finish (guard: Option <NodeGuard>, exit_status: Option <ExitStatus>)
+{
+	if let Some (mut guard) = guard
+	{
+		guard . finish (NodeState::Exited (exit_status));
+	}
+}
+
+// Picks up the exit status of outputs that have one, using autoref
+// specialization so that the macros don't need to know the output type.
+#[doc (hidden)]
+pub struct ExitReporter <'a, T> (pub &'a T);
+
+#[doc (hidden)]
+pub trait ReportExitStatus
+{
+	fn reported_exit_status (&self) -> Option <ExitStatus>;
+}
+
+impl <T> ReportExitStatus for ExitReporter <'_, T>
+where T: ServiceExitStatus
+{
+	fn reported_exit_status (&self) -> Option <ExitStatus>
+	{
+		Some (self . 0 . exit_status ())
+	}
+}
+
+#[doc (hidden)]
+pub trait ReportNoExitStatus
+{
+	fn reported_exit_status (&self) -> Option <ExitStatus>;
+}
+
+impl <T> ReportNoExitStatus for &ExitReporter <'_, T>
+{
+	fn reported_exit_status (&self) -> Option <ExitStatus>
+	{
+		None
+	}
+}
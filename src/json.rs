@@ -6,8 +6,21 @@ use serde::{Serialize, Deserialize};
 use tracing::{Level, event};
 use tungstenite::{Message, Utf8Bytes};
 
-use crate::{service, expand_streams, event_loop, check_break, feed};
-use crate::websocket::io_format::{InputFormat, OutputFormat};
+use crate::{
+	service,
+	expand_streams,
+	event_loop,
+	event_loop_fallible,
+	check_break,
+	feed
+};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::websocket::io_format::{
+	Decoder,
+	InputFormat,
+	OutputFormat,
+	binary_in_text_protocol
+};
 
 use crate as compute_graph;
 
@@ -35,26 +48,36 @@ pub async fn json_serialize <IS, OS>
 	}
 }
 
+// Strings that fail to decode are handled as output_format says, like
+// messages coming off a websocket.
 #[expand_streams]
 #[service]
-pub async fn json_deserialize <IS, OS, OI>
+pub async fn json_deserialize <F, IS, OS>
 (
+	output_format: F,
 	string_stream: input! (IS -> impl AsRef <str>),
-	item_sink: output! (OS <- OI: for <'de> Deserialize <'de>)
+	item_sink: output! (OS <- F::External)
 )
+-> ExitStatus
+where F: OutputFormat
 {
-	event_loop!
+	let mut decoder = Decoder::new (output_format);
+
+	event_loop_fallible!
 	{
-		string_stream -> string => match serde_json::from_str (string . as_ref ())
+		string_stream -> string =>
 		{
-			Ok (item) => check_break! (feed! (item_sink, item)),
-			Err (serde_error) => event!
-			(
-				Level::ERROR,
-				item_string = ?string . as_ref (),
-				error = %serde_error,
-				"failed to deserialize item"
-			)
+			let message = Message::text (string . as_ref ());
+
+			if let Some (item) = decoder . decode (message)
+			{
+				check_break!
+				(
+					feed! (item_sink, item) . map_break (|_| ExitStatus::Clean)
+				);
+			}
+
+			check_break! (decoder . check_failures ());
 		}
 	}
 }
@@ -111,37 +134,16 @@ where T: for <'de> Deserialize <'de>
 {
 	type External = T;
 
-	fn convert_text (utf8_bytes: Utf8Bytes) -> Option <Self::External>
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
 	{
-		let item_string: &str = utf8_bytes . as_ref ();
-
-		match serde_json::from_str (item_string)
-		{
-			Ok (item) => Some (item),
-			Err (serde_error) =>
-			{
-				event!
-				(
-					Level::ERROR,
-					item_string = ?item_string,
-					error = %serde_error,
-					"failed to deserialize item"
-				);
-
-				None
-			}
-		}
+		serde_json::from_str (utf8_bytes . as_str ()) . map_err
+		(
+			|serde_error| Failure::from_error (FailureKind::Decode, serde_error)
+		)
 	}
 
-	fn convert_binary (bytes: Bytes) -> Option <Self::External>
+	fn convert_binary (_bytes: Bytes) -> Result <Self::External, Failure>
 	{
-		event!
-		(
-			Level::ERROR,
-			message_bytes = ?bytes,
-			"received binary message in text-only protocol"
-		);
-
-		None
+		Err (binary_in_text_protocol ())
 	}
 }
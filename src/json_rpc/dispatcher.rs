@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use tracing::{Level, event};
+
+use super::{ErrorObject, Request, Response};
+
+type HandlerFuture = BoxFuture <'static, Result <Value, ErrorObject>>;
+
+type Handler = dyn Fn (Option <Value>) -> HandlerFuture + Send + Sync;
+
+// Routes requests to async handlers by method name.
+#[derive (Clone, Default)]
+pub struct Dispatcher
+{
+	methods: HashMap <String, Arc <Handler>>
+}
+
+impl Dispatcher
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	// Handlers get the request's params, which are None if it had none.
+	pub fn with_method <F, R>
+	(
+		mut self,
+		method: impl Into <String>,
+		handler: F
+	)
+	-> Self
+	where
+		F: Fn (Option <Value>) -> R + Send + Sync + 'static,
+		R: Future <Output = Result <Value, ErrorObject>> + Send + 'static
+	{
+		let handler = move |params| handler (params) . boxed ();
+		self . methods . insert (method . into (), Arc::new (handler));
+		self
+	}
+
+	pub fn handles (&self, method: &str) -> bool
+	{
+		self . methods . contains_key (method)
+	}
+
+	// Notifications get no response, even if their handler fails.
+	pub fn handle (&self, request: Request)
+	-> BoxFuture <'static, Option <Response>>
+	{
+		let handler = self . methods . get (&request . method) . cloned ();
+
+		async move
+		{
+			let result = match handler
+			{
+				Some (handler) => handler (request . params) . await,
+				None => Err (ErrorObject::method_not_found (&request . method))
+			};
+
+			match (request . id, result)
+			{
+				(Some (id), result) => Some (Response::new (id, result)),
+				(None, Ok (_)) => None,
+				(None, Err (error)) =>
+				{
+					event!
+					(
+						Level::WARN,
+						method = request . method,
+						error = %error,
+						"notification handler failed"
+					);
+
+					None
+				}
+			}
+		}
+			. boxed ()
+	}
+}
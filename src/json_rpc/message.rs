@@ -0,0 +1,363 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeMap;
+use serde_json::Value;
+
+use crate::exit_status::{ExitStatus, Failure};
+
+// The error member of a response.  A call answered with one fails as Remote,
+// carrying it as the source.
+#[derive (Clone, PartialEq, Debug)]
+pub struct ErrorObject
+{
+	pub code: i64,
+	pub message: String,
+	pub data: Option <Value>
+}
+
+impl ErrorObject
+{
+	pub const PARSE_ERROR: i64 = -32700;
+	pub const INVALID_REQUEST: i64 = -32600;
+	pub const METHOD_NOT_FOUND: i64 = -32601;
+	pub const INVALID_PARAMS: i64 = -32602;
+	pub const INTERNAL_ERROR: i64 = -32603;
+
+	pub fn new (code: i64, message: impl Into <String>) -> Self
+	{
+		Self {code, message: message . into (), data: None}
+	}
+
+	pub fn with_data (mut self, data: Value) -> Self
+	{
+		self . data = Some (data);
+		self
+	}
+
+	pub fn parse_error (message: impl Into <String>) -> Self
+	{
+		Self::new (Self::PARSE_ERROR, message)
+	}
+
+	pub fn invalid_request (message: impl Into <String>) -> Self
+	{
+		Self::new (Self::INVALID_REQUEST, message)
+	}
+
+	pub fn method_not_found (method: &str) -> Self
+	{
+		Self::new
+		(
+			Self::METHOD_NOT_FOUND,
+			format! ("method not found: {}", method)
+		)
+	}
+
+	pub fn invalid_params (message: impl Into <String>) -> Self
+	{
+		Self::new (Self::INVALID_PARAMS, message)
+	}
+
+	pub fn internal_error (message: impl Into <String>) -> Self
+	{
+		Self::new (Self::INTERNAL_ERROR, message)
+	}
+
+	pub fn from_failure (failure: &Failure) -> Option <&Self>
+	{
+		failure . error () ?. downcast_ref ()
+	}
+
+	pub fn from_exit_status (exit_status: &ExitStatus) -> Option <&Self>
+	{
+		Self::from_failure (exit_status . failure ()?)
+	}
+}
+
+impl TryFrom <Value> for ErrorObject
+{
+	type Error = ();
+
+	fn try_from (value: Value) -> Result <Self, Self::Error>
+	{
+		let Value::Object (mut fields) = value
+		else
+		{
+			return Err (());
+		};
+
+		let code = fields . get ("code") . and_then (Value::as_i64);
+		let code = code . ok_or (())?;
+
+		let Some (Value::String (message)) = fields . remove ("message")
+		else
+		{
+			return Err (());
+		};
+
+		Ok (Self {code, message, data: fields . remove ("data")})
+	}
+}
+
+impl Serialize for ErrorObject
+{
+	fn serialize <S> (&self, serializer: S) -> Result <S::Ok, S::Error>
+	where S: Serializer
+	{
+		let mut map = serializer . serialize_map (None)?;
+		map . serialize_entry ("code", &self . code)?;
+		map . serialize_entry ("message", &self . message)?;
+
+		if let Some (data) = &self . data
+		{
+			map . serialize_entry ("data", data)?;
+		}
+
+		map . end ()
+	}
+}
+
+impl Display for ErrorObject
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		write! (f, "{} (code {})", self . message, self . code)
+	}
+}
+
+impl Error for ErrorObject {}
+
+// A call, or a notification if it has no id.
+#[derive (Clone, PartialEq, Debug)]
+pub struct Request
+{
+	pub id: Option <Value>,
+	pub method: String,
+	pub params: Option <Value>
+}
+
+impl Request
+{
+	pub fn call
+	(
+		id: impl Into <Value>,
+		method: impl Into <String>,
+		params: Option <Value>
+	)
+	-> Self
+	{
+		Self {id: Some (id . into ()), method: method . into (), params}
+	}
+
+	pub fn notification (method: impl Into <String>, params: Option <Value>)
+	-> Self
+	{
+		Self {id: None, method: method . into (), params}
+	}
+
+	pub fn is_notification (&self) -> bool
+	{
+		self . id . is_none ()
+	}
+}
+
+impl Serialize for Request
+{
+	fn serialize <S> (&self, serializer: S) -> Result <S::Ok, S::Error>
+	where S: Serializer
+	{
+		let mut map = serializer . serialize_map (None)?;
+		map . serialize_entry ("jsonrpc", "2.0")?;
+		map . serialize_entry ("method", &self . method)?;
+
+		if let Some (params) = &self . params
+		{
+			map . serialize_entry ("params", params)?;
+		}
+
+		if let Some (id) = &self . id
+		{
+			map . serialize_entry ("id", id)?;
+		}
+
+		map . end ()
+	}
+}
+
+#[derive (Clone, PartialEq, Debug)]
+pub struct Response
+{
+	pub id: Value,
+	pub result: Result <Value, ErrorObject>
+}
+
+impl Response
+{
+	pub fn new (id: Value, result: Result <Value, ErrorObject>) -> Self
+	{
+		Self {id, result}
+	}
+}
+
+impl Serialize for Response
+{
+	fn serialize <S> (&self, serializer: S) -> Result <S::Ok, S::Error>
+	where S: Serializer
+	{
+		let mut map = serializer . serialize_map (None)?;
+		map . serialize_entry ("jsonrpc", "2.0")?;
+
+		match &self . result
+		{
+			Ok (result) => map . serialize_entry ("result", result)?,
+			Err (error) => map . serialize_entry ("error", error)?
+		}
+
+		map . serialize_entry ("id", &self . id)?;
+		map . end ()
+	}
+}
+
+#[derive (Clone, PartialEq, Debug)]
+pub enum Message
+{
+	Request (Request),
+	Response (Response)
+}
+
+fn is_valid_id (id: &Value) -> bool
+{
+	matches! (id, Value::Null | Value::Number (_) | Value::String (_))
+}
+
+fn invalid_request (id: Value, message: &str) -> Response
+{
+	Response::new (id, Err (ErrorObject::invalid_request (message)))
+}
+
+// An invalid message is answered with the error response it converts into.
+impl TryFrom <Value> for Message
+{
+	type Error = Response;
+
+	fn try_from (value: Value) -> Result <Self, Self::Error>
+	{
+		let Value::Object (mut fields) = value
+		else
+		{
+			return Err (invalid_request (Value::Null, "expected an object"));
+		};
+
+		let id = fields . remove ("id");
+		let reply_id =
+			id . clone () . filter (is_valid_id) . unwrap_or_default ();
+
+		if fields . get ("jsonrpc") . and_then (Value::as_str) != Some ("2.0")
+		{
+			return Err (invalid_request (reply_id, "expected jsonrpc 2.0"));
+		}
+
+		if ! id . as_ref () . is_none_or (is_valid_id)
+		{
+			return Err (invalid_request (reply_id, "invalid id"));
+		}
+
+		if let Some (method) = fields . remove ("method")
+		{
+			let Value::String (method) = method
+			else
+			{
+				return Err
+				(
+					invalid_request (reply_id, "method must be a string")
+				);
+			};
+
+			let params = fields . remove ("params");
+
+			let valid_params = match params
+			{
+				None | Some (Value::Array (_) | Value::Object (_)) => true,
+				Some (_) => false
+			};
+
+			if ! valid_params
+			{
+				return Err
+				(
+					invalid_request
+					(
+						reply_id,
+						"params must be an array or object"
+					)
+				);
+			}
+
+			return Ok (Self::Request (Request {id, method, params}));
+		}
+
+		let Some (id) = id
+		else
+		{
+			return Err (invalid_request (reply_id, "missing id"));
+		};
+
+		let result = fields . remove ("result");
+		let error = fields . remove ("error");
+
+		let result = match (result, error)
+		{
+			(Some (result), None) => Ok (result),
+			(None, Some (error)) => match ErrorObject::try_from (error)
+			{
+				Ok (error) => Err (error),
+				Err (()) => return Err
+				(
+					invalid_request (reply_id, "invalid error object")
+				)
+			},
+			_ => return Err
+			(
+				invalid_request (reply_id, "expected one of result and error")
+			)
+		};
+
+		Ok (Self::Response (Response {id, result}))
+	}
+}
+
+impl Serialize for Message
+{
+	fn serialize <S> (&self, serializer: S) -> Result <S::Ok, S::Error>
+	where S: Serializer
+	{
+		match self
+		{
+			Self::Request (request) => request . serialize (serializer),
+			Self::Response (response) => response . serialize (serializer)
+		}
+	}
+}
+
+// What goes in a single websocket message.
+#[derive (Clone, PartialEq, Debug)]
+pub enum Packet
+{
+	Single (Message),
+	Batch (Vec <Message>)
+}
+
+impl Serialize for Packet
+{
+	fn serialize <S> (&self, serializer: S) -> Result <S::Ok, S::Error>
+	where S: Serializer
+	{
+		match self
+		{
+			Self::Single (message) => message . serialize (serializer),
+			Self::Batch (messages) => serializer . collect_seq (messages)
+		}
+	}
+}
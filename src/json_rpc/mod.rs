@@ -0,0 +1,6 @@
+mod message;
+pub use message::{ErrorObject, Message, Packet, Request, Response};
+mod dispatcher;
+pub use dispatcher::Dispatcher;
+mod peer;
+pub use peer::{JsonRpcClient, JsonRpcPeer};
@@ -0,0 +1,436 @@
+use std::fmt::Display;
+
+use futures::{FutureExt, SinkExt, StreamExt};
+use futures::future::{BoxFuture, join_all, ready};
+use futures::stream::{FuturesUnordered, iter};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Level, event};
+
+use crate::{service, check_break, send_all};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::json::JSON;
+use crate::pending_calls::{PendingCalls, Reply};
+use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
+use crate::websocket::connection::{MessageStream, websocket_node};
+use crate::websocket::io_format::DeadLetters;
+
+use super::{Dispatcher, ErrorObject, Message, Packet, Request, Response};
+
+use crate as compute_graph;
+
+fn encode_params (params: impl Serialize) -> Result <Option <Value>, Failure>
+{
+	let params = serde_json::to_value (params) . map_err
+	(
+		|serde_error| Failure::from_error (FailureKind::Encode, serde_error)
+	)?;
+
+	match params
+	{
+		Value::Null => Ok (None),
+		Value::Array (_) | Value::Object (_) => Ok (Some (params)),
+		_ => Err
+		(
+			Failure::new
+			(
+				FailureKind::Encode,
+				"params must be an array or object"
+			)
+		)
+	}
+}
+
+fn decode_result <R> (reply: Reply) -> Result <R, Failure>
+where R: DeserializeOwned
+{
+	serde_json::from_value (reply?) . map_err
+	(
+		|serde_error| Failure::from_error (FailureKind::Decode, serde_error)
+	)
+}
+
+// Makes calls and sends notifications through a JsonRpcPeer.  Params are
+// omitted if they serialize to null.
+#[derive (Clone)]
+pub struct JsonRpcClient
+{
+	packets: mpsc::Sender <Packet>,
+	pending_calls: PendingCalls,
+	call_timeout: Duration
+}
+
+impl JsonRpcClient
+{
+	pub async fn call <P, R> (&self, method: &str, params: P)
+	-> Result <R, Failure>
+	where
+		P: Serialize,
+		R: DeserializeOwned
+	{
+		self . call_with_timeout (method, params, self . call_timeout) . await
+	}
+
+	pub async fn call_with_timeout <P, R>
+	(
+		&self,
+		method: &str,
+		params: P,
+		call_timeout: Duration
+	)
+	-> Result <R, Failure>
+	where
+		P: Serialize,
+		R: DeserializeOwned
+	{
+		let params = encode_params (params)?;
+		let (id, reply_receiver) = self . pending_calls . register ();
+		let request = Request::call (id, method, params);
+
+		let mut replies = self . exchange
+		(
+			Packet::Single (Message::Request (request)),
+			vec! [(id, reply_receiver)],
+			call_timeout
+		)
+			. await?;
+
+		decode_result (replies . remove (0))
+	}
+
+	// Results come back in the order of the calls.
+	pub async fn batch <M, P, R>
+	(
+		&self,
+		calls: impl IntoIterator <Item = (M, P)>
+	)
+	-> Result <Vec <Result <R, Failure>>, Failure>
+	where
+		M: Into <String>,
+		P: Serialize,
+		R: DeserializeOwned
+	{
+		// Encoded up front, so a bad call leaves nothing registered.
+		let calls = calls
+			. into_iter ()
+			. map (|(method, params)| Ok ((method, encode_params (params)?)))
+			. collect::<Result <Vec <_>, Failure>> ()?;
+
+		let mut requests = Vec::new ();
+		let mut reply_receivers = Vec::new ();
+
+		for (method, params) in calls
+		{
+			let (id, reply_receiver) = self . pending_calls . register ();
+
+			let request = Request::call (id, method, params);
+
+			requests . push (Message::Request (request));
+			reply_receivers . push ((id, reply_receiver));
+		}
+
+		if requests . is_empty ()
+		{
+			return Ok (Vec::new ());
+		}
+
+		let replies = self . exchange
+		(
+			Packet::Batch (requests),
+			reply_receivers,
+			self . call_timeout
+		)
+			. await?;
+
+		Ok (replies . into_iter () . map (decode_result) . collect ())
+	}
+
+	pub async fn notify <P> (&self, method: &str, params: P)
+	-> Result <(), Failure>
+	where P: Serialize
+	{
+		let request = Request::notification (method, encode_params (params)?);
+
+		self
+			. packets
+			. send (Packet::Single (Message::Request (request)))
+			. await
+			. map_err (|_| Self::stopped ())
+	}
+
+	fn stopped () -> Failure
+	{
+		Failure::new (FailureKind::Connection, "json-rpc peer stopped")
+	}
+
+	async fn exchange
+	(
+		&self,
+		packet: Packet,
+		reply_receivers: Vec <(u64, oneshot::Receiver <Reply>)>,
+		call_timeout: Duration
+	)
+	-> Result <Vec <Reply>, Failure>
+	{
+		let send = async
+		{
+			self
+				. packets
+				. send (packet)
+				. await
+				. map_err (|_| Self::stopped ())
+		};
+
+		self
+			. pending_calls
+			. exchange (send, reply_receivers, call_timeout, Self::stopped)
+			. await
+	}
+}
+
+// Speaks JSON-RPC 2.0 over a single connection, in both directions.  Requests
+// from the other side go to the dispatcher, except notifications it has no
+// handler for, which go to the notifications output instead.
+pub struct JsonRpcPeer
+{
+	dispatcher: Dispatcher,
+	packets: ReceiverStream <Packet>,
+	client: JsonRpcClient
+}
+
+impl JsonRpcPeer
+{
+	pub fn new (dispatcher: Dispatcher) -> Self
+	{
+		let (packet_sender, packet_receiver) = mpsc::channel (64);
+
+		Self
+		{
+			dispatcher,
+			packets: ReceiverStream::new (packet_receiver),
+			client: JsonRpcClient
+			{
+				packets: packet_sender,
+				pending_calls: PendingCalls::new (),
+				call_timeout: Duration::from_secs (30)
+			}
+		}
+	}
+
+	// Only affects clients taken afterwards.
+	pub fn with_call_timeout (mut self, call_timeout: Duration) -> Self
+	{
+		self . client . call_timeout = call_timeout;
+		self
+	}
+
+	pub fn client (&self) -> JsonRpcClient
+	{
+		self . client . clone ()
+	}
+
+	pub fn start <WS, NS> (self, websocket: WS, notifications: NS)
+	-> SignallableServiceHandle <ExitStatus>
+	where
+		WS: MessageStream + Send + 'static,
+		NS: SinkExt <Request> + Unpin + Send + 'static,
+		NS::Error: Display
+	{
+		json_rpc_peer
+		(
+			websocket,
+			self . dispatcher,
+			self . packets,
+			self . client . pending_calls,
+			notifications
+		)
+	}
+}
+
+fn resolve_call (pending_calls: &PendingCalls, response: Response)
+{
+	let reply_sender = response
+		. id
+		. as_u64 ()
+		. and_then (|id| pending_calls . take (id));
+
+	let Some (reply_sender) = reply_sender
+	else
+	{
+		event!
+		(
+			Level::WARN,
+			id = %response . id,
+			"received response to unknown call"
+		);
+
+		return;
+	};
+
+	let reply = response . result . map_err
+	(
+		|error| Failure::from_error (FailureKind::Remote, error)
+	);
+
+	let _ = reply_sender . send (reply);
+}
+
+async fn collect_responses
+(
+	responses: Vec <BoxFuture <'static, Option <Response>>>,
+	is_batch: bool
+)
+-> Option <Packet>
+{
+	let mut messages: Vec <Message> = join_all (responses)
+		. await
+		. into_iter ()
+		. flatten ()
+		. map (Message::Response)
+		. collect ();
+
+	match is_batch
+	{
+		true if messages . is_empty () => None,
+		true => Some (Packet::Batch (messages)),
+		false => messages . pop () . map (Packet::Single)
+	}
+}
+
+#[service (shutdown = shutdown)]
+async fn json_rpc_peer <WS, LS, NS>
+(
+	websocket: WS,
+	dispatcher: Dispatcher,
+	mut outgoing_packets: LS,
+	pending_calls: PendingCalls,
+	mut notifications: NS
+)
+-> ExitStatus
+where
+	WS: MessageStream + Send + 'static,
+	LS: StreamExt <Item = Packet> + Unpin,
+	NS: SinkExt <Request> + Unpin,
+	NS::Error: Display
+{
+	let (mut packet_sink, packet_stream) = crate::stream::mpsc (1);
+	let (value_sink, mut values) = crate::stream::mpsc (1);
+
+	let mut node_handle = websocket_node
+	(
+		JSON::<Packet>::default (),
+		DeadLetters (JSON::<Value>::default ()),
+		packet_stream,
+		value_sink,
+		websocket
+	);
+
+	let mut in_flight = FuturesUnordered::new ();
+
+	let exit_status = loop
+	{
+		let packet = tokio::select!
+		{
+			biased;
+			_ = &mut shutdown => break ExitStatus::Clean,
+			node_exit_status = node_handle . exit_status () =>
+				break node_exit_status . unwrap_or_default (),
+			Some (packet) = in_flight . next () => match packet
+			{
+				Some (packet) => packet,
+				None => continue
+			},
+			Some (packet) = outgoing_packets . next () => packet,
+			Some (value) = values . next () =>
+			{
+				let value = match value
+				{
+					Ok (value) => value,
+					Err (dead_letter) =>
+					{
+						let message = dead_letter . failure . to_string ();
+						let error = ErrorObject::parse_error (message);
+						let response = Response::new (Value::Null, Err (error));
+						let packet =
+							Packet::Single (Message::Response (response));
+
+						in_flight . push (ready (Some (packet)) . boxed ());
+						continue;
+					}
+				};
+
+				let (values, is_batch) = match value
+				{
+					Value::Array (values) => (values, true),
+					value => (vec! [value], false)
+				};
+
+				if values . is_empty ()
+				{
+					let error = ErrorObject::invalid_request ("empty batch");
+					let response = Response::new (Value::Null, Err (error));
+					let packet = Packet::Single (Message::Response (response));
+
+					in_flight . push (ready (Some (packet)) . boxed ());
+				}
+
+				let mut responses = Vec::new ();
+				let mut unhandled = Vec::new ();
+
+				for value in values
+				{
+					match Message::try_from (value)
+					{
+						Ok (Message::Response (response)) =>
+							resolve_call (&pending_calls, response),
+						Ok (Message::Request (request))
+							if request . is_notification ()
+								&& ! dispatcher . handles (&request . method) =>
+							unhandled . push (request),
+						Ok (Message::Request (request)) =>
+							responses . push (dispatcher . handle (request)),
+						Err (response) => responses
+							. push (ready (Some (response)) . boxed ())
+					}
+				}
+
+				if ! responses . is_empty ()
+				{
+					let packet = collect_responses (responses, is_batch);
+
+					in_flight . push (packet . boxed ());
+				}
+
+				let mut unhandled = iter (unhandled) . map (Ok);
+
+				check_break!
+				(
+					send_all! (notifications, &mut unhandled)
+						. map_break (|_| ExitStatus::Clean)
+				);
+
+				continue;
+			}
+		};
+
+		// The node only stops taking packets when it is exiting.
+		if packet_sink . send (packet) . await . is_err ()
+		{
+			break node_handle . exit_status () . await . unwrap_or_default ();
+		}
+	};
+
+	node_handle . shutdown ();
+	node_handle . await;
+
+	pending_calls . fail_all
+	(
+		Failure::new (FailureKind::Connection, "connection dropped")
+	);
+
+	exit_status
+}
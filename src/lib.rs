@@ -4,12 +4,26 @@ pub use macros::*;
 
 pub mod exit_status;
 pub mod service_handle;
+pub mod service_set;
+pub mod shutdown;
 pub mod task_handle;
 pub mod service_state;
+pub mod backoff;
+pub mod introspection;
 
 pub mod robust_service;
 
 pub mod json;
+#[cfg (feature = "msgpack")]
+pub mod msgpack;
+#[cfg (feature = "cbor")]
+pub mod cbor;
+#[cfg (feature = "postcard")]
+pub mod postcard;
+#[cfg (feature = "protobuf")]
+pub mod protobuf;
+pub mod json_rpc;
+mod pending_calls;
 pub mod websocket;
 pub mod stream;
 pub mod stream_collection;
@@ -36,6 +36,13 @@ macro_rules! __handle_sink_result
 				core::ops::ControlFlow::Break
 				(
 					compute_graph::exit_status::ExitStatus::Spurious
+					(
+						compute_graph::exit_status::Failure::new
+						(
+							compute_graph::exit_status::FailureKind::Sink,
+							std::string::ToString::to_string (&sink_error)
+						)
+					)
 				)
 			}
 		}
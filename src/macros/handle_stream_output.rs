@@ -23,7 +23,11 @@ macro_rules! __handle_stream_output
 			core::option::Option::Some ($item) => $handler,
 			core::option::Option::None => core::ops::ControlFlow::Break
 			(
-				compute_graph::exit_status::ExitStatus::Spurious
+				compute_graph::exit_status::ExitStatus::spurious
+				(
+					compute_graph::exit_status::FailureKind::StreamEnded,
+					std::stringify! ($output)
+				)
 			)
 		}
 	}
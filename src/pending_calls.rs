@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures::future::join_all;
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tokio::time::{Duration, timeout};
+
+use crate::exit_status::{Failure, FailureKind};
+
+pub (crate) type Reply = Result <Value, Failure>;
+
+type ReplySenders = HashMap <u64, oneshot::Sender <Reply>>;
+
+// Matches replies up with the calls waiting for them, for clients that tag
+// each request with an id.
+#[derive (Clone)]
+pub (crate) struct PendingCalls
+{
+	reply_senders: Arc <Mutex <ReplySenders>>,
+	next_id: Arc <AtomicU64>
+}
+
+impl PendingCalls
+{
+	pub (crate) fn new () -> Self
+	{
+		Self
+		{
+			reply_senders: Arc::new (Mutex::new (HashMap::new ())),
+			next_id: Arc::new (AtomicU64::new (1))
+		}
+	}
+
+	pub (crate) fn register (&self) -> (u64, oneshot::Receiver <Reply>)
+	{
+		let id = self . next_id . fetch_add (1, Ordering::Relaxed);
+		let (reply_sender, reply_receiver) = oneshot::channel ();
+
+		self . lock () . insert (id, reply_sender);
+
+		(id, reply_receiver)
+	}
+
+	// Hands over the call waiting for this id, if there is one.
+	pub (crate) fn take (&self, id: u64) -> Option <oneshot::Sender <Reply>>
+	{
+		self . lock () . remove (&id)
+	}
+
+	pub (crate) fn fail_all (&self, failure: Failure)
+	{
+		let reply_senders: Vec <_> = self . lock () . drain () . collect ();
+
+		for (_, reply_sender) in reply_senders
+		{
+			let _ = reply_sender . send (Err (failure . clone ()));
+		}
+	}
+
+	// Sends the request and waits for a reply to every call in it.  The calls
+	// are forgotten however this ends, so a late reply goes nowhere.
+	pub (crate) async fn exchange <F>
+	(
+		&self,
+		send: F,
+		reply_receivers: Vec <(u64, oneshot::Receiver <Reply>)>,
+		call_timeout: Duration,
+		stopped: fn () -> Failure
+	)
+	-> Result <Vec <Reply>, Failure>
+	where F: Future <Output = Result <(), Failure>>
+	{
+		let ids: Vec <u64> =
+			reply_receivers . iter () . map (|(id, _)| *id) . collect ();
+		let _forget = Forget {pending_calls: self, ids: &ids};
+
+		let replies = timeout
+		(
+			call_timeout,
+			async
+			{
+				send . await?;
+
+				let replies = join_all
+				(
+					reply_receivers
+						. into_iter ()
+						. map (|(_, reply_receiver)| reply_receiver)
+				)
+					. await;
+
+				let replies = replies . into_iter () . map
+				(
+					|reply| reply . unwrap_or_else (|_| Err (stopped ()))
+				);
+
+				Ok (replies . collect ())
+			}
+		)
+			. await;
+
+		replies . unwrap_or_else
+		(
+			|_| Err
+			(
+				Failure::new
+				(
+					FailureKind::Timeout,
+					match ids . as_slice ()
+					{
+						[id] => format! ("no reply to call {}", id),
+						_ => format! ("no reply to calls {:?}", ids)
+					}
+				)
+			)
+		)
+	}
+
+	fn lock (&self) -> MutexGuard <'_, ReplySenders>
+	{
+		self
+			. reply_senders
+			. lock ()
+			. unwrap_or_else (PoisonError::into_inner)
+	}
+}
+
+struct Forget <'a>
+{
+	pending_calls: &'a PendingCalls,
+	ids: &'a [u64]
+}
+
+impl Drop for Forget <'_>
+{
+	fn drop (&mut self)
+	{
+		let mut reply_senders = self . pending_calls . lock ();
+
+		for id in self . ids
+		{
+			reply_senders . remove (id);
+		}
+	}
+}
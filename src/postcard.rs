@@ -0,0 +1,156 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use bytes::Bytes;
+use serde::{Serialize, Deserialize};
+use tracing::{Level, event};
+use tungstenite::{Message, Utf8Bytes};
+
+use crate::{
+	service,
+	expand_streams,
+	event_loop,
+	event_loop_fallible,
+	check_break,
+	feed
+};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::websocket::io_format::{
+	Decoder,
+	InputFormat,
+	OutputFormat,
+	text_in_binary_protocol
+};
+
+use crate as compute_graph;
+
+// Items carry no field names or types, so both ends must agree on T exactly.
+fn encode <T> (item: &T) -> Result <Bytes, postcard::Error>
+where T: Serialize
+{
+	postcard::to_allocvec (item) . map (Bytes::from)
+}
+
+#[expand_streams]
+#[service]
+pub async fn postcard_serialize <IS, OS>
+(
+	item_stream: input! (IS -> impl Serialize + Debug),
+	bytes_sink: output! (OS <- Bytes)
+)
+{
+	event_loop!
+	{
+		item_stream -> item => match encode (&item)
+		{
+			Ok (item_bytes) => check_break! (feed! (bytes_sink, item_bytes)),
+			Err (postcard_error) => event!
+			(
+				Level::ERROR,
+				item = ?item,
+				error = %postcard_error,
+				"failed to serialize item"
+			)
+		}
+	}
+}
+
+// Frames that fail to deserialize are handled as output_format says.
+#[expand_streams]
+#[service]
+pub async fn postcard_deserialize <F, IS, OS>
+(
+	output_format: F,
+	bytes_stream: input! (IS -> impl AsRef <[u8]>),
+	item_sink: output! (OS <- F::External)
+)
+-> ExitStatus
+where F: OutputFormat
+{
+	let mut decoder = Decoder::new (output_format);
+
+	event_loop_fallible!
+	{
+		bytes_stream -> bytes =>
+		{
+			let message =
+				Message::binary (Bytes::copy_from_slice (bytes . as_ref ()));
+
+			if let Some (item) = decoder . decode (message)
+			{
+				check_break!
+				(
+					feed! (item_sink, item) . map_break (|_| ExitStatus::Clean)
+				);
+			}
+
+			check_break! (decoder . check_failures ());
+		}
+	}
+}
+
+#[derive (Debug)]
+pub struct Postcard <T> (PhantomData <T>);
+
+impl <T> Copy for Postcard <T> {}
+
+impl <T> Clone for Postcard <T>
+{
+	fn clone (&self) -> Self
+	{
+		*self
+	}
+}
+
+impl <T> Default for Postcard <T>
+{
+	fn default () -> Self
+	{
+		Self (PhantomData)
+	}
+}
+
+impl <T> InputFormat for Postcard <T>
+where T: Serialize + Debug
+{
+	type Intermediate = T;
+
+	fn convert (item: Self::Intermediate) -> Option <Message>
+	{
+		match encode (&item)
+		{
+			Ok (item_bytes) => Some (Message::Binary (item_bytes)),
+			Err (postcard_error) =>
+			{
+				event!
+				(
+					Level::ERROR,
+					item = ?item,
+					error = %postcard_error,
+					"failed to serialize item"
+				);
+
+				None
+			}
+		}
+	}
+}
+
+impl <T> OutputFormat for Postcard <T>
+where T: for <'de> Deserialize <'de>
+{
+	type External = T;
+
+	fn convert_text (_utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
+	{
+		Err (text_in_binary_protocol ())
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
+	{
+		postcard::from_bytes (&bytes) . map_err
+		(
+			|error| Failure::from_error (FailureKind::Decode, error)
+		)
+	}
+}
@@ -0,0 +1,260 @@
+use std::marker::PhantomData;
+
+use bytes::{Buf, Bytes};
+use futures::StreamExt;
+use futures::stream::iter;
+use prost::{DecodeError, Message as ProstMessage};
+use tracing::{Level, event};
+use tungstenite::{Message, Utf8Bytes};
+
+use crate::{
+	service,
+	expand_streams,
+	event_loop,
+	event_loop_fallible,
+	check_break,
+	feed,
+	send_all
+};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::websocket::io_format::{
+	Decoder,
+	InputFormat,
+	OutputFormat,
+	text_in_binary_protocol
+};
+
+use crate as compute_graph;
+
+// Each message is prefixed with its length as a varint, so several can share
+// one frame.
+fn encode_delimited <T> (items: &[T]) -> Bytes
+where T: ProstMessage
+{
+	let mut item_bytes = Vec::new ();
+
+	for item in items
+	{
+		item_bytes . extend (item . encode_length_delimited_to_vec ());
+	}
+
+	Bytes::from (item_bytes)
+}
+
+fn decode_delimited <T> (mut bytes: impl Buf) -> Result <Vec <T>, DecodeError>
+where T: ProstMessage + Default
+{
+	let mut items = Vec::new ();
+
+	while bytes . has_remaining ()
+	{
+		items . push (T::decode_length_delimited (&mut bytes)?);
+	}
+
+	Ok (items)
+}
+
+#[expand_streams]
+#[service]
+pub async fn protobuf_encode <IS, OS>
+(
+	item_stream: input! (IS -> impl ProstMessage),
+	bytes_sink: output! (OS <- Bytes)
+)
+{
+	event_loop!
+	{
+		item_stream -> item =>
+		{
+			let item_bytes = Bytes::from (item . encode_to_vec ());
+			check_break! (feed! (bytes_sink, item_bytes))
+		}
+	}
+}
+
+// Frames that fail to decode are handled as output_format says.
+#[expand_streams]
+#[service]
+pub async fn protobuf_decode <F, IS, OS>
+(
+	output_format: F,
+	bytes_stream: input! (IS -> impl AsRef <[u8]>),
+	item_sink: output! (OS <- F::External)
+)
+-> ExitStatus
+where F: OutputFormat
+{
+	let mut decoder = Decoder::new (output_format);
+
+	event_loop_fallible!
+	{
+		bytes_stream -> bytes =>
+		{
+			let message =
+				Message::binary (Bytes::copy_from_slice (bytes . as_ref ()));
+
+			if let Some (item) = decoder . decode (message)
+			{
+				check_break!
+				(
+					feed! (item_sink, item) . map_break (|_| ExitStatus::Clean)
+				);
+			}
+
+			check_break! (decoder . check_failures ());
+		}
+	}
+}
+
+#[expand_streams]
+#[service]
+pub async fn protobuf_encode_delimited <IS, OS, II>
+(
+	items_stream: input! (IS -> impl AsRef <[II]>),
+	bytes_sink: output! (OS <- Bytes)
+)
+where II: ProstMessage
+{
+	event_loop!
+	{
+		items_stream -> items =>
+		{
+			let items_bytes = encode_delimited (items . as_ref ());
+			check_break! (feed! (bytes_sink, items_bytes))
+		}
+	}
+}
+
+// Items from a frame that fails to decode are all dropped, since the framing
+// can't be trusted past the failure.
+#[expand_streams]
+#[service]
+pub async fn protobuf_decode_delimited <IS, OS, OI>
+(
+	bytes_stream: input! (IS -> impl AsRef <[u8]>),
+	item_sink: output! (OS <- OI: ProstMessage + Default)
+)
+{
+	event_loop!
+	{
+		bytes_stream -> bytes => match decode_delimited (bytes . as_ref ())
+		{
+			Ok (items) =>
+			{
+				let mut items = iter (items) . map (Ok);
+				check_break! (send_all! (item_sink, &mut items))
+			},
+			Err (decode_error) => event!
+			(
+				Level::ERROR,
+				items_bytes = ?bytes . as_ref (),
+				error = %decode_error,
+				"failed to decode items"
+			)
+		}
+	}
+}
+
+// One message per frame.
+#[derive (Debug)]
+pub struct Protobuf <T> (PhantomData <T>);
+
+impl <T> Copy for Protobuf <T> {}
+
+impl <T> Clone for Protobuf <T>
+{
+	fn clone (&self) -> Self
+	{
+		*self
+	}
+}
+
+impl <T> Default for Protobuf <T>
+{
+	fn default () -> Self
+	{
+		Self (PhantomData)
+	}
+}
+
+impl <T> InputFormat for Protobuf <T>
+where T: ProstMessage
+{
+	type Intermediate = T;
+
+	fn convert (item: Self::Intermediate) -> Option <Message>
+	{
+		Some (Message::Binary (Bytes::from (item . encode_to_vec ())))
+	}
+}
+
+impl <T> OutputFormat for Protobuf <T>
+where T: ProstMessage + Default
+{
+	type External = T;
+
+	fn convert_text (_utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
+	{
+		Err (text_in_binary_protocol ())
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
+	{
+		T::decode (bytes) . map_err
+		(
+			|error| Failure::from_error (FailureKind::Decode, error)
+		)
+	}
+}
+
+// Any number of length-delimited messages per frame.
+#[derive (Debug)]
+pub struct DelimitedProtobuf <T> (PhantomData <T>);
+
+impl <T> Copy for DelimitedProtobuf <T> {}
+
+impl <T> Clone for DelimitedProtobuf <T>
+{
+	fn clone (&self) -> Self
+	{
+		*self
+	}
+}
+
+impl <T> Default for DelimitedProtobuf <T>
+{
+	fn default () -> Self
+	{
+		Self (PhantomData)
+	}
+}
+
+impl <T> InputFormat for DelimitedProtobuf <T>
+where T: ProstMessage
+{
+	type Intermediate = Vec <T>;
+
+	fn convert (items: Self::Intermediate) -> Option <Message>
+	{
+		Some (Message::Binary (encode_delimited (&items)))
+	}
+}
+
+impl <T> OutputFormat for DelimitedProtobuf <T>
+where T: ProstMessage + Default
+{
+	type External = Vec <T>;
+
+	fn convert_text (_utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
+	{
+		Err (text_in_binary_protocol ())
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
+	{
+		decode_delimited (bytes) . map_err
+		(
+			|error| Failure::from_error (FailureKind::Decode, error)
+		)
+	}
+}
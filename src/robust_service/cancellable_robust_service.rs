@@ -1,235 +1,208 @@
 use std::future::Future;
+use std::ops::ControlFlow;
 use std::pin::{Pin, pin};
 
 use tokio::sync::watch;
 use tokio::time::{Duration, sleep};
+use tracing::{Level, event};
 
-use crate::{service, event_loop};
-use crate::exit_status::ServiceExitStatus;
+use crate::{service, event_loop_fallible, check_break};
+use crate::exit_status::{ExitStatus, ServiceExitStatus};
 use crate::service_handle::{ServiceHandle, CancellableServiceHandle};
-use crate::service_state::ServiceState;
+use crate::service_state::{ServiceState, ServiceStatus};
 
+use super::config::{RobustServiceConfig, StatusReporter, replacement_timer};
 use super::fallible_service_factory::CancellableFallibleServiceFactory;
+use super::restart_policy::{RestartTracker, budget_exhausted};
 
 use crate as compute_graph;
 
-async fn replace_down_service <C, T>
-(
-	constructor_handle: Pin <&mut C>,
-	service_handle: &mut CancellableServiceHandle <T>
-)
-where
-	C: Future <Output = CancellableServiceHandle <T>>,
-	T: Default + ServiceExitStatus + Unpin
-{
-	*service_handle = constructor_handle . await;
-}
-
 async fn replace_service <C, T>
 (
 	mut constructor_handle: Pin <&mut C>,
-	service_handle: &mut CancellableServiceHandle <T>
-)
-where
-	C: Future <Output = CancellableServiceHandle <T>>,
-	T: Default + ServiceExitStatus + Unpin
-{
-	tokio::select!
-	{
-		_ = &mut *service_handle => replace_down_service
-		(
-			constructor_handle,
-			service_handle
-		) . await,
-		new_service_handle = constructor_handle . as_mut () =>
-		{
-			let mut old_service_handle =
-				std::mem::replace (service_handle, new_service_handle);
-
-			old_service_handle . shutdown ();
-			old_service_handle . await;
-		}
-	};
-}
-
-async fn replace_down_service_with_status_reporting <C, T>
-(
-	constructor_handle: Pin <&mut C>,
 	service_handle: &mut CancellableServiceHandle <T>,
-	status_sender: &mut watch::Sender <ServiceState>
+	status: &mut StatusReporter,
+	restart_tracker: &mut RestartTracker
 )
+-> ControlFlow <ExitStatus>
 where
 	C: Future <Output = CancellableServiceHandle <T>>,
 	T: Default + ServiceExitStatus + Unpin
 {
-	*service_handle = constructor_handle . await;
+	// Replacements count against the restart budget like any other restart.
+	if ! restart_tracker . record_restart ()
+	{
+		event! (Level::ERROR, "service exhausted its restart budget");
+		return ControlFlow::Break (budget_exhausted (ExitStatus::Clean));
+	}
 
-	status_sender . send_replace (ServiceState::Up);
-}
+	status . report (ServiceState::Degraded);
 
-async fn replace_service_with_status_reporting <C, T>
-(
-	mut constructor_handle: Pin <&mut C>,
-	service_handle: &mut CancellableServiceHandle <T>,
-	status_sender: &mut watch::Sender <ServiceState>
-)
-where
-	C: Future <Output = CancellableServiceHandle <T>>,
-	T: Default + ServiceExitStatus + Unpin
-{
 	tokio::select!
 	{
-		_ = &mut *service_handle => replace_down_service_with_status_reporting
-		(
-			constructor_handle,
-			service_handle,
-			status_sender
-		) . await,
+		output = &mut *service_handle =>
+		{
+			// The replacement is already on its way, so there's no point in
+			// backing off.
+			status . report_exit (output . exit_status ());
+			status . report_restart ();
+
+			*service_handle = constructor_handle . await;
+
+			status . report (ServiceState::Up);
+		},
 		new_service_handle = constructor_handle . as_mut () =>
 		{
 			let mut old_service_handle =
 				std::mem::replace (service_handle, new_service_handle);
 
-			// State UP (though we should still be UP at this point)
-
 			old_service_handle . shutdown ();
 			old_service_handle . await;
+
+			status . report_replacement ();
 		}
 	};
+
+	restart_tracker . started ();
+
+	ControlFlow::Continue (())
 }
 
-pub trait CancellableRobustService
+pub trait CancellableRobustService: Sized
 {
-	fn into_robust_service (self) -> CancellableServiceHandle <()>;
+	fn into_robust_service_with_config (self, config: RobustServiceConfig)
+	-> CancellableServiceHandle <ExitStatus>;
+
+	fn into_robust_service (self) -> CancellableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config (RobustServiceConfig::new ())
+	}
 
 	fn into_robust_service_with_preemptive_replacement
 	(
 		self,
 		replacement_interval: Duration
 	)
-	-> CancellableServiceHandle <()>;
+	-> CancellableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new ()
+				. with_preemptive_replacement (replacement_interval)
+		)
+	}
 
 	fn into_robust_service_with_status_reporting
 	(
 		self,
-		status_sender: watch::Sender <ServiceState>
+		status_sender: watch::Sender <ServiceStatus>
 	)
-	-> CancellableServiceHandle <()>;
+	-> CancellableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new () . with_status_reporting (status_sender)
+		)
+	}
 
 	fn into_robust_service_with_preemptive_replacement_and_status_reporting
 	(
 		self,
 		replacement_interval: Duration,
-		status_sender: watch::Sender <ServiceState>
+		status_sender: watch::Sender <ServiceStatus>
 	)
-	-> CancellableServiceHandle <()>;
+	-> CancellableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new ()
+				. with_preemptive_replacement (replacement_interval)
+				. with_status_reporting (status_sender)
+		)
+	}
 }
 
 impl <T> CancellableRobustService for T
 where T: CancellableFallibleServiceFactory + Send + 'static
 {
 	#[service]
-	async fn into_robust_service (mut self)
-	{
-		let mut service_handle = self . construct () . await;
-
-		loop
-		{
-			(&mut service_handle) . await;
-
-			replace_down_service
-			(
-				pin! (self . construct ()),
-				&mut service_handle
-			)
-				. await;
-		}
-	}
-
-	#[service]
-	async fn into_robust_service_with_preemptive_replacement
+	async fn into_robust_service_with_config
 	(
 		mut self,
-		replacement_interval: Duration
+		config: RobustServiceConfig
 	)
+	-> ExitStatus
 	{
-		let mut service_handle = self . construct () . await;
-
-		event_loop!
+		// Cancellable services stop as soon as they are shut down, so the
+		// shutdown timeout doesn't apply to them.
+		let RobustServiceConfig
 		{
-			_ = &mut service_handle => replace_down_service
-			(
-				pin! (self . construct ()),
-				&mut service_handle
-			) . await,
-			_ = sleep (replacement_interval) => replace_service
-			(
-				pin! (self . construct ()),
-				&mut service_handle
-			) . await
+			replacement_interval,
+			status_sender,
+			restart_policy,
+			..
 		}
-	}
-
-	#[service]
-	async fn into_robust_service_with_status_reporting
-	(
-		mut self,
-		mut status_sender: watch::Sender <ServiceState>
-	)
-	{
-		let mut service_handle = self . construct () . await;
+			= config;
 
-		status_sender . send_replace (ServiceState::Up);
+		let mut status = StatusReporter::new (status_sender);
+		let mut restart_tracker = RestartTracker::new (restart_policy);
 
-		loop
-		{
-			(&mut service_handle) . await;
-
-			status_sender . send_replace (ServiceState::Down);
+		status . report (ServiceState::Starting);
 
-			replace_down_service_with_status_reporting
-			(
-				pin! (self . construct ()),
-				&mut service_handle,
-				&mut status_sender
-			) . await;
-		}
-	}
-
-	#[service]
-	async fn into_robust_service_with_preemptive_replacement_and_status_reporting
-	(
-		mut self,
-		replacement_interval: Duration,
-		mut status_sender: watch::Sender <ServiceState>
-	)
-	{
 		let mut service_handle = self . construct () . await;
 
-		status_sender . send_replace (ServiceState::Up);
+		restart_tracker . started ();
+		status . report (ServiceState::Up);
 
-		event_loop!
+		let exit_status = event_loop_fallible!
 		{
-			_ = &mut service_handle =>
+			output = &mut service_handle =>
 			{
-				status_sender . send_replace (ServiceState::Down);
-
-				replace_down_service_with_status_reporting
-				(
-					pin! (self . construct ()),
-					&mut service_handle,
-					&mut status_sender
-				) . await;
+				let exit_status = output . exit_status ();
+				status . report_exit (exit_status . clone ());
+
+				let restart_delay = match restart_tracker . restart_delay ()
+				{
+					Some (restart_delay) => restart_delay,
+					None =>
+					{
+						event!
+						(
+							Level::ERROR,
+							"service exhausted its restart budget"
+						);
+
+						break budget_exhausted (exit_status);
+					}
+				};
+
+				if ! restart_delay . is_zero ()
+				{
+					status . report (ServiceState::Restarting);
+					sleep (restart_delay) . await;
+				}
+
+				status . report_restart ();
+
+				service_handle = self . construct () . await;
+
+				restart_tracker . started ();
+				status . report (ServiceState::Up);
 			},
-			_ = sleep (replacement_interval) =>
-			{
-				replace_service_with_status_reporting
+			_ = replacement_timer (replacement_interval) => check_break!
+			(
+				replace_service
 				(
 					pin! (self . construct ()),
 					&mut service_handle,
-					&mut status_sender
-				) . await;
-			}
-		}
+					&mut status,
+					&mut restart_tracker
+				) . await
+			)
+		};
+
+		status . report_final (&exit_status);
+
+		exit_status
 	}
 }
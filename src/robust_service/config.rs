@@ -0,0 +1,176 @@
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant, sleep};
+use tracing::{Level, event};
+
+use crate::exit_status::ExitStatus;
+use crate::service_handle::ServiceHandle;
+use crate::service_state::{ServiceState, ServiceStatus};
+
+use super::restart_policy::RestartPolicy;
+
+#[derive (Default)]
+pub struct RobustServiceConfig
+{
+	pub replacement_interval: Option <Duration>,
+	pub status_sender: Option <watch::Sender <ServiceStatus>>,
+	pub restart_policy: RestartPolicy,
+	// Without a shutdown timeout, services may take as long as they like to
+	// shut down.
+	pub shutdown_timeout: Option <Duration>
+}
+
+impl RobustServiceConfig
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn with_preemptive_replacement (mut self, replacement_interval: Duration)
+	-> Self
+	{
+		self . replacement_interval = Some (replacement_interval);
+		self
+	}
+
+	pub fn with_status_reporting
+	(
+		mut self,
+		status_sender: watch::Sender <ServiceStatus>
+	)
+	-> Self
+	{
+		self . status_sender = Some (status_sender);
+		self
+	}
+
+	pub fn with_restart_policy (mut self, restart_policy: RestartPolicy) -> Self
+	{
+		self . restart_policy = restart_policy;
+		self
+	}
+
+	pub fn with_shutdown_timeout (mut self, shutdown_timeout: Duration) -> Self
+	{
+		self . shutdown_timeout = Some (shutdown_timeout);
+		self
+	}
+}
+
+pub (in crate::robust_service) struct StatusReporter
+{
+	status_sender: Option <watch::Sender <ServiceStatus>>,
+	restart_count: u32,
+	last_exit_status: Option <ExitStatus>
+}
+
+impl StatusReporter
+{
+	pub (in crate::robust_service) fn new
+	(
+		status_sender: Option <watch::Sender <ServiceStatus>>
+	)
+	-> Self
+	{
+		Self {status_sender, restart_count: 0, last_exit_status: None}
+	}
+
+	pub (in crate::robust_service) fn report (&self, state: ServiceState)
+	{
+		if let Some (status_sender) = &self . status_sender
+		{
+			status_sender . send_replace
+			(
+				ServiceStatus
+				{
+					state,
+					restart_count: self . restart_count,
+					last_exit_status: self . last_exit_status . clone (),
+					since: Instant::now ()
+				}
+			);
+		}
+	}
+
+	pub (in crate::robust_service) fn report_exit
+	(
+		&mut self,
+		exit_status: ExitStatus
+	)
+	{
+		self . last_exit_status = Some (exit_status);
+		self . report (ServiceState::Down);
+	}
+
+	pub (in crate::robust_service) fn report_restart (&mut self)
+	{
+		self . restart_count = self . restart_count . saturating_add (1);
+		self . report (ServiceState::Starting);
+	}
+
+	// A preemptive replacement counts as a restart, but the service never goes
+	// down.
+	pub (in crate::robust_service) fn report_replacement (&mut self)
+	{
+		self . restart_count = self . restart_count . saturating_add (1);
+		self . report (ServiceState::Up);
+	}
+
+	// Reports the state the robust service leaves behind once it exits.
+	pub (in crate::robust_service) fn report_final
+	(
+		&self,
+		exit_status: &ExitStatus
+	)
+	{
+		match exit_status . is_spurious ()
+		{
+			true => self . report (ServiceState::Failed),
+			false => self . report (ServiceState::Down)
+		}
+	}
+}
+
+// Never completes if there's no replacement interval.
+pub (in crate::robust_service) async fn replacement_timer
+(
+	replacement_interval: Option <Duration>
+)
+{
+	match replacement_interval
+	{
+		Some (replacement_interval) => sleep (replacement_interval) . await,
+		None => std::future::pending () . await
+	}
+}
+
+pub (in crate::robust_service) async fn stop_service <S>
+(
+	service_handle: &mut S,
+	shutdown_timeout: Option <Duration>
+)
+where S: ServiceHandle + Unpin + Send
+{
+	match shutdown_timeout
+	{
+		None =>
+		{
+			service_handle . shutdown ();
+			service_handle . await;
+		},
+		Some (shutdown_timeout) =>
+		{
+			let shutdown_outcome =
+				service_handle . shutdown_with_timeout (shutdown_timeout) . await;
+
+			if shutdown_outcome . is_escalated ()
+			{
+				event!
+				(
+					Level::WARN,
+					"service missed its shutdown deadline and was aborted"
+				);
+			}
+		}
+	}
+}
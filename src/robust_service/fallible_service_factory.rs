@@ -1,6 +1,8 @@
 use std::future::Future;
 
-use crate::exit_status::ServiceExitStatus;
+use futures::future::FusedFuture;
+
+use crate::exit_status::{ExitStatus, ServiceExitStatus};
 use crate::service_handle::{ServiceHandle, CancellableServiceHandle};
 use crate::task_handle::TaskHandle;
 
@@ -19,16 +21,43 @@ pub trait CancellableFallibleServiceFactory
 		+ 'static;
 }
 
+// What a constructor came up with.  A constructor that gives up says what
+// the robust service should exit with; one that's cut short by shutdown or
+// aborted comes up with nothing.
+#[derive (Default)]
+pub enum Construction <S>
+{
+	Built (S),
+	GaveUp (ExitStatus),
+	#[default]
+	Aborted
+}
+
+impl <S> Construction <S>
+{
+	pub fn map <T, F> (self, f: F) -> Construction <T>
+	where F: FnOnce (S) -> T
+	{
+		match self
+		{
+			Self::Built (service) => Construction::Built (f (service)),
+			Self::GaveUp (exit_status) => Construction::GaveUp (exit_status),
+			Self::Aborted => Construction::Aborted
+		}
+	}
+}
+
 pub trait SignallableFallibleServiceFactory
 {
 	fn construct (&mut self)
 	-> impl TaskHandle
 		+ Future
 		<
-			Output = Option
+			Output = Construction
 			<
 				impl ServiceHandle
 					+ Future <Output: ServiceExitStatus + Default + Send + Unpin + 'static>
+					+ FusedFuture
 					+ Unpin
 					+ Send
 					+ 'static
@@ -1,8 +1,17 @@
 mod fallible_service_factory;
 pub use fallible_service_factory::*;
 
+mod restart_policy;
+pub use restart_policy::{RestartPolicy, RestartBudget};
+
+mod config;
+pub use config::RobustServiceConfig;
+
 mod cancellable_robust_service;
 pub use cancellable_robust_service::*;
 
 mod signallable_robust_service;
 pub use signallable_robust_service::*;
+
+mod supervisor;
+pub use supervisor::{Supervisor, SupervisorBuilder, Strategy};
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+
+use tokio::time::{Duration, Instant};
+
+use crate::backoff::{Backoff, BackoffPolicy};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+
+#[derive (Copy, Clone, Debug)]
+pub struct RestartBudget
+{
+	pub max_restarts: u32,
+	pub window: Duration
+}
+
+#[derive (Copy, Clone, Debug)]
+pub struct RestartPolicy
+{
+	// Without a backoff policy, services are restarted immediately.
+	pub backoff: Option <BackoffPolicy>,
+	// Without a budget, services are restarted forever.
+	pub budget: Option <RestartBudget>,
+	// A service that stays up at least this long resets the backoff.
+	pub reset_after: Duration
+}
+
+impl Default for RestartPolicy
+{
+	fn default () -> Self
+	{
+		Self
+		{
+			backoff: None,
+			budget: None,
+			reset_after: Duration::from_secs (60)
+		}
+	}
+}
+
+impl RestartPolicy
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn with_backoff (mut self, backoff: BackoffPolicy) -> Self
+	{
+		self . backoff = Some (backoff);
+		self
+	}
+
+	pub fn with_budget (mut self, max_restarts: u32, window: Duration) -> Self
+	{
+		self . budget = Some (RestartBudget {max_restarts, window});
+		self
+	}
+
+	pub fn with_reset_after (mut self, reset_after: Duration) -> Self
+	{
+		self . reset_after = reset_after;
+		self
+	}
+}
+
+pub (crate) struct RestartTracker
+{
+	policy: RestartPolicy,
+	backoff: Option <Backoff>,
+	restarts: VecDeque <Instant>,
+	started_at: Instant
+}
+
+impl RestartTracker
+{
+	pub (crate) fn new (policy: RestartPolicy) -> Self
+	{
+		Self
+		{
+			policy,
+			backoff: policy . backoff . map (Backoff::new),
+			restarts: VecDeque::new (),
+			started_at: Instant::now ()
+		}
+	}
+
+	pub (crate) fn started (&mut self)
+	{
+		self . started_at = Instant::now ();
+	}
+
+	// Records a restart against the budget without backing off, returning
+	// false if the budget has been used up.
+	pub (crate) fn record_restart (&mut self) -> bool
+	{
+		let now = Instant::now ();
+
+		if let Some (budget) = self . policy . budget
+		{
+			while let Some (restarted_at) = self . restarts . front ()
+			{
+				if now . duration_since (*restarted_at) < budget . window
+				{
+					break;
+				}
+
+				self . restarts . pop_front ();
+			}
+
+			if self . restarts . len () >= budget . max_restarts as usize
+			{
+				return false;
+			}
+
+			self . restarts . push_back (now);
+		}
+
+		true
+	}
+
+	// Records a restart and returns how long to wait before performing it, or
+	// None if the restart budget has been used up.
+	pub (crate) fn restart_delay (&mut self) -> Option <Duration>
+	{
+		if ! self . record_restart ()
+		{
+			return None;
+		}
+
+		let now = Instant::now ();
+
+		let delay = match &mut self . backoff
+		{
+			None => Duration::ZERO,
+			Some (backoff) =>
+			{
+				if now . duration_since (self . started_at)
+					>= self . policy . reset_after
+				{
+					backoff . reset ();
+				}
+
+				backoff . next_delay ()
+			}
+		};
+
+		Some (delay)
+	}
+}
+
+// The exit status of a robust service that ran out of restarts, wrapping the
+// failure of the last service it ran.
+pub (crate) fn budget_exhausted (last_exit_status: ExitStatus) -> ExitStatus
+{
+	let failure = Failure::new
+	(
+		FailureKind::RestartBudget,
+		"service exhausted its restart budget"
+	);
+
+	match last_exit_status
+	{
+		ExitStatus::Clean => ExitStatus::Spurious (failure),
+		ExitStatus::Spurious (last_failure) =>
+			ExitStatus::Spurious (failure . with_source (last_failure))
+	}
+}
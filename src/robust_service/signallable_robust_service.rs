@@ -2,15 +2,28 @@ use std::future::Future;
 use std::ops::ControlFlow;
 use std::pin::{Pin, pin};
 
+use futures::future::FusedFuture;
 use tokio::sync::{oneshot, watch};
 use tokio::time::{Duration, sleep};
+use tracing::{Level, event};
 
-use crate::{service, select, event_loop, check_break};
+use crate::{service, event_loop_fallible, check_break};
+use crate::exit_status::{ExitStatus, FailureKind, ServiceExitStatus};
 use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
-use crate::service_state::ServiceState;
+use crate::service_state::{ServiceState, ServiceStatus};
 use crate::task_handle::TaskHandle;
 
-use super::fallible_service_factory::SignallableFallibleServiceFactory;
+use super::config::{
+	RobustServiceConfig,
+	StatusReporter,
+	replacement_timer,
+	stop_service
+};
+use super::fallible_service_factory::{
+	Construction,
+	SignallableFallibleServiceFactory
+};
+use super::restart_policy::{RestartTracker, budget_exhausted};
 
 use crate as compute_graph;
 
@@ -19,27 +32,27 @@ async fn shutdown_constructor_handle <C, S>
 	mut constructor_handle: Pin <&mut C>
 )
 where
-	C: TaskHandle + Future <Output = Option <S>>,
+	C: TaskHandle + Future <Output = Construction <S>>,
 	S: ServiceHandle
 {
 	constructor_handle . as_mut () . abort ();
 
-	if let Some (mut new_service_handle) = constructor_handle . await
+	if let Construction::Built (mut new_service_handle) =
+		constructor_handle . await
 	{
 		new_service_handle . shutdown ();
 		new_service_handle . await;
 	}
 }
 
-async fn replace_down_service <C, S>
+pub (in crate::robust_service) async fn start_service <C, S>
 (
 	shutdown: &mut oneshot::Receiver <()>,
-	mut constructor_handle: Pin <&mut C>,
-	service_handle: &mut S
+	mut constructor_handle: Pin <&mut C>
 )
--> ControlFlow <()>
+-> ControlFlow <ExitStatus, S>
 where
-	C: TaskHandle + Future <Output = Option <S>>,
+	C: TaskHandle + Future <Output = Construction <S>>,
 	S: ServiceHandle
 {
 	tokio::select!
@@ -48,349 +61,284 @@ where
 		_ = &mut *shutdown =>
 		{
 			shutdown_constructor_handle (constructor_handle) . await;
-			ControlFlow::Break (())
+			ControlFlow::Break (ExitStatus::Clean)
 		},
 		constructor_result = constructor_handle . as_mut () =>
-		{
-			*service_handle = constructor_result . unwrap ();
-			ControlFlow::Continue (())
-		}
+			built_service (constructor_result)
 	}
 }
 
-async fn replace_service <C, S>
-(
-	shutdown: &mut oneshot::Receiver <()>,
-	mut constructor_handle: Pin <&mut C>,
-	service_handle: &mut S
-)
--> ControlFlow <()>
-where
-	C: TaskHandle + Future <Output = Option <S>>,
-	S: ServiceHandle + Unpin
+// We only abort the constructor on shutdown, so if it came up without a
+// service otherwise, it gave up.
+fn built_service <S> (construction: Construction <S>)
+-> ControlFlow <ExitStatus, S>
 {
-	select!
+	let exit_status = match construction
 	{
-		_ = &mut *shutdown =>
-		{
-			shutdown_constructor_handle (constructor_handle) . await;
-			ControlFlow::Break (())
-		},
-		_ = &mut *service_handle => replace_down_service
+		Construction::Built (service_handle) =>
+			return ControlFlow::Continue (service_handle),
+		Construction::GaveUp (exit_status) => exit_status,
+		Construction::Aborted => ExitStatus::spurious
 		(
-			shutdown,
-			constructor_handle,
-			service_handle
-		) . await,
-		constructor_result = constructor_handle . as_mut () =>
-		{
-			// The constructor should always return Some (S) unless it was
-			// aborted.
-			let new_service_handle = constructor_result . unwrap ();
-			let mut old_service_handle =
-				std::mem::replace (service_handle, new_service_handle);
+			FailureKind::Constructor,
+			"service constructor gave up without saying why"
+		)
+	};
 
-			old_service_handle . shutdown ();
-			old_service_handle . await;
-
-			ControlFlow::Continue (())
-		}
-	}
+	event! (Level::ERROR, ?exit_status, "service constructor gave up");
+	ControlFlow::Break (exit_status)
 }
 
-pub async fn replace_down_service_with_status_reporting <C, S>
+pub (in crate::robust_service) async fn wait_for_restart
 (
 	shutdown: &mut oneshot::Receiver <()>,
-	mut constructor_handle: Pin <&mut C>,
-	service_handle: &mut S,
-	status_sender: &mut watch::Sender <ServiceState>
+	restart_delay: Duration
 )
--> ControlFlow <()>
-where
-	C: TaskHandle + Future <Output = Option <S>>,
-	S: ServiceHandle
+-> ControlFlow <ExitStatus>
 {
+	if restart_delay . is_zero ()
+	{
+		return ControlFlow::Continue (());
+	}
+
 	tokio::select!
 	{
 		biased;
-		_ = &mut *shutdown =>
-		{
-			shutdown_constructor_handle (constructor_handle) . await;
-			ControlFlow::Break (())
-		},
-		constructor_result = constructor_handle . as_mut () =>
-		{
-			// The constructor should always return Some (S) unless it was
-			// aborted.
-			*service_handle = constructor_result . unwrap ();
-
-			status_sender . send_replace (ServiceState::Up);
-
-			ControlFlow::Continue (())
-		}
+		_ = &mut *shutdown => ControlFlow::Break (ExitStatus::Clean),
+		_ = sleep (restart_delay) => ControlFlow::Continue (())
 	}
 }
 
-pub async fn replace_service_with_status_reporting <C, S>
+async fn replace_service <C, S>
 (
 	shutdown: &mut oneshot::Receiver <()>,
 	mut constructor_handle: Pin <&mut C>,
 	service_handle: &mut S,
-	status_sender: &mut watch::Sender <ServiceState>
+	status: &mut StatusReporter,
+	restart_tracker: &mut RestartTracker,
+	shutdown_timeout: Option <Duration>
 )
--> ControlFlow <()>
+-> ControlFlow <ExitStatus>
 where
-	C: TaskHandle + Future <Output = Option <S>>,
-	S: ServiceHandle + Unpin
+	C: TaskHandle + Future <Output = Construction <S>>,
+	S: ServiceHandle + Unpin + Send,
+	S::Output: ServiceExitStatus
 {
+	// Replacements count against the restart budget like any other restart.
+	if ! restart_tracker . record_restart ()
+	{
+		event! (Level::ERROR, "service exhausted its restart budget");
+		shutdown_constructor_handle (constructor_handle) . await;
+		return ControlFlow::Break (budget_exhausted (ExitStatus::Clean));
+	}
+
+	status . report (ServiceState::Degraded);
+
 	tokio::select!
 	{
 		biased;
 		_ = &mut *shutdown =>
 		{
 			shutdown_constructor_handle (constructor_handle) . await;
-			ControlFlow::Break (())
+			ControlFlow::Break (ExitStatus::Clean)
 		},
-		_ = &mut *service_handle =>
+		output = &mut *service_handle =>
 		{
-			status_sender . send_replace (ServiceState::Down);
+			// The replacement is already on its way, so there's no point in
+			// backing off.
+			status . report_exit (output . exit_status ());
+			status . report_restart ();
 
-			replace_down_service_with_status_reporting
-			(
-				shutdown,
-				constructor_handle,
-				service_handle,
-				status_sender
-			) . await
+			*service_handle = start_service (shutdown, constructor_handle) . await?;
+
+			restart_tracker . started ();
+			status . report (ServiceState::Up);
+
+			ControlFlow::Continue (())
 		},
 		constructor_result = constructor_handle . as_mut () =>
 		{
-			// The constructor should always return Some (S) unless it was
-			// aborted.
-			let new_service_handle = constructor_result . unwrap ();
+			let new_service_handle = built_service (constructor_result)?;
+
 			let mut old_service_handle =
 				std::mem::replace (service_handle, new_service_handle);
 
-			// State UP (though we should still be UP at this point)
+			stop_service (&mut old_service_handle, shutdown_timeout) . await;
 
-			old_service_handle . shutdown ();
-			old_service_handle . await;
+			restart_tracker . started ();
+			status . report_replacement ();
 
 			ControlFlow::Continue (())
 		}
 	}
 }
 
-pub trait SignallableRobustService
+pub trait SignallableRobustService: Sized
 {
-	fn into_robust_service (self) -> SignallableServiceHandle <()>;
+	fn into_robust_service_with_config (self, config: RobustServiceConfig)
+	-> SignallableServiceHandle <ExitStatus>;
+
+	fn into_robust_service (self) -> SignallableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config (RobustServiceConfig::new ())
+	}
 
 	fn into_robust_service_with_preemptive_replacement
 	(
 		self,
 		replacement_interval: Duration
 	)
-	-> SignallableServiceHandle <()>;
+	-> SignallableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new ()
+				. with_preemptive_replacement (replacement_interval)
+		)
+	}
 
 	fn into_robust_service_with_status_reporting
 	(
 		self,
-		status_sender: watch::Sender <ServiceState>
+		status_sender: watch::Sender <ServiceStatus>
 	)
-	-> SignallableServiceHandle <()>;
+	-> SignallableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new () . with_status_reporting (status_sender)
+		)
+	}
 
 	fn into_robust_service_with_preemptive_replacement_and_status_reporting
 	(
 		self,
 		replacement_interval: Duration,
-		status_sender: watch::Sender <ServiceState>
+		status_sender: watch::Sender <ServiceStatus>
 	)
-	-> SignallableServiceHandle <()>;
-}
-
-macro_rules! init_service_handle_with_shutdown
-{
-	($constructor_handle: expr, $shutdown: expr) =>
-	{{
-		let mut constructor_handle = $constructor_handle;
-		let shutdown = $shutdown;
-
-		tokio::select!
-		{
-			biased;
-			_ = shutdown =>
-			{
-				constructor_handle . as_mut () . abort ();
-				if let Some (mut service_handle) = constructor_handle . await
-				{
-					service_handle . shutdown ();
-					service_handle . await;
-				}
-
-				return;
-			},
-			constructor_result = &mut constructor_handle =>
-				constructor_result . unwrap ()
-		}
-	}}
+	-> SignallableServiceHandle <ExitStatus>
+	{
+		self . into_robust_service_with_config
+		(
+			RobustServiceConfig::new ()
+				. with_preemptive_replacement (replacement_interval)
+				. with_status_reporting (status_sender)
+		)
+	}
 }
 
 impl <T> SignallableRobustService for T
 where T: SignallableFallibleServiceFactory + Send + 'static
 {
 	#[service (shutdown = shutdown)]
-	async fn into_robust_service (mut self)
-	{
-		let mut service_handle = init_service_handle_with_shutdown!
-		(
-			pin! (self . construct ()),
-			&mut shutdown
-		);
-
-		event_loop!
-		{
-			?&mut shutdown,
-			_ = &mut service_handle => check_break!
-			(
-				replace_down_service
-				(
-					&mut shutdown,
-					pin! (self . construct ()),
-					&mut service_handle
-				) . await
-			)
-		};
-
-		service_handle . shutdown ();
-		service_handle . await;
-	}
-
-	#[service (shutdown = shutdown)]
-	async fn into_robust_service_with_preemptive_replacement
+	async fn into_robust_service_with_config
 	(
 		mut self,
-		replacement_interval: Duration
+		config: RobustServiceConfig
 	)
+	-> ExitStatus
 	{
-		let mut service_handle = init_service_handle_with_shutdown!
-		(
-			pin! (self . construct ()),
-			&mut shutdown
-		);
-
-		event_loop!
+		let RobustServiceConfig
 		{
-			?&mut shutdown,
-			_ = &mut service_handle => check_break!
-			(
-				replace_down_service
-				(
-					&mut shutdown,
-					pin! (self . construct ()),
-					&mut service_handle
-				) . await
-			),
-			_ = sleep (replacement_interval) => check_break!
-			(
-				replace_service
-				(
-					&mut shutdown,
-					pin! (self . construct ()),
-					&mut service_handle
-				) . await
-			)
-		};
+			replacement_interval,
+			status_sender,
+			restart_policy,
+			shutdown_timeout
+		}
+			= config;
 
-		service_handle . shutdown ();
-		service_handle . await;
-	}
+		let mut status = StatusReporter::new (status_sender);
+		let mut restart_tracker = RestartTracker::new (restart_policy);
 
-	#[service (shutdown = shutdown)]
-	async fn into_robust_service_with_status_reporting
-	(
-		mut self,
-		mut status_sender: watch::Sender <ServiceState>
-	)
-	{
-		let mut service_handle = init_service_handle_with_shutdown!
-		(
-			pin! (self . construct ()),
-			&mut shutdown
-		);
-
-		status_sender . send_replace (ServiceState::Up);
+		status . report (ServiceState::Starting);
 
-		event_loop!
+		let mut service_handle = match start_service
+		(
+			&mut shutdown,
+			pin! (self . construct ())
+		) . await
 		{
-			?&mut shutdown,
-			_ = &mut service_handle =>
+			ControlFlow::Continue (service_handle) => service_handle,
+			ControlFlow::Break (exit_status) =>
 			{
-				status_sender . send_replace (ServiceState::Down);
-
-				check_break!
-				(
-					replace_down_service_with_status_reporting
-					(
-						&mut shutdown,
-						pin! (self . construct ()),
-						&mut service_handle,
-						&mut status_sender
-					) . await
-				)
+				status . report_final (&exit_status);
+				return exit_status;
 			}
 		};
 
-		service_handle . shutdown ();
-		service_handle . await;
-	}
-
-	#[service (shutdown = shutdown)]
-	async fn into_robust_service_with_preemptive_replacement_and_status_reporting
-	(
-		mut self,
-		replacement_interval: Duration,
-		mut status_sender: watch::Sender <ServiceState>
-	)
-	{
-		let mut service_handle = init_service_handle_with_shutdown!
-		(
-			pin! (self . construct ()),
-			&mut shutdown
-		);
-
-		status_sender . send_replace (ServiceState::Up);
+		restart_tracker . started ();
+		status . report (ServiceState::Up);
 
-		event_loop!
+		let exit_status = event_loop_fallible!
 		{
 			?&mut shutdown,
-			_ = &mut service_handle =>
+			output = &mut service_handle =>
 			{
-				status_sender . send_replace (ServiceState::Down);
+				let exit_status = output . exit_status ();
+				status . report_exit (exit_status . clone ());
+
+				let restart_delay = match restart_tracker . restart_delay ()
+				{
+					Some (restart_delay) => restart_delay,
+					None =>
+					{
+						event!
+						(
+							Level::ERROR,
+							"service exhausted its restart budget"
+						);
+
+						break budget_exhausted (exit_status);
+					}
+				};
+
+				if ! restart_delay . is_zero ()
+				{
+					status . report (ServiceState::Restarting);
+				}
 
 				check_break!
 				(
-					replace_down_service_with_status_reporting
+					wait_for_restart (&mut shutdown, restart_delay) . await
+				);
+
+				status . report_restart ();
+
+				service_handle = check_break!
+				(
+					start_service
 					(
 						&mut shutdown,
-						pin! (self . construct ()),
-						&mut service_handle,
-						&mut status_sender
+						pin! (self . construct ())
 					) . await
-				)
+				);
+
+				restart_tracker . started ();
+				status . report (ServiceState::Up);
 			},
-			_ = sleep (replacement_interval) =>	check_break!
+			_ = replacement_timer (replacement_interval) => check_break!
 			(
-				replace_service_with_status_reporting
+				replace_service
 				(
 					&mut shutdown,
 					pin! (self . construct ()),
 					&mut service_handle,
-					&mut status_sender
+					&mut status,
+					&mut restart_tracker,
+					shutdown_timeout
 				) . await
 			)
 		};
 
-		service_handle . shutdown ();
-		service_handle . await;
+		// The event loop may have already consumed the service handle if it
+		// exited while we were replacing it.
+		if ! service_handle . is_terminated ()
+		{
+			stop_service (&mut service_handle, shutdown_timeout) . await;
+		}
+
+		status . report_final (&exit_status);
+
+		exit_status
 	}
 }
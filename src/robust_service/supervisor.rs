@@ -0,0 +1,404 @@
+use std::future::{Future, poll_fn};
+use std::ops::ControlFlow;
+use std::pin::{Pin, pin};
+use std::sync::Arc;
+use std::task::Poll;
+
+use futures::future::BoxFuture;
+use tokio::sync::{Mutex, oneshot};
+use tracing::{Level, event};
+
+use crate::{service, task};
+use crate::exit_status::{ExitStatus, ServiceExitStatus};
+use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
+use crate::task_handle::TaskHandle;
+
+use super::fallible_service_factory::{
+	CancellableFallibleServiceFactory,
+	Construction,
+	SignallableFallibleServiceFactory
+};
+use super::restart_policy::{RestartPolicy, RestartTracker, budget_exhausted};
+use super::signallable_robust_service::{start_service, wait_for_restart};
+
+use crate as compute_graph;
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Strategy
+{
+	// Only the child that exited is restarted.
+	OneForOne,
+	// Every child is shut down and restarted when any of them exits.
+	OneForAll,
+	// The child that exited is restarted along with every child that was
+	// started after it.
+	RestForOne
+}
+
+// Children are started in the order they were added and shut down in reverse.
+pub struct Supervisor
+{
+	strategy: Strategy,
+	restart_policy: RestartPolicy,
+	children: Arc <Mutex <Vec <Child>>>
+}
+
+// Collects a supervisor's children.  They can't be added once it's built, so
+// a running supervisor's children never change under it.
+pub struct SupervisorBuilder
+{
+	strategy: Strategy,
+	restart_policy: RestartPolicy,
+	children: Vec <Child>
+}
+
+type ChildHandle = SignallableServiceHandle <ExitStatus>;
+
+struct Child
+{
+	name: String,
+	factory: Box <dyn SupervisedFactory>
+}
+
+impl Supervisor
+{
+	pub fn builder (strategy: Strategy) -> SupervisorBuilder
+	{
+		SupervisorBuilder
+		{
+			strategy,
+			restart_policy: RestartPolicy::default (),
+			children: Vec::new ()
+		}
+	}
+
+	// A supervisor only runs one instance at a time.  Starting it again waits
+	// for the previous instance to shut down before starting any children.
+	pub fn start (&self) -> SignallableServiceHandle <ExitStatus>
+	{
+		supervise
+		(
+			self . strategy,
+			self . restart_policy,
+			self . children . clone ()
+		)
+	}
+}
+
+impl SupervisorBuilder
+{
+	// The restart policy applies to the supervisor as a whole, so a budget
+	// limits how often any of its children may be restarted.
+	pub fn with_restart_policy (mut self, restart_policy: RestartPolicy)
+	-> Self
+	{
+		self . restart_policy = restart_policy;
+		self
+	}
+
+	pub fn with_signallable_child <F>
+	(
+		self,
+		name: impl Into <String>,
+		factory: F
+	)
+	-> Self
+	where F: SignallableFallibleServiceFactory + Send + 'static
+	{
+		let factory = Box::new (SignallableChild (factory));
+		self . with_child (name . into (), factory)
+	}
+
+	pub fn with_cancellable_child <F>
+	(
+		self,
+		name: impl Into <String>,
+		factory: F
+	)
+	-> Self
+	where F: CancellableFallibleServiceFactory + Send + 'static
+	{
+		let factory = Box::new (CancellableChild (factory));
+		self . with_child (name . into (), factory)
+	}
+
+	fn with_child
+	(
+		mut self,
+		name: String,
+		factory: Box <dyn SupervisedFactory>
+	)
+	-> Self
+	{
+		self . children . push (Child {name, factory});
+		self
+	}
+
+	pub fn build (self) -> Supervisor
+	{
+		Supervisor
+		{
+			strategy: self . strategy,
+			restart_policy: self . restart_policy,
+			children: Arc::new (Mutex::new (self . children))
+		}
+	}
+}
+
+// Lets supervisors nest, and lets them be run as robust services.
+impl SignallableFallibleServiceFactory for Supervisor
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <SignallableServiceHandle <ExitStatus>>
+	{
+		Construction::Built (self . start ())
+	}
+}
+
+trait SupervisedFactory: Send
+{
+	fn start <'a> (&'a mut self, shutdown: &'a mut oneshot::Receiver <()>)
+	-> BoxFuture <'a, ControlFlow <ExitStatus, ChildHandle>>;
+}
+
+struct SignallableChild <F> (F);
+
+impl <F> SupervisedFactory for SignallableChild <F>
+where F: SignallableFallibleServiceFactory + Send
+{
+	fn start <'a> (&'a mut self, shutdown: &'a mut oneshot::Receiver <()>)
+	-> BoxFuture <'a, ControlFlow <ExitStatus, ChildHandle>>
+	{
+		Box::pin
+		(
+			async move
+			{
+				start_service (shutdown, pin! (self . 0 . construct ()))
+					. await
+					. map_continue (supervise_child)
+			}
+		)
+	}
+}
+
+struct CancellableChild <F> (F);
+
+impl <F> SupervisedFactory for CancellableChild <F>
+where F: CancellableFallibleServiceFactory + Send
+{
+	fn start <'a> (&'a mut self, shutdown: &'a mut oneshot::Receiver <()>)
+	-> BoxFuture <'a, ControlFlow <ExitStatus, ChildHandle>>
+	{
+		Box::pin
+		(
+			async move
+			{
+				let mut constructor_handle = pin! (self . 0 . construct ());
+
+				tokio::select!
+				{
+					biased;
+					_ = &mut *shutdown =>
+					{
+						constructor_handle . as_mut () . abort ();
+						ControlFlow::Break (ExitStatus::Clean)
+					},
+					service_handle = constructor_handle . as_mut () =>
+					{
+						ControlFlow::Continue (supervise_child (service_handle))
+					}
+				}
+			}
+		)
+	}
+}
+
+// Erases the type of a child's service handle.
+#[service (shutdown = shutdown)]
+async fn supervise_child <S> (mut service_handle: S) -> ExitStatus
+where
+	S: ServiceHandle + Unpin,
+	S::Output: ServiceExitStatus + Send
+{
+	tokio::select!
+	{
+		biased;
+		_ = &mut shutdown =>
+		{
+			service_handle . shutdown ();
+			service_handle . await . exit_status ()
+		},
+		output = &mut service_handle => output . exit_status ()
+	}
+}
+
+async fn start_children
+(
+	shutdown: &mut oneshot::Receiver <()>,
+	children: &mut [Child],
+	running: &mut [Option <ChildHandle>]
+)
+-> ControlFlow <ExitStatus>
+{
+	for (child, slot) in children . iter_mut () . zip (running . iter_mut ())
+	{
+		let service_handle = child . factory . start (shutdown) . await;
+
+		*slot = Some
+		(
+			service_handle . map_break
+			(
+				|exit_status|
+				{
+					if exit_status . is_spurious ()
+					{
+						event!
+						(
+							Level::ERROR,
+							child = child . name,
+							"failed to start child"
+						);
+					}
+
+					exit_status
+				}
+			)?
+		);
+	}
+
+	ControlFlow::Continue (())
+}
+
+async fn stop_children
+(
+	running: &mut [Option <ChildHandle>]
+)
+{
+	for slot in running . iter_mut () . rev ()
+	{
+		if let Some (mut service_handle) = slot . take ()
+		{
+			service_handle . shutdown ();
+			service_handle . await;
+		}
+	}
+}
+
+// Stays pending while no children are running.
+fn next_exit
+(
+	running: &mut [Option <ChildHandle>]
+)
+-> impl Future <Output = (usize, ExitStatus)> + '_
+{
+	poll_fn
+	(
+		move |cx|
+		{
+			for (index, slot) in running . iter_mut () . enumerate ()
+			{
+				if let Some (service_handle) = slot
+				{
+					if let Poll::Ready (exit_status) =
+						Pin::new (service_handle) . poll (cx)
+					{
+						*slot = None;
+						return Poll::Ready ((index, exit_status));
+					}
+				}
+			}
+
+			Poll::Pending
+		}
+	)
+}
+
+#[service (shutdown = shutdown)]
+async fn supervise
+(
+	strategy: Strategy,
+	restart_policy: RestartPolicy,
+	children: Arc <Mutex <Vec <Child>>>
+)
+-> ExitStatus
+{
+	let mut children = children . lock_owned () . await;
+	let mut running: Vec <_> = children . iter () . map (|_| None) . collect ();
+	let mut restart_tracker = RestartTracker::new (restart_policy);
+
+	let exit_status = 'supervise:
+	{
+		if let ControlFlow::Break (exit_status) =
+			start_children (&mut shutdown, &mut children, &mut running) . await
+		{
+			break 'supervise exit_status;
+		}
+
+		restart_tracker . started ();
+
+		loop
+		{
+			let (index, exit_status) = tokio::select!
+			{
+				biased;
+				_ = &mut shutdown => break 'supervise ExitStatus::Clean,
+				exit = next_exit (&mut running) => exit
+			};
+
+			event!
+			(
+				Level::WARN,
+				child = children [index] . name,
+				spurious = exit_status . is_spurious (),
+				"child exited"
+			);
+
+			let restart_delay = match restart_tracker . restart_delay ()
+			{
+				Some (restart_delay) => restart_delay,
+				None =>
+				{
+					event!
+					(
+						Level::ERROR,
+						"supervisor exhausted its restart budget"
+					);
+
+					break 'supervise budget_exhausted (exit_status);
+				}
+			};
+
+			let restarted = match strategy
+			{
+				Strategy::OneForOne => index..index + 1,
+				Strategy::OneForAll => 0..children . len (),
+				Strategy::RestForOne => index..children . len ()
+			};
+
+			stop_children (&mut running [restarted . clone ()]) . await;
+
+			if let ControlFlow::Break (exit_status) =
+				wait_for_restart (&mut shutdown, restart_delay) . await
+			{
+				break 'supervise exit_status;
+			}
+
+			if let ControlFlow::Break (exit_status) = start_children
+			(
+				&mut shutdown,
+				&mut children [restarted . clone ()],
+				&mut running [restarted]
+			) . await
+			{
+				break 'supervise exit_status;
+			}
+
+			restart_tracker . started ();
+		}
+	};
+
+	stop_children (&mut running) . await;
+
+	exit_status
+}
@@ -63,6 +63,15 @@ where
 		}
 	}
 
+	fn abort (&mut self)
+	{
+		if let Self::Handle (handle) = self
+		{
+			handle . abort ();
+			*self = Self::Taken;
+		}
+	}
+
 	async fn exit_status (&mut self) -> Option <ExitStatus>
 	{
 		let output_result = match self
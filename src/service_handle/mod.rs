@@ -2,18 +2,100 @@ mod cancellable;
 pub use cancellable::CancellableServiceHandle;
 
 mod signallable;
-pub use signallable::SignallableServiceHandle;
+pub use signallable::{SignallableServiceHandle, ShutdownTrigger};
 
 use std::future::Future;
 
+use tokio::time::{Duration, timeout};
+
 use crate::exit_status::*;
 
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ShutdownOutcome <T>
+{
+	// The service shut down on its own within the deadline.
+	Graceful (T),
+	// The service missed the deadline and was aborted, so it has no output.
+	Escalated
+}
+
+impl <T> ShutdownOutcome <T>
+{
+	pub fn is_graceful (&self) -> bool
+	{
+		matches! (self, Self::Graceful (_))
+	}
+
+	pub fn is_escalated (&self) -> bool
+	{
+		matches! (self, Self::Escalated)
+	}
+
+	pub fn into_output (self) -> Option <T>
+	{
+		match self
+		{
+			Self::Graceful (output) => Some (output),
+			Self::Escalated => None
+		}
+	}
+}
+
+// An aborted service did not get to clean up after itself, so escalation counts
+// as a spurious exit.
+impl <T> ServiceExitStatus for ShutdownOutcome <T>
+where T: ServiceExitStatus
+{
+	type Value = T::Value;
+
+	fn exit_status (&self) -> ExitStatus
+	{
+		match self
+		{
+			Self::Graceful (output) => output . exit_status (),
+			Self::Escalated => ExitStatus::spurious
+			(
+				FailureKind::Timeout,
+				"service did not shut down before its deadline"
+			)
+		}
+	}
+}
+
 pub trait ServiceHandle: Future
 {
 	fn shutdown (&mut self);
 
+	// Stops the service's task immediately.  The handle will not produce an
+	// output afterwards.  Handles that can't stop their task outright only
+	// signal it to shut down, and are left to finish in the background.
+	fn abort (&mut self)
+	{
+		self . shutdown ();
+	}
+
 	#[allow (async_fn_in_trait)]
 	async fn exit_status (&mut self) -> Option <ExitStatus>;
 
 	fn take_output (&mut self) -> Option <Self::Output>;
+
+	fn shutdown_with_timeout (&mut self, deadline: Duration)
+	-> impl Future <Output = ShutdownOutcome <Self::Output>> + Send + '_
+	where Self: Sized + Unpin + Send
+	{
+		self . shutdown ();
+
+		async move
+		{
+			match timeout (deadline, &mut *self) . await
+			{
+				Ok (output) => ShutdownOutcome::Graceful (output),
+				Err (_) =>
+				{
+					self . abort ();
+					ShutdownOutcome::Escalated
+				}
+			}
+		}
+	}
 }
@@ -5,17 +5,59 @@ use std::task::{Context, Poll};
 use futures::future::FusedFuture;
 use tokio::sync::oneshot::Sender;
 use tokio::task::{JoinHandle, JoinError};
+use tokio_util::sync::DropGuard;
 
 use crate::exit_status::{ExitStatus, ServiceExitStatus};
+use crate::shutdown::ShutdownToken;
 
 use super::ServiceHandle;
 
+// Dropping a trigger signals shutdown just like triggering it, so services
+// don't outlive their handles.
+#[derive (Debug)]
+pub enum ShutdownTrigger
+{
+	Oneshot (Sender <()>),
+	Token (DropGuard)
+}
+
+impl ShutdownTrigger
+{
+	fn trigger (self)
+	{
+		match self
+		{
+			Self::Oneshot (sender) =>
+			{
+				let _ = sender . send (());
+			},
+			Self::Token (drop_guard) => drop (drop_guard)
+		}
+	}
+}
+
+impl From <Sender <()>> for ShutdownTrigger
+{
+	fn from (sender: Sender <()>) -> Self
+	{
+		Self::Oneshot (sender)
+	}
+}
+
+impl From <ShutdownToken> for ShutdownTrigger
+{
+	fn from (token: ShutdownToken) -> Self
+	{
+		Self::Token (token . cancellation_token () . clone () . drop_guard ())
+	}
+}
+
 pub enum SignallableServiceHandle <T>
 {
 	Handle
 	{
 		handle: JoinHandle <T>,
-		shutdown_trigger: Option <Sender <()>>
+		shutdown_trigger: Option <ShutdownTrigger>
 	},
 	Output (T),
 	Taken
@@ -23,9 +65,14 @@ pub enum SignallableServiceHandle <T>
 
 impl <T> SignallableServiceHandle <T>
 {
-	pub fn new (handle: JoinHandle <T>, shutdown_trigger: Sender <()>) -> Self
+	pub fn new
+	(
+		handle: JoinHandle <T>,
+		shutdown_trigger: impl Into <ShutdownTrigger>
+	)
+	-> Self
 	{
-		Self::Handle {handle, shutdown_trigger: Some (shutdown_trigger)}
+		Self::Handle {handle, shutdown_trigger: Some (shutdown_trigger . into ())}
 	}
 
 	fn unwrap_output_result (output_result: Result <T, JoinError>) -> T
@@ -61,11 +108,20 @@ where
 		{
 			if let Some (shutdown_trigger) = shutdown_trigger . take ()
 			{
-				let _ = shutdown_trigger . send (());
+				shutdown_trigger . trigger ();
 			}
 		}
 	}
 
+	fn abort (&mut self)
+	{
+		if let Self::Handle {handle, ..} = self
+		{
+			handle . abort ();
+			*self = Self::Taken;
+		}
+	}
+
 	async fn exit_status (&mut self) -> Option <ExitStatus>
 	{
 		let output_result = match self
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures::Stream;
+
+use crate::service_handle::ServiceHandle;
+
+// A set of running services keyed by id.  As a stream, it yields each service's
+// output along with its key once the service exits, and removes the service
+// from the set.  The stream never ends - it stays pending while the set is
+// empty, and picks up services inserted later on.
+pub struct ServiceSet <K, S>
+{
+	services: HashMap <K, S>,
+	waker: Option <Waker>
+}
+
+impl <K, S> Default for ServiceSet <K, S>
+{
+	fn default () -> Self
+	{
+		Self {services: HashMap::new (), waker: None}
+	}
+}
+
+impl <K, S> ServiceSet <K, S>
+where
+	K: Eq + Hash,
+	S: ServiceHandle + Unpin
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn len (&self) -> usize
+	{
+		self . services . len ()
+	}
+
+	pub fn is_empty (&self) -> bool
+	{
+		self . services . is_empty ()
+	}
+
+	pub fn contains_key (&self, key: &K) -> bool
+	{
+		self . services . contains_key (key)
+	}
+
+	pub fn keys (&self) -> impl Iterator <Item = &K>
+	{
+		self . services . keys ()
+	}
+
+	pub fn get_mut (&mut self, key: &K) -> Option <&mut S>
+	{
+		self . services . get_mut (key)
+	}
+
+	// Returns the service that was previously registered under the key, if
+	// any.  The replaced service keeps running.
+	pub fn insert (&mut self, key: K, service: S) -> Option <S>
+	{
+		let replaced = self . services . insert (key, service);
+
+		if let Some (waker) = self . waker . take ()
+		{
+			waker . wake ();
+		}
+
+		replaced
+	}
+
+	// The removed service keeps running.  Shut it down and await it to stop it.
+	pub fn remove (&mut self, key: &K) -> Option <S>
+	{
+		self . services . remove (key)
+	}
+
+	// Signals every service in the set to shut down without waiting for them.
+	pub fn signal_shutdown (&mut self)
+	{
+		for service in self . services . values_mut ()
+		{
+			service . shutdown ();
+		}
+	}
+
+	// Shuts down every service in the set and waits for all of them to exit,
+	// leaving the set empty.
+	pub async fn shutdown (&mut self) -> HashMap <K, S::Output>
+	{
+		self . signal_shutdown ();
+
+		let mut outputs = HashMap::with_capacity (self . services . len ());
+
+		for (key, service) in self . services . drain ()
+		{
+			outputs . insert (key, service . await);
+		}
+
+		outputs
+	}
+}
+
+impl <K, S> Stream for ServiceSet <K, S>
+where
+	K: Clone + Eq + Hash + Unpin,
+	S: ServiceHandle + Unpin
+{
+	type Item = (K, S::Output);
+
+	fn poll_next (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Option <Self::Item>>
+	{
+		let this = self . get_mut ();
+
+		let exited = this . services . iter_mut () . find_map
+		(
+			|(key, service)| match Pin::new (service) . poll (cx)
+			{
+				Poll::Ready (output) => Some ((key . clone (), output)),
+				Poll::Pending => None
+			}
+		);
+
+		match exited
+		{
+			Some ((key, output)) =>
+			{
+				this . services . remove (&key);
+				Poll::Ready (Some ((key, output)))
+			},
+			None =>
+			{
+				this . waker = Some (cx . waker () . clone ());
+				Poll::Pending
+			}
+		}
+	}
+}
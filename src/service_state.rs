@@ -1,6 +1,57 @@
+use tokio::time::Instant;
+
+use crate::exit_status::ExitStatus;
+
 #[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ServiceState
 {
+	// The service is being constructed for the first time, or after a restart.
+	Starting,
 	Up,
-	Down
+	// The service is up, but a preemptive replacement is being constructed
+	// alongside it.
+	Degraded,
+	// The service exited and has not been restarted yet.
+	Down,
+	// The service exited and we are backing off before restarting it.
+	Restarting,
+	// The service exited and will not be restarted again.
+	Failed
+}
+
+#[derive (Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ServiceStatus
+{
+	pub state: ServiceState,
+	pub restart_count: u32,
+	pub last_exit_status: Option <ExitStatus>,
+	// When the service entered its current state.
+	pub since: Instant
+}
+
+impl ServiceStatus
+{
+	pub fn new (state: ServiceState) -> Self
+	{
+		Self
+		{
+			state,
+			restart_count: 0,
+			last_exit_status: None,
+			since: Instant::now ()
+		}
+	}
+
+	pub fn is_up (&self) -> bool
+	{
+		matches! (self . state, ServiceState::Up | ServiceState::Degraded)
+	}
+}
+
+impl Default for ServiceStatus
+{
+	fn default () -> Self
+	{
+		Self::new (ServiceState::Starting)
+	}
 }
@@ -0,0 +1,105 @@
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+// A shutdown signal that can be shared across a tree of services.  Cancelling a
+// token cancels every token derived from it, but not the token it was derived
+// from.  Awaiting a token waits for it to be cancelled, which makes it a drop-in
+// replacement for a oneshot receiver in shutdown arms.
+pub struct ShutdownToken
+{
+	token: CancellationToken,
+	cancelled: Option <Pin <Box <WaitForCancellationFutureOwned>>>
+}
+
+impl ShutdownToken
+{
+	pub fn new () -> Self
+	{
+		Self::from (CancellationToken::new ())
+	}
+
+	pub fn child_token (&self) -> Self
+	{
+		Self::from (self . token . child_token ())
+	}
+
+	pub fn cancel (&self)
+	{
+		self . token . cancel ();
+	}
+
+	pub fn is_cancelled (&self) -> bool
+	{
+		self . token . is_cancelled ()
+	}
+
+	pub fn cancellation_token (&self) -> &CancellationToken
+	{
+		&self . token
+	}
+}
+
+impl Default for ShutdownToken
+{
+	fn default () -> Self
+	{
+		Self::new ()
+	}
+}
+
+impl From <CancellationToken> for ShutdownToken
+{
+	fn from (token: CancellationToken) -> Self
+	{
+		Self {token, cancelled: None}
+	}
+}
+
+impl Clone for ShutdownToken
+{
+	fn clone (&self) -> Self
+	{
+		Self::from (self . token . clone ())
+	}
+}
+
+impl Debug for ShutdownToken
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		f . debug_struct ("ShutdownToken")
+			. field ("is_cancelled", &self . is_cancelled ())
+			. finish ()
+	}
+}
+
+// Unlike a oneshot receiver, a token may be polled again after it completes.
+impl Future for ShutdownToken
+{
+	type Output = ();
+
+	fn poll (self: Pin <&mut Self>, cx: &mut Context <'_>) -> Poll <()>
+	{
+		let this = self . get_mut ();
+
+		if this . token . is_cancelled ()
+		{
+			return Poll::Ready (());
+		}
+
+		let token = &this . token;
+
+		this
+			. cancelled
+			. get_or_insert_with
+			(
+				|| Box::pin (token . clone () . cancelled_owned ())
+			)
+			. as_mut ()
+			. poll (cx)
+	}
+}
@@ -1,5 +1,7 @@
 mod ready_items;
 pub use ready_items::*;
+mod shared;
+pub use shared::SharedStream;
 
 use std::fmt::{Debug, Display};
 
@@ -0,0 +1,54 @@
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+
+// Lets several consumers take turns pulling from one stream, e.g. the
+// successive connections of a websocket client.  Only the most recent poller
+// is woken, so it isn't meant for consumers that poll at the same time.
+pub struct SharedStream <S>
+{
+	stream: Arc <Mutex <S>>
+}
+
+impl <S> SharedStream <S>
+{
+	pub fn new (stream: S) -> Self
+	{
+		Self {stream: Arc::new (Mutex::new (stream))}
+	}
+}
+
+impl <S> Clone for SharedStream <S>
+{
+	fn clone (&self) -> Self
+	{
+		Self {stream: self . stream . clone ()}
+	}
+}
+
+impl <S> Debug for SharedStream <S>
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		f . debug_struct ("SharedStream") . finish_non_exhaustive ()
+	}
+}
+
+impl <S> Stream for SharedStream <S>
+where S: Stream + Unpin
+{
+	type Item = S::Item;
+
+	fn poll_next (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Option <Self::Item>>
+	{
+		self
+			. stream
+			. lock ()
+			. unwrap_or_else (PoisonError::into_inner)
+			. poll_next_unpin (cx)
+	}
+}
@@ -0,0 +1,186 @@
+use rand::{Rng, rng};
+use tokio::time::{Duration, Instant};
+use tracing::{Level, event};
+use tungstenite::http::Uri;
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EndpointSelection
+{
+	// Cycles through the endpoints, one per connection attempt.
+	RoundRobin,
+	// Always tries the primary request first, then the failover requests in
+	// order.
+	Priority,
+	// Picks endpoints at random, in proportion to their weights.
+	Random
+}
+
+#[derive (Clone, Debug)]
+pub struct FailoverRequest <R>
+{
+	pub request: R,
+	pub weight: u32
+}
+
+// An endpoint that fails to connect this many times in a row is skipped for a
+// while, unless every endpoint is being skipped.
+#[derive (Copy, Clone, Debug)]
+pub struct QuarantinePolicy
+{
+	pub after_failures: u32,
+	pub duration: Duration
+}
+
+impl Default for QuarantinePolicy
+{
+	fn default () -> Self
+	{
+		Self {after_failures: 3, duration: Duration::from_secs (60)}
+	}
+}
+
+#[derive (Clone, PartialEq, Eq, Debug)]
+pub struct ActiveEndpoint
+{
+	// Zero for the primary request, and one more than the position in the
+	// failover requests otherwise.
+	pub index: usize,
+	pub uri: Uri
+}
+
+#[derive (Clone, Default)]
+struct EndpointHealth
+{
+	consecutive_failures: u32,
+	quarantined_until: Option <Instant>
+}
+
+impl EndpointHealth
+{
+	fn is_quarantined (&self, now: Instant) -> bool
+	{
+		self
+			. quarantined_until
+			. is_some_and (|quarantined_until| now < quarantined_until)
+	}
+}
+
+pub (in crate::websocket::client) struct EndpointTracker
+{
+	health: Vec <EndpointHealth>,
+	last_attempt: Option <usize>
+}
+
+impl EndpointTracker
+{
+	pub (in crate::websocket::client) fn new () -> Self
+	{
+		Self {health: Vec::new (), last_attempt: None}
+	}
+
+	// weights holds the weight of every endpoint, primary first.
+	pub (in crate::websocket::client) fn select
+	(
+		&mut self,
+		selection: EndpointSelection,
+		weights: &[u32]
+	)
+	-> usize
+	{
+		self . health . resize (weights . len (), EndpointHealth::default ());
+
+		let now = Instant::now ();
+		let mut candidates: Vec <usize> = (0 .. weights . len ())
+			. filter (|&index| ! self . health [index] . is_quarantined (now))
+			. collect ();
+
+		if candidates . is_empty ()
+		{
+			candidates = (0 .. weights . len ()) . collect ();
+		}
+
+		let selected = match selection
+		{
+			EndpointSelection::Priority => candidates [0],
+			EndpointSelection::RoundRobin =>
+			{
+				let after = self . last_attempt . map_or (0, |last| last + 1);
+
+				candidates
+					. iter ()
+					. copied ()
+					. find (|&index| index >= after)
+					. unwrap_or (candidates [0])
+			},
+			EndpointSelection::Random => pick_weighted (&candidates, weights)
+		};
+
+		self . last_attempt = Some (selected);
+		selected
+	}
+
+	pub (in crate::websocket::client) fn record_success
+	(
+		&mut self,
+		index: usize
+	)
+	{
+		self . health [index] = EndpointHealth::default ();
+	}
+
+	pub (in crate::websocket::client) fn record_failure
+	(
+		&mut self,
+		index: usize,
+		quarantine_policy: &QuarantinePolicy
+	)
+	{
+		let health = &mut self . health [index];
+
+		health . consecutive_failures += 1;
+
+		if health . consecutive_failures >= quarantine_policy . after_failures
+		{
+			event!
+			(
+				Level::WARN,
+				endpoint = index,
+				failures = health . consecutive_failures,
+				"quarantining websocket endpoint"
+			);
+
+			health . consecutive_failures = 0;
+			health . quarantined_until =
+				Some (Instant::now () + quarantine_policy . duration);
+		}
+	}
+}
+
+fn pick_weighted (candidates: &[usize], weights: &[u32]) -> usize
+{
+	let total_weight: u64 = candidates
+		. iter ()
+		. map (|&index| u64::from (weights [index]))
+		. sum ();
+
+	if total_weight == 0
+	{
+		return candidates [rng () . random_range (0 .. candidates . len ())];
+	}
+
+	let mut ticket = rng () . random_range (0 .. total_weight);
+
+	for &index in candidates
+	{
+		let weight = u64::from (weights [index]);
+
+		if ticket < weight
+		{
+			return index;
+		}
+
+		ticket -= weight;
+	}
+
+	unreachable! ("ticket exceeded the total weight")
+}
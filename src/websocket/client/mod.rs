@@ -10,25 +10,183 @@ mod sink;
 pub use sink::WebSocketClientSink;
 mod sink_with_pings;
 pub use sink_with_pings::WebSocketClientSinkWithPings;
+mod redundant;
+pub use redundant::WebSocketRedundantClientNode;
+mod rpc;
+pub use rpc::{RpcClient, WebSocketRpcClientNode};
+mod endpoint;
+pub use endpoint::{
+	ActiveEndpoint,
+	EndpointSelection,
+	FailoverRequest,
+	QuarantinePolicy
+};
+mod proxy;
+pub use proxy::{ProxyConfig, ProxyCredentials, ProxyProtocol};
+mod resolver;
+pub use resolver::{Resolver, StaticResolver, SystemResolver};
+mod on_connect;
+pub use on_connect::{OnConnect, ClientStream};
+mod outbox;
+pub use outbox::{OutboxConfig, OverflowPolicy, ReplayFilter};
+pub (in crate::websocket) use outbox::Outbox;
+mod tls;
 
-use rand::rng;
-use rand::distr::{Distribution, Uniform};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use futures::future::BoxFuture;
 use tokio::net::TcpStream;
 use tokio::sync::oneshot::Receiver;
-use tokio::time::{Duration, sleep};
-use tokio_tungstenite::{Connector, WebSocketStream, MaybeTlsStream, connect_async_tls_with_config};
-use tracing::{Level, event};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant, sleep, timeout};
+use tokio_tungstenite::{Connector, client_async_with_config};
+use tracing::{Instrument, Level, event, info_span};
+use tungstenite::Message;
+use tungstenite::http::Uri;
 use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::client::{Request, Response};
+use tungstenite::protocol::frame::coding::CloseCode;
 use tungstenite::protocol::WebSocketConfig;
 
+use crate::service;
+use crate::backoff::{Backoff, BackoffPolicy};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::robust_service::Construction;
+use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
+
+use endpoint::EndpointTracker;
+use on_connect::OnConnectFn;
+use proxy::open_tunnel;
+use resolver::race_connections;
+
+pub use super::PingConfig;
+use super::{
+	ConnectionStats,
+	DeflateConfig,
+	DeflateStream,
+	MeteredStream,
+	PeerClosed
+};
+
+use crate as compute_graph;
+
+#[derive (Clone, Debug)]
+pub struct ReconnectPolicy
+{
+	pub backoff: BackoffPolicy,
+	// Give up on the connection after this many consecutive failed attempts.
+	pub max_attempts: Option <u32>,
+	// A connection that stays up at least this long resets the backoff.
+	pub reset_after: Duration,
+	// How long to wait before reconnecting after the server closes the
+	// connection with one of these codes, in place of the backoff.
+	pub close_delays: Vec <(CloseCode, Duration)>
+}
+
+impl Default for ReconnectPolicy
+{
+	fn default () -> Self
+	{
+		Self::new
+		(
+			BackoffPolicy::new
+			(
+				Duration::from_millis (500),
+				Duration::from_secs (30)
+			)
+		)
+	}
+}
+
+impl ReconnectPolicy
+{
+	pub fn new (backoff: BackoffPolicy) -> Self
+	{
+		Self
+		{
+			backoff,
+			max_attempts: None,
+			reset_after: Duration::from_secs (60),
+			close_delays: Vec::new ()
+		}
+	}
+
+	pub fn with_max_attempts (mut self, max_attempts: u32) -> Self
+	{
+		self . max_attempts = Some (max_attempts);
+		self
+	}
+
+	pub fn with_reset_after (mut self, reset_after: Duration) -> Self
+	{
+		self . reset_after = reset_after;
+		self
+	}
+
+	// For instance, CloseCode::Again (1013) for a server asking us to come
+	// back later.
+	pub fn with_close_delay (mut self, code: CloseCode, delay: Duration)
+	-> Self
+	{
+		self . close_delays . push ((code, delay));
+		self
+	}
+
+	fn close_delay (&self, peer_closed: &PeerClosed) -> Option <Duration>
+	{
+		self
+			. close_delays
+			. iter ()
+			. find (|(code, _)| *code == peer_closed . code)
+			. map (|(_, delay)| *delay)
+	}
+}
+
+#[derive (Clone)]
 pub struct ConnectionConfig <R>
 {
 	pub request: R,
+	// Only used for random endpoint selection.
+	pub request_weight: u32,
+	// Tried in place of the request when it can't be reached.
+	pub failover_requests: Vec <FailoverRequest <R>>,
+	pub endpoint_selection: EndpointSelection,
+	pub quarantine_policy: QuarantinePolicy,
+	pub endpoint_sender: Option <watch::Sender <Option <ActiveEndpoint>>>,
+	pub stats_sender: Option <watch::Sender <ConnectionStats>>,
+	// Further endpoints for redundant clients to keep connections to alongside
+	// the primary request.  Other clients ignore them.
+	pub standby_requests: Vec <R>,
 	pub stream_config: Option <WebSocketConfig>,
+	// Offered to the server, which may turn it down.
+	pub deflate: Option <DeflateConfig>,
 	pub disable_nagle: bool,
-	pub connector: Option <Connector>
+	pub connector: Option <Connector>,
+	pub proxy: Option <ProxyConfig>,
+	// Only consulted if there's no explicit proxy.
+	pub proxy_from_env: bool,
+	pub resolver: Arc <dyn Resolver>,
+	pub resolve_timeout: Option <Duration>,
+	// Applies to each address separately.
+	pub connect_timeout: Option <Duration>,
+	// Bounds the upgrade handshake once the TCP connection is open, for servers
+	// that accept connections but never answer.  Also bounds the handshake with
+	// a proxy, falling back to the connect timeout.
+	pub handshake_timeout: Option <Duration>,
+	// How long to wait on one address before also trying the next.
+	pub connection_attempt_delay: Duration,
+	pub reconnect_policy: ReconnectPolicy,
+	pub on_upgrade: Option <Arc <OnUpgrade>>,
+	pub on_connect: Option <Arc <dyn OnConnect>>
 }
 
+// Sees the server's response to the upgrade request, for its negotiated
+// subprotocol, rate limits or session id.  An error fails the connection
+// attempt.
+pub type OnUpgrade = dyn Fn (&Response) -> Result <(), Failure> + Send + Sync;
+
 impl <R> ConnectionConfig <R>
 {
 	pub fn new (request: R) -> Self
@@ -36,18 +194,143 @@ impl <R> ConnectionConfig <R>
 		Self
 		{
 			request,
+			request_weight: 1,
+			failover_requests: Vec::new (),
+			endpoint_selection: EndpointSelection::Priority,
+			quarantine_policy: QuarantinePolicy::default (),
+			endpoint_sender: None,
+			stats_sender: None,
+			standby_requests: Vec::new (),
 			stream_config: None,
+			deflate: None,
 			disable_nagle: false,
-			connector: None
+			connector: None,
+			proxy: None,
+			proxy_from_env: false,
+			resolver: Arc::new (SystemResolver),
+			resolve_timeout: None,
+			connect_timeout: None,
+			handshake_timeout: None,
+			connection_attempt_delay: Duration::from_millis (250),
+			reconnect_policy: ReconnectPolicy::default (),
+			on_upgrade: None,
+			on_connect: None
+		}
+	}
+
+	pub fn with_request_weight (mut self, weight: u32) -> Self
+	{
+		self . request_weight = weight;
+		self
+	}
+
+	pub fn with_failover (self, request: R) -> Self
+	{
+		self . with_weighted_failover (request, 1)
+	}
+
+	pub fn with_weighted_failover (mut self, request: R, weight: u32) -> Self
+	{
+		self . failover_requests . push (FailoverRequest {request, weight});
+		self
+	}
+
+	pub fn with_endpoint_selection
+	(
+		mut self,
+		endpoint_selection: EndpointSelection
+	)
+	-> Self
+	{
+		self . endpoint_selection = endpoint_selection;
+		self
+	}
+
+	pub fn with_quarantine_policy
+	(
+		mut self,
+		quarantine_policy: QuarantinePolicy
+	)
+	-> Self
+	{
+		self . quarantine_policy = quarantine_policy;
+		self
+	}
+
+	// Reports which endpoint the client is connected to, or None while it's
+	// reconnecting.
+	pub fn with_endpoint_reporting
+	(
+		mut self,
+		endpoint_sender: watch::Sender <Option <ActiveEndpoint>>
+	)
+	-> Self
+	{
+		self . endpoint_sender = Some (endpoint_sender);
+		self
+	}
+
+	// Reports the stats of the current connection, starting afresh each time
+	// the client reconnects.
+	pub fn with_stats_reporting
+	(
+		mut self,
+		stats_sender: watch::Sender <ConnectionStats>
+	)
+	-> Self
+	{
+		self . stats_sender = Some (stats_sender);
+		self
+	}
+
+	fn endpoint_request (&self, index: usize) -> &R
+	{
+		match index
+		{
+			0 => &self . request,
+			_ => &self . failover_requests [index - 1] . request
+		}
+	}
+
+	fn endpoint_weights (&self) -> Vec <u32>
+	{
+		std::iter::once (self . request_weight)
+			. chain
+			(
+				self
+					. failover_requests
+					. iter ()
+					. map (|failover_request| failover_request . weight)
+			)
+			. collect ()
+	}
+
+	fn report_endpoint (&self, active_endpoint: Option <ActiveEndpoint>)
+	{
+		if let Some (endpoint_sender) = &self . endpoint_sender
+		{
+			endpoint_sender . send_replace (active_endpoint);
 		}
 	}
 
+	pub fn with_standby (mut self, request: R) -> Self
+	{
+		self . standby_requests . push (request);
+		self
+	}
+
 	pub fn with_stream_config (mut self, stream_config: WebSocketConfig) -> Self
 	{
 		self . stream_config = Some (stream_config);
 		self
 	}
 
+	pub fn with_deflate (mut self, deflate_config: DeflateConfig) -> Self
+	{
+		self . deflate = Some (deflate_config);
+		self
+	}
+
 	pub fn disable_nagle (mut self) -> Self
 	{
 		self . disable_nagle = true;
@@ -59,57 +342,680 @@ impl <R> ConnectionConfig <R>
 		self . connector = Some (connector);
 		self
 	}
+
+	pub fn with_proxy (mut self, proxy: ProxyConfig) -> Self
+	{
+		self . proxy = Some (proxy);
+		self
+	}
+
+	pub fn with_proxy_from_env (mut self) -> Self
+	{
+		self . proxy_from_env = true;
+		self
+	}
+
+	pub fn with_resolver <T> (mut self, resolver: T) -> Self
+	where T: Resolver + 'static
+	{
+		self . resolver = Arc::new (resolver);
+		self
+	}
+
+	pub fn with_resolve_timeout (mut self, resolve_timeout: Duration) -> Self
+	{
+		self . resolve_timeout = Some (resolve_timeout);
+		self
+	}
+
+	pub fn with_connect_timeout (mut self, connect_timeout: Duration) -> Self
+	{
+		self . connect_timeout = Some (connect_timeout);
+		self
+	}
+
+	pub fn with_handshake_timeout (mut self, handshake_timeout: Duration)
+	-> Self
+	{
+		self . handshake_timeout = Some (handshake_timeout);
+		self
+	}
+
+	pub fn with_connection_attempt_delay
+	(
+		mut self,
+		connection_attempt_delay: Duration
+	)
+	-> Self
+	{
+		self . connection_attempt_delay = connection_attempt_delay;
+		self
+	}
+
+	fn proxy_for (&self, target: &Uri) -> std::io::Result <Option <ProxyConfig>>
+	{
+		match (&self . proxy, self . proxy_from_env)
+		{
+			(Some (proxy), _) => Ok (Some (proxy . clone ())),
+			(None, true) => ProxyConfig::from_env (target),
+			(None, false) => Ok (None)
+		}
+	}
+
+	pub fn with_reconnect_policy (mut self, reconnect_policy: ReconnectPolicy)
+	-> Self
+	{
+		self . reconnect_policy = reconnect_policy;
+		self
+	}
+
+	pub fn with_on_upgrade <F> (mut self, on_upgrade: F) -> Self
+	where F: Fn (&Response) -> Result <(), Failure> + Send + Sync + 'static
+	{
+		self . on_upgrade = Some (Arc::new (on_upgrade));
+		self
+	}
+
+	pub fn with_on_connect <H> (mut self, on_connect: H) -> Self
+	where H: OnConnect + 'static
+	{
+		self . on_connect = Some (Arc::new (on_connect));
+		self
+	}
+
+	pub fn with_on_connect_messages <I> (self, messages: I) -> Self
+	where I: IntoIterator <Item = Message>
+	{
+		self . with_on_connect (messages . into_iter () . collect::<Vec <_>> ())
+	}
+
+	pub fn with_on_connect_fn <F> (self, on_connect: F) -> Self
+	where
+		F: for <'a> Fn (&'a mut ClientStream)
+			-> BoxFuture <'a, Result <(), Failure>>
+			+ Send
+			+ Sync
+			+ 'static
+	{
+		self . with_on_connect (OnConnectFn (on_connect))
+	}
+}
+
+// Carried by each client factory across calls to `construct`, so that a
+// connection which keeps dropping shortly after it is established still backs
+// off.
+pub (in crate::websocket::client) struct ReconnectState
+{
+	backoff: Backoff,
+	connected_at: Option <Instant>,
+	// Why the server closed the last connection, if it did.
+	last_close: Arc <Mutex <Option <PeerClosed>>>,
+	endpoints: EndpointTracker
+}
+
+impl ReconnectState
+{
+	pub (in crate::websocket::client) fn new (policy: &ReconnectPolicy) -> Self
+	{
+		Self
+		{
+			backoff: Backoff::new (policy . backoff),
+			connected_at: None,
+			last_close: Arc::new (Mutex::new (None)),
+			endpoints: EndpointTracker::new ()
+		}
+	}
+
+	pub (in crate::websocket::client) fn watch_close
+	(
+		&self,
+		node_handle: SignallableServiceHandle <ExitStatus>
+	)
+	-> SignallableServiceHandle <ExitStatus>
+	{
+		record_close (node_handle, self . last_close . clone ())
+	}
+
+	fn take_last_close (&self) -> Option <PeerClosed>
+	{
+		self
+			. last_close
+			. lock ()
+			. unwrap_or_else (PoisonError::into_inner)
+			. take ()
+	}
 }
 
-pub struct PingConfig
+// Passes the node's exit status through, remembering any close frame it
+// carries.
+#[service (shutdown = shutdown)]
+async fn record_close
+(
+	mut node_handle: SignallableServiceHandle <ExitStatus>,
+	last_close: Arc <Mutex <Option <PeerClosed>>>
+)
+-> ExitStatus
 {
-	pub ping_interval: Duration,
-	pub ping_timeout: Duration
+	let exit_status = tokio::select!
+	{
+		biased;
+		_ = &mut shutdown =>
+		{
+			node_handle . shutdown ();
+			node_handle . await
+		},
+		exit_status = &mut node_handle => exit_status
+	};
+
+	*last_close . lock () . unwrap_or_else (PoisonError::into_inner) =
+		PeerClosed::from_exit_status (&exit_status) . cloned ();
+
+	exit_status
+}
+
+async fn sleep_or_shutdown (duration: Duration, shutdown: &mut Receiver <()>)
+-> bool
+{
+	tokio::select!
+	{
+		biased;
+		_ = &mut *shutdown => false,
+		_ = sleep (duration) => true
+	}
 }
 
 async fn connect_with_retry <R>
 (
 	connection_config: &ConnectionConfig <R>,
+	reconnect_state: &mut ReconnectState,
 	shutdown: &mut Receiver <()>
 )
--> Option <WebSocketStream <MaybeTlsStream <TcpStream>>>
+-> Construction <MeteredStream <ClientStream>>
 where R: Clone + IntoClientRequest + Unpin
 {
-	let random_time_distribution = Uniform::new_inclusive
+	let reconnect_policy = &connection_config . reconnect_policy;
+
+	let close_delay = reconnect_state
+		. take_last_close ()
+		. and_then
 	(
-		Duration::from_secs (5) . as_millis () as u64,
-		Duration::from_secs (30) . as_millis () as u64
-	) . unwrap ();
+		|peer_closed| reconnect_policy . close_delay (&peer_closed)
+	);
+
+	let delay = match (close_delay, reconnect_state . connected_at . take ())
+	{
+		(Some (close_delay), _) =>
+		{
+			event!
+			(
+				Level::INFO,
+				?close_delay,
+				"waiting before reconnecting, as the server asked"
+			);
+
+			Some (close_delay)
+		},
+		(None, Some (connected_at))
+			if connected_at . elapsed () >= reconnect_policy . reset_after =>
+		{
+			reconnect_state . backoff . reset ();
+			None
+		},
+		// The last connection didn't stay up for long, so we wait before
+		// trying again rather than hammering the server.
+		(None, Some (_)) => Some (reconnect_state . backoff . next_delay ()),
+		(None, None) => None
+	};
+
+	if let Some (delay) = delay
+	{
+		if ! sleep_or_shutdown (delay, shutdown) . await
+		{
+			return Construction::Aborted;
+		}
+	}
+
+	connection_config . report_endpoint (None);
+
+	let endpoint_weights = connection_config . endpoint_weights ();
+	let mut failed_attempts: u32 = 0;
 
 	loop
 	{
-		match connect_async_tls_with_config
+		let endpoint = reconnect_state . endpoints . select
 		(
-			connection_config . request . clone (),
-			connection_config . stream_config . clone (),
-			connection_config . disable_nagle,
-			connection_config . connector . clone ()
+			connection_config . endpoint_selection,
+			&endpoint_weights
+		);
+
+		let failure = match attempt_connection
+		(
+			connection_config,
+			endpoint,
+			shutdown
 		) . await
 		{
-			Ok ((stream, _)) => return Some (stream),
-			Err (connect_error) => event!
+			Attempt::Connected (stream, uri) =>
+			{
+				reconnect_state . endpoints . record_success (endpoint);
+				reconnect_state . connected_at = Some (Instant::now ());
+
+				connection_config . report_endpoint
+				(
+					Some (ActiveEndpoint {index: endpoint, uri})
+				);
+
+				return Construction::Built
+				(
+					MeteredStream::new
+					(
+						*stream,
+						connection_config . stats_sender . clone ()
+					)
+				);
+			},
+			Attempt::Failed (failure) =>
+			{
+				reconnect_state . endpoints . record_failure
+				(
+					endpoint,
+					&connection_config . quarantine_policy
+				);
+
+				failure
+			},
+			Attempt::Shutdown => return Construction::Aborted
+		};
+
+		failed_attempts += 1;
+
+		if let Some (max_attempts) = reconnect_policy . max_attempts
+		{
+			if failed_attempts >= max_attempts
+			{
+				event!
+				(
+					Level::ERROR,
+					failed_attempts,
+					"giving up on websocket connection"
+				);
+
+				return Construction::GaveUp (ExitStatus::Spurious (failure));
+			}
+		}
+
+		let delay = reconnect_state . backoff . next_delay ();
+
+		if ! sleep_or_shutdown (delay, shutdown) . await
+		{
+			return Construction::Aborted;
+		}
+	}
+}
+
+// Resolutions that take longer than this are logged even if they succeed.
+const SLOW_RESOLUTION: Duration = Duration::from_secs (1);
+
+async fn resolve <R>
+(
+	connection_config: &ConnectionConfig <R>,
+	host: &str,
+	port: u16
+)
+-> Result <Vec <SocketAddr>, Failure>
+{
+	let started_at = Instant::now ();
+	let resolution = connection_config . resolver . resolve (host, port);
+
+	let resolve_result = match connection_config . resolve_timeout
+	{
+		None => resolution . await,
+		Some (resolve_timeout) =>
+			match timeout (resolve_timeout, resolution) . await
+		{
+			Ok (resolve_result) => resolve_result,
+			Err (_) =>
+			{
+				event! (Level::ERROR, host, "dns resolution timed out");
+
+				return Err
+				(
+					Failure::new
+					(
+						FailureKind::Resolution,
+						format! ("resolving {} timed out", host)
+					)
+				);
+			}
+		}
+	};
+
+	let addresses = match resolve_result
+	{
+		Ok (addresses) if ! addresses . is_empty () => addresses,
+		Ok (_) =>
+		{
+			event! (Level::ERROR, host, "dns resolution returned no addresses");
+
+			return Err
+			(
+				Failure::new
+				(
+					FailureKind::Resolution,
+					format! ("no addresses for {}", host)
+				)
+			);
+		},
+		Err (resolve_error) =>
+		{
+			event!
 			(
 				Level::ERROR,
-				?connect_error,
-				"failed to establish websocket connection"
-			)
+				host,
+				%resolve_error,
+				"dns resolution failed"
+			);
+
+			return Err
+			(
+				Failure::from_error (FailureKind::Resolution, resolve_error)
+			);
 		}
+	};
+
+	let elapsed = started_at . elapsed ();
+
+	if elapsed >= SLOW_RESOLUTION
+	{
+		event! (Level::WARN, host, ?elapsed, "slow dns resolution");
+	}
+
+	Ok (addresses)
+}
+
+fn connect_failure (connect_error: std::io::Error) -> Failure
+{
+	match connect_error . kind ()
+	{
+		ErrorKind::TimedOut =>
+			Failure::from_error (FailureKind::Timeout, connect_error),
+		_ => Failure::from_error (FailureKind::Connection, connect_error)
+	}
+}
+
+async fn open_tcp_stream <R>
+(
+	connection_config: &ConnectionConfig <R>,
+	uri: &Uri
+)
+-> Result <TcpStream, Failure>
+{
+	let invalid_uri = || Failure::new
+	(
+		FailureKind::Connection,
+		format! ("no host or port in {}", uri)
+	);
 
-		let sleep_duration = Duration::from_millis
+	let host = uri
+		. host ()
+		. ok_or_else (invalid_uri)?
+		. trim_start_matches ('[')
+		. trim_end_matches (']');
+
+	let port = uri
+		. port_u16 ()
+		. or_else
 		(
-			random_time_distribution . sample (&mut rng ())
+			|| match uri . scheme_str ()
+			{
+				Some ("wss") => Some (443),
+				Some ("ws") => Some (80),
+				_ => None
+			}
+		)
+		. ok_or_else (invalid_uri)?;
+
+	let proxy =
+		connection_config . proxy_for (uri) . map_err (connect_failure)?;
+
+	let (connect_host, connect_port) = match &proxy
+	{
+		Some (proxy) => proxy . host_and_port () . map_err (connect_failure)?,
+		None => (host, port)
+	};
+
+	let addresses =
+		resolve (connection_config, connect_host, connect_port) . await?;
+
+	let mut tcp_stream = race_connections
+	(
+		addresses,
+		connection_config . connection_attempt_delay,
+		connection_config . connect_timeout
+	)
+		. await
+		. map_err (connect_failure)?;
+
+	if let Some (proxy) = proxy
+	{
+		event!
+		(
+			Level::DEBUG,
+			proxy = %proxy . address,
+			"tunnelling websocket connection through proxy"
 		);
 
-		tokio::select!
+		let tunnel = open_tunnel (tcp_stream, &proxy, host, port);
+
+		// The proxy's handshake counts as part of the upgrade handshake, so a
+		// proxy that stops answering can't hold up the attempt.
+		let proxy_timeout = connection_config
+			. handshake_timeout
+			. or (connection_config . connect_timeout);
+
+		tcp_stream = match proxy_timeout
+		{
+			None => tunnel . await,
+			Some (proxy_timeout) =>
+				match timeout (proxy_timeout, tunnel) . await
+			{
+				Ok (tunnel_result) => tunnel_result,
+				Err (_) => return Err
+				(
+					Failure::new
+					(
+						FailureKind::Timeout,
+						"proxy handshake timed out"
+					)
+				)
+			}
+		}
+			. map_err (connect_failure)?;
+	}
+
+	if connection_config . disable_nagle
+	{
+		tcp_stream . set_nodelay (true) . map_err (connect_failure)?;
+	}
+
+	Ok (tcp_stream)
+}
+
+async fn connect <R>
+(
+	connection_config: &ConnectionConfig <R>,
+	mut request: Request
+)
+-> Result <ClientStream, Failure>
+{
+	let tcp_stream =
+		open_tcp_stream (connection_config, request . uri ()) . await?;
+
+	if let Some (deflate_config) = &connection_config . deflate
+	{
+		deflate_config . offer (request . headers_mut ());
+	}
+
+	let handshake = async
+	{
+		let stream = tls::wrap_stream
+		(
+			tcp_stream,
+			request . uri (),
+			connection_config . connector . clone ()
+		)
+			. await?;
+
+		client_async_with_config
+		(
+			request,
+			DeflateStream::new (stream),
+			connection_config . stream_config
+		)
+			. await
+	};
+
+	let handshake_result = match connection_config . handshake_timeout
+	{
+		None => handshake . await,
+		Some (handshake_timeout) =>
+			match timeout (handshake_timeout, handshake) . await
+		{
+			Ok (handshake_result) => handshake_result,
+			Err (_) => return Err
+			(
+				Failure::new
+				(
+					FailureKind::Timeout,
+					"websocket handshake timed out"
+				)
+			)
+		}
+	};
+
+	let (mut stream, response) = handshake_result . map_err
+	(
+		|handshake_error|
+		Failure::from_error (FailureKind::Connection, handshake_error)
+	)?;
+
+	if let Some (deflate_config) = &connection_config . deflate
+	{
+		let negotiated = deflate_config . accept (response . headers ())?;
+
+		if let Some (negotiated) = negotiated
+		{
+			stream
+				. get_mut ()
+				. enable (negotiated, connection_config . stream_config);
+		}
+	}
+
+	if let Some (on_upgrade) = &connection_config . on_upgrade
+	{
+		on_upgrade (&response)?;
+	}
+
+	Ok (stream)
+}
+
+enum Attempt
+{
+	Connected (Box <ClientStream>, Uri),
+	Failed (Failure),
+	Shutdown
+}
+
+async fn attempt_connection <R>
+(
+	connection_config: &ConnectionConfig <R>,
+	endpoint: usize,
+	shutdown: &mut Receiver <()>
+)
+-> Attempt
+where R: Clone + IntoClientRequest + Unpin
+{
+	let request = match connection_config
+		. endpoint_request (endpoint)
+		. clone ()
+		. into_client_request ()
+	{
+		Ok (request) => request,
+		Err (request_error) =>
+		{
+			event!
+			(
+				Level::ERROR,
+				endpoint,
+				%request_error,
+				"invalid websocket request"
+			);
+
+			return Attempt::Failed
+			(
+				Failure::from_error (FailureKind::Connection, request_error)
+			);
+		}
+	};
+
+	let uri = request . uri () . clone ();
+	let span = info_span! ("websocket_connect", endpoint, uri = %uri);
+
+	async move
+	{
+		// Shutting down mustn't wait for an attempt that is stuck, e.g. on a
+		// server that never answers the handshake and no handshake timeout.
+		let connect_result = tokio::select!
 		{
 			biased;
-			_ = &mut *shutdown => return None,
-			_ = sleep (sleep_duration) => {}
+			_ = &mut *shutdown => return Attempt::Shutdown,
+			connect_result = connect (connection_config, request) =>
+				connect_result
+		};
+
+		let mut stream = match connect_result
+		{
+			Ok (stream) => stream,
+			Err (failure) =>
+			{
+				event!
+				(
+					Level::ERROR,
+					%failure,
+					"failed to establish websocket connection"
+				);
+
+				return Attempt::Failed (failure);
+			}
+		};
+
+		let on_connect_result = match &connection_config . on_connect
+		{
+			None => Ok (()),
+			Some (on_connect) => tokio::select!
+			{
+				biased;
+				_ = &mut *shutdown => return Attempt::Shutdown,
+				result = on_connect . on_connect (&mut stream) => result
+			}
+		};
+
+		match on_connect_result
+		{
+			Ok (()) => Attempt::Connected (Box::new (stream), uri),
+			Err (failure) =>
+			{
+				event!
+				(
+					Level::ERROR,
+					%failure,
+					"websocket on-connect hook failed"
+				);
+
+				Attempt::Failed (failure)
+			}
 		}
 	}
+		. instrument (span)
+		. await
 }
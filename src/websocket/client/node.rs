@@ -6,12 +6,18 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
-use crate::websocket::connection::websocket_node;
+use crate::websocket::connection::{websocket_node, websocket_node_with_outbox};
 use crate::websocket::io_format::{InputFormat, OutputFormat};
 
-use super::{ConnectionConfig, connect_with_retry};
+use super::{
+	ConnectionConfig,
+	Outbox,
+	OutboxConfig,
+	ReconnectState,
+	connect_with_retry
+};
 
 use crate as compute_graph;
 
@@ -20,6 +26,8 @@ pub struct WebSocketClientNode <IF, OF, R, IS, OS>
 	input_format: IF,
 	output_format: OF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
+	outbox: Option <Outbox>,
 	input: IS,
 	output: OS,
 	_if: PhantomData <IF>,
@@ -42,13 +50,26 @@ impl <IF, OF, R, IS, OS> WebSocketClientNode <IF, OF, R, IS, OS>
 		{
 			input_format,
 			output_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
+			outbox: None,
 			input,
 			output,
 			_if: PhantomData::default (),
 			_of: PhantomData::default ()
 		}
 	}
+
+	// Keeps messages pulled from the input across reconnects, replaying the
+	// ones that never made it out once the connection is back.
+	pub fn with_outbox (mut self, outbox_config: OutboxConfig) -> Self
+	{
+		self . outbox = Some (Outbox::new (outbox_config));
+		self
+	}
 }
 
 impl <IF, OF, R, IS, OS> SignallableFallibleServiceFactory
@@ -65,21 +86,67 @@ where
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
-			. await
-			. map
-		(
-			|websocket_stream|
-			websocket_node
+		let Some (outbox) = &self . outbox
+		else
+		{
+			return connect_with_retry
 			(
-				self . input_format . clone (),
-				self . output_format . clone (),
-				self . input . clone (),
-				self . output . clone (),
-				websocket_stream
+				&self . connection_config,
+				&mut self . reconnect_state,
+				&mut shutdown
 			)
+				. await
+				. map
+			(
+				|websocket_stream|
+				self . reconnect_state . watch_close
+				(
+					websocket_node
+					(
+						self . input_format . clone (),
+						self . output_format . clone (),
+						self . input . clone (),
+						self . output . clone (),
+						websocket_stream
+					)
+				)
+			);
+		};
+
+		outbox . prepare_replay ();
+
+		// Input that arrives while we're reconnecting goes into the outbox, so
+		// that the overflow policy applies to it.
+		let construction = tokio::select!
+		{
+			construction = connect_with_retry
+			(
+				&self . connection_config,
+				&mut self . reconnect_state,
+				&mut shutdown
+			) => construction,
+			_ = outbox . fill::<IF, _> (&mut self . input) =>
+				unreachable! ("outbox fill completed")
+		};
+
+		construction . map
+		(
+			|websocket_stream|
+			{
+				let node_handle = websocket_node_with_outbox
+				(
+					self . input_format . clone (),
+					self . output_format . clone (),
+					self . input . clone (),
+					outbox . clone (),
+					self . output . clone (),
+					websocket_stream
+				);
+
+				self . reconnect_state . watch_close (node_handle)
+			}
 		)
 	}
 }
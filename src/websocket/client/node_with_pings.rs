@@ -6,23 +6,25 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
-use crate::websocket::connection::websocket_node_with_pings;
+use crate::websocket::{Heartbeat, PingPong};
+use crate::websocket::connection::websocket_node_with_heartbeat;
 use crate::websocket::io_format::{InputFormat, OutputFormat};
 
-use super::{ConnectionConfig, PingConfig, connect_with_retry};
+use super::{ConnectionConfig, ReconnectState, PingConfig, connect_with_retry};
 
 use crate as compute_graph;
 
-pub struct WebSocketClientNodeWithPings <IF, OF, R, IS, OS>
+pub struct WebSocketClientNodeWithPings <IF, OF, R, IS, OS, H = PingPong>
 {
 	input_format: IF,
 	output_format: OF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
 	input: IS,
 	output: OS,
-	ping_config: PingConfig,
+	heartbeat: H,
 	_if: PhantomData <IF>,
 	_of: PhantomData <OF>
 }
@@ -39,23 +41,52 @@ impl <IF, OF, R, IS, OS> WebSocketClientNodeWithPings <IF, OF, R, IS, OS>
 		ping_config: PingConfig
 	)
 	-> Self
+	{
+		Self::with_heartbeat
+		(
+			input_format,
+			output_format,
+			connection_config,
+			input,
+			output,
+			PingPong::from (ping_config)
+		)
+	}
+}
+
+impl <IF, OF, R, IS, OS, H> WebSocketClientNodeWithPings <IF, OF, R, IS, OS, H>
+{
+	pub fn with_heartbeat
+	(
+		input_format: IF,
+		output_format: OF,
+		connection_config: ConnectionConfig <R>,
+		input: IS,
+		output: OS,
+		heartbeat: H
+	)
+	-> Self
 	{
 		Self
 		{
 			input_format,
 			output_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
 			input,
 			output,
-			ping_config,
+			heartbeat,
 			_if: PhantomData::default (),
 			_of: PhantomData::default ()
 		}
 	}
 }
 
-impl <IF, OF, R, IS, OS> SignallableFallibleServiceFactory
-for WebSocketClientNodeWithPings <IF, OF, R, IS, OS>
+impl <IF, OF, R, IS, OS, H> SignallableFallibleServiceFactory
+for WebSocketClientNodeWithPings <IF, OF, R, IS, OS, H>
 where
 	IF: Clone + InputFormat + Send + 'static,
 	OF: Clone + OutputFormat + Send + 'static,
@@ -64,26 +95,34 @@ where
 	IS::Item: Into <IF::Intermediate> + Send,
 	OS: Clone + SinkExt <OF::External> + Unpin + Debug + Send + 'static,
 	OF::External: Send,
-	OS::Error: Display
+	OS::Error: Display,
+	H: Heartbeat
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
+		connect_with_retry
+		(
+			&self . connection_config,
+			&mut self . reconnect_state,
+			&mut shutdown
+		)
 			. await
 			. map
 		(
 			|websocket_stream|
-			websocket_node_with_pings
+			self . reconnect_state . watch_close
 			(
-				self . input_format . clone (),
-				self . output_format . clone (),
-				self . input . clone (),
-				self . output . clone (),
-				websocket_stream,
-				self . ping_config . ping_interval,
-				self . ping_config . ping_timeout
+				websocket_node_with_heartbeat
+				(
+					self . input_format . clone (),
+					self . output_format . clone (),
+					self . input . clone (),
+					self . output . clone (),
+					websocket_stream,
+					self . heartbeat . clone ()
+				)
 			)
 		)
 	}
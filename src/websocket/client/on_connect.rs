@@ -0,0 +1,64 @@
+use futures::SinkExt;
+use futures::future::BoxFuture;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{WebSocketStream, MaybeTlsStream};
+use tungstenite::Message;
+
+use crate::exit_status::{Failure, FailureKind};
+use crate::websocket::DeflateStream;
+
+pub type ClientStream =
+	WebSocketStream <DeflateStream <MaybeTlsStream <TcpStream>>>;
+
+// Runs on every fresh connection before any of the client's own traffic, to
+// authenticate and subscribe.  An error fails the connection attempt, which is
+// then retried like any other.
+pub trait OnConnect: Send + Sync
+{
+	fn on_connect <'a> (&'a self, websocket: &'a mut ClientStream)
+	-> BoxFuture <'a, Result <(), Failure>>;
+}
+
+// Sends the messages in order.
+impl OnConnect for Vec <Message>
+{
+	fn on_connect <'a> (&'a self, websocket: &'a mut ClientStream)
+	-> BoxFuture <'a, Result <(), Failure>>
+	{
+		Box::pin
+		(
+			async move
+			{
+				for message in self
+				{
+					websocket
+						. feed (message . clone ())
+						. await
+						. map_err (connection_failure)?;
+				}
+
+				websocket . flush () . await . map_err (connection_failure)
+			}
+		)
+	}
+}
+
+pub (in crate::websocket::client) struct OnConnectFn <F> (pub F);
+
+impl <F> OnConnect for OnConnectFn <F>
+where
+	F: for <'a> Fn (&'a mut ClientStream) -> BoxFuture <'a, Result <(), Failure>>
+		+ Send
+		+ Sync
+{
+	fn on_connect <'a> (&'a self, websocket: &'a mut ClientStream)
+	-> BoxFuture <'a, Result <(), Failure>>
+	{
+		(self . 0) (websocket)
+	}
+}
+
+fn connection_failure (ws_error: tungstenite::Error) -> Failure
+{
+	Failure::from_error (FailureKind::Connection, ws_error)
+}
@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::future::pending;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use tokio::sync::Notify;
+use tracing::{Level, event};
+use tungstenite::Message;
+
+use crate::websocket::io_format::InputFormat;
+
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum OverflowPolicy
+{
+	DropOldest,
+	DropNewest,
+	// Stops pulling from the input stream until there is room again.
+	Block
+}
+
+pub type ReplayFilter = Arc <dyn Fn (&Message) -> bool + Send + Sync>;
+
+#[derive (Clone)]
+pub struct OutboxConfig
+{
+	// Counts the messages being sent, so a capacity of 0 is taken as 1: no
+	// buffering beyond the message in flight.
+	pub capacity: usize,
+	pub overflow_policy: OverflowPolicy,
+	// Decides which of the messages left over from a dropped connection are
+	// still worth sending on the next one.  Everything is replayed by default.
+	pub replay_filter: Option <ReplayFilter>
+}
+
+impl OutboxConfig
+{
+	pub fn new (capacity: usize) -> Self
+	{
+		Self
+		{
+			capacity,
+			overflow_policy: OverflowPolicy::Block,
+			replay_filter: None
+		}
+	}
+
+	pub fn with_overflow_policy (mut self, overflow_policy: OverflowPolicy)
+	-> Self
+	{
+		self . overflow_policy = overflow_policy;
+		self
+	}
+
+	pub fn with_replay_filter <F> (mut self, replay_filter: F) -> Self
+	where F: Fn (&Message) -> bool + Send + Sync + 'static
+	{
+		self . replay_filter = Some (Arc::new (replay_filter));
+		self
+	}
+}
+
+struct OutboxState
+{
+	messages: VecDeque <Message>,
+	// Messages taken out by a connection that hasn't finished flushing them.
+	in_flight: usize,
+	// Connections currently draining the outbox.
+	senders: usize
+}
+
+impl OutboxState
+{
+	fn len (&self) -> usize
+	{
+		self . messages . len () + self . in_flight
+	}
+}
+
+// Messages pulled from the input stream but not yet flushed to a connection.
+// They're only removed once a flush succeeds, so whatever a dropped connection
+// was holding is still here for the next one.  Preemptive replacement means
+// two connections may share the outbox for a while.
+#[derive (Clone)]
+pub (in crate::websocket) struct Outbox
+{
+	config: Arc <OutboxConfig>,
+	state: Arc <Mutex <OutboxState>>,
+	changed: Arc <Notify>
+}
+
+impl Outbox
+{
+	pub (in crate::websocket) fn new (config: OutboxConfig) -> Self
+	{
+		let config = OutboxConfig
+		{
+			capacity: config . capacity . max (1),
+			.. config
+		};
+
+		let state = OutboxState
+		{
+			messages: VecDeque::with_capacity (config . capacity),
+			in_flight: 0,
+			senders: 0
+		};
+
+		Self
+		{
+			config: Arc::new (config),
+			state: Arc::new (Mutex::new (state)),
+			changed: Arc::new (Notify::new ())
+		}
+	}
+
+	fn lock_state (&self) -> MutexGuard <'_, OutboxState>
+	{
+		self . state . lock () . unwrap_or_else (PoisonError::into_inner)
+	}
+
+	pub (in crate::websocket) fn is_empty (&self) -> bool
+	{
+		self . lock_state () . messages . is_empty ()
+	}
+
+	fn has_room (&self, state: &OutboxState) -> bool
+	{
+		self . config . overflow_policy != OverflowPolicy::Block
+			|| state . len () < self . config . capacity
+	}
+
+	// Hands the message back if the outbox is full and blocks.
+	pub (in crate::websocket) fn try_push (&self, message: Message)
+	-> Result <(), Message>
+	{
+		let mut state = self . lock_state ();
+
+		if state . len () >= self . config . capacity
+		{
+			match self . config . overflow_policy
+			{
+				// If everything is in flight, the newest message has to go
+				// instead.
+				OverflowPolicy::DropOldest if ! state . messages . is_empty () =>
+				{
+					state . messages . pop_front ();
+				},
+				OverflowPolicy::DropOldest | OverflowPolicy::DropNewest =>
+				{
+					event!
+					(
+						Level::WARN,
+						"websocket outbox is full, dropping message"
+					);
+
+					return Ok (());
+				},
+				OverflowPolicy::Block => return Err (message)
+			}
+		}
+
+		state . messages . push_back (message);
+
+		Ok (())
+	}
+
+	async fn push (&self, mut message: Message)
+	{
+		loop
+		{
+			let changed = self . changed . notified ();
+
+			match self . try_push (message)
+			{
+				Ok (()) => return,
+				Err (rejected_message) => message = rejected_message
+			}
+
+			changed . await;
+		}
+	}
+
+	pub (in crate::websocket) async fn wait_for_room (&self)
+	{
+		loop
+		{
+			let changed = self . changed . notified ();
+
+			if self . has_room (&self . lock_state ())
+			{
+				return;
+			}
+
+			changed . await;
+		}
+	}
+
+	// Called with whatever a dropped connection left behind, before the next
+	// connection is established.  Messages that a live connection is still
+	// sending are left alone.
+	pub (in crate::websocket) fn prepare_replay (&self)
+	{
+		let Some (replay_filter) = &self . config . replay_filter
+		else
+		{
+			return;
+		};
+
+		let mut state = self . lock_state ();
+
+		if state . senders > 0
+		{
+			return;
+		}
+
+		let unsent = state . messages . len ();
+
+		state . messages . retain (|message| replay_filter (message));
+
+		if state . messages . len () < unsent
+		{
+			event!
+			(
+				Level::INFO,
+				discarded = unsent - state . messages . len (),
+				"discarded stale websocket messages before replay"
+			);
+		}
+	}
+
+	// Keeps the outbox topped up while there is no connection to drain it.  A
+	// live connection pulls its own input.  Never completes.
+	pub (in crate::websocket) async fn fill <F, IS> (&self, inputs: &mut IS)
+	where
+		F: InputFormat,
+		IS: Stream + Unpin,
+		IS::Item: Into <F::Intermediate>
+	{
+		loop
+		{
+			let changed = self . changed . notified ();
+
+			let can_fill =
+			{
+				let state = self . lock_state ();
+				state . senders == 0 && self . has_room (&state)
+			};
+
+			if ! can_fill
+			{
+				changed . await;
+				continue;
+			}
+
+			match inputs . next () . await
+			{
+				Some (input) =>
+				{
+					if let Some (message) = F::convert (input . into ())
+					{
+						self . push (message) . await;
+					}
+				},
+				None => pending () . await
+			}
+		}
+	}
+
+	// Registers a connection that drains the outbox until the guard is
+	// dropped.
+	pub (in crate::websocket) fn register_sender (&self) -> SenderGuard
+	{
+		self . lock_state () . senders += 1;
+		SenderGuard (self . clone ())
+	}
+
+	// Sends everything currently queued.  The messages are taken out while
+	// they're in flight, so that no other connection sends them too, and go
+	// back to the front if the flush doesn't go through.
+	pub (in crate::websocket) async fn send_queued <WS>
+	(
+		&self,
+		websocket: &mut WS
+	)
+	-> Result <(), WS::Error>
+	where WS: Sink <Message> + Unpin
+	{
+		let mut in_flight = InFlight::take (self);
+
+		for message in in_flight . messages . iter () . cloned ()
+		{
+			websocket . feed (message) . await?;
+		}
+
+		websocket . flush () . await?;
+
+		in_flight . messages . clear ();
+
+		Ok (())
+	}
+}
+
+pub (in crate::websocket) struct SenderGuard (Outbox);
+
+impl Drop for SenderGuard
+{
+	fn drop (&mut self)
+	{
+		self . 0 . lock_state () . senders -= 1;
+		self . 0 . changed . notify_waiters ();
+	}
+}
+
+// Puts back whatever wasn't sent when dropped, including when the send is
+// cancelled.
+struct InFlight <'a>
+{
+	outbox: &'a Outbox,
+	taken: usize,
+	messages: Vec <Message>
+}
+
+impl <'a> InFlight <'a>
+{
+	fn take (outbox: &'a Outbox) -> Self
+	{
+		let mut state = outbox . lock_state ();
+		let messages: Vec <Message> =
+			state . messages . drain (..) . collect ();
+
+		state . in_flight += messages . len ();
+
+		Self {outbox, taken: messages . len (), messages}
+	}
+}
+
+impl Drop for InFlight <'_>
+{
+	fn drop (&mut self)
+	{
+		{
+			let mut state = self . outbox . lock_state ();
+
+			state . in_flight -= self . taken;
+
+			for message in self . messages . drain (..) . rev ()
+			{
+				state . messages . push_front (message);
+			}
+		}
+
+		self . outbox . changed . notify_waiters ();
+	}
+}
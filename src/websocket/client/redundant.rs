@@ -0,0 +1,612 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::iter::once;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use futures::{SinkExt, Stream, StreamExt};
+use futures::future::join_all;
+use futures::stream::{BoxStream, select_all};
+use tokio::sync::{Notify, watch};
+use tokio_stream::wrappers::WatchStream;
+use tracing::{Level, event};
+use tungstenite::client::IntoClientRequest;
+
+use crate::{service, task, check_break, send};
+use crate::exit_status::{ExitStatus, FailureKind};
+use crate::robust_service::{
+	Construction,
+	SignallableFallibleServiceFactory,
+	SignallableRobustService
+};
+use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
+use crate::service_state::{ServiceState, ServiceStatus};
+use crate::websocket::ConnectionStats;
+use crate::websocket::io_format::{InputFormat, OutputFormat};
+
+use super::{
+	ActiveEndpoint,
+	ConnectionConfig,
+	EndpointSelection,
+	QuarantinePolicy,
+	WebSocketClientNode
+};
+
+use crate as compute_graph;
+
+// Keeps a connection open to the primary request and to every standby request
+// in the connection config at once.  Their outputs are merged, dropping any
+// output whose key was already seen among the last dedup_window outputs, and
+// input goes to whichever connection has been up the longest.  Endpoint and
+// stats reporting follow that connection, with endpoint indices counting the
+// standby requests rather than the failover requests, which don't apply.
+pub struct WebSocketRedundantClientNode <IF, OF, R, IS, OS, D>
+{
+	input_format: IF,
+	output_format: OF,
+	connection_config: ConnectionConfig <R>,
+	input: IS,
+	output: OS,
+	dedup_key: Arc <D>,
+	dedup_window: usize
+}
+
+impl <IF, OF, R, IS, OS, D> WebSocketRedundantClientNode <IF, OF, R, IS, OS, D>
+{
+	pub fn new
+	(
+		input_format: IF,
+		output_format: OF,
+		connection_config: ConnectionConfig <R>,
+		input: IS,
+		output: OS,
+		dedup_key: D
+	)
+	-> Self
+	{
+		Self
+		{
+			input_format,
+			output_format,
+			connection_config,
+			input,
+			output,
+			dedup_key: Arc::new (dedup_key),
+			dedup_window: 1024
+		}
+	}
+
+	pub fn with_dedup_window (mut self, dedup_window: usize) -> Self
+	{
+		self . dedup_window = dedup_window;
+		self
+	}
+}
+
+impl <IF, OF, R, IS, OS, D, K>
+WebSocketRedundantClientNode <IF, OF, R, IS, OS, D>
+where
+	IF: Clone + InputFormat + Send + 'static,
+	OF: Clone + OutputFormat + Send + 'static,
+	R: Clone + IntoClientRequest + Unpin + Send + Sync + 'static,
+	IS: Clone + StreamExt + Unpin + Send + 'static,
+	IS::Item: Into <IF::Intermediate> + Send + 'static,
+	OS: Clone + SinkExt <OF::External> + Unpin + Send + 'static,
+	OF::External: Debug + Send + 'static,
+	OS::Error: Display,
+	D: Fn (&OF::External) -> K + Send + Sync + 'static,
+	K: Clone + Hash + Eq + Send + 'static
+{
+	// Each connection gets its own reporting channels, so that they don't
+	// overwrite each other's endpoints and stats.
+	fn leg_config
+	(
+		&self,
+		request: &R,
+		endpoint_sender: watch::Sender <Option <ActiveEndpoint>>,
+		stats_sender: watch::Sender <ConnectionStats>
+	)
+	-> ConnectionConfig <R>
+	{
+		let connection_config = &self . connection_config;
+
+		ConnectionConfig
+		{
+			request: request . clone (),
+			request_weight: 1,
+			failover_requests: Vec::new (),
+			endpoint_selection: EndpointSelection::Priority,
+			quarantine_policy: QuarantinePolicy::default (),
+			endpoint_sender: Some (endpoint_sender),
+			stats_sender: Some (stats_sender),
+			standby_requests: Vec::new (),
+			stream_config: connection_config . stream_config,
+			deflate: connection_config . deflate,
+			disable_nagle: connection_config . disable_nagle,
+			connector: connection_config . connector . clone (),
+			proxy: connection_config . proxy . clone (),
+			proxy_from_env: connection_config . proxy_from_env,
+			resolver: connection_config . resolver . clone (),
+			resolve_timeout: connection_config . resolve_timeout,
+			connect_timeout: connection_config . connect_timeout,
+			handshake_timeout: connection_config . handshake_timeout,
+			connection_attempt_delay:
+				connection_config . connection_attempt_delay,
+			reconnect_policy: connection_config . reconnect_policy . clone (),
+			on_upgrade: connection_config . on_upgrade . clone (),
+			on_connect: connection_config . on_connect . clone ()
+		}
+	}
+
+	pub fn start (&self) -> SignallableServiceHandle <ExitStatus>
+	{
+		let requests = once (&self . connection_config . request)
+			. chain (&self . connection_config . standby_requests);
+
+		let (leg_output_sink, leg_outputs) = crate::stream::mpsc
+		(
+			1 + self . connection_config . standby_requests . len ()
+		);
+
+		let mut legs = Vec::new ();
+
+		for request in requests
+		{
+			let handoff = Handoff::new ();
+			let (status_sender, status) =
+				watch::channel (ServiceStatus::default ());
+			let (endpoint_sender, endpoint) = watch::channel (None);
+			let (stats_sender, stats) =
+				watch::channel (ConnectionStats::default ());
+
+			let handle = WebSocketClientNode::new
+			(
+				self . input_format . clone (),
+				self . output_format . clone (),
+				self . leg_config (request, endpoint_sender, stats_sender),
+				handoff . clone (),
+				leg_output_sink . clone ()
+			)
+				. into_robust_service_with_status_reporting (status_sender);
+
+			legs . push (Leg {handoff, handle, status, endpoint, stats});
+		}
+
+		arbitrate
+		(
+			self . input . clone (),
+			legs,
+			leg_outputs,
+			self . output . clone (),
+			Reporting
+			{
+				endpoint_sender:
+					self . connection_config . endpoint_sender . clone (),
+				stats_sender: self . connection_config . stats_sender . clone ()
+			},
+			self . dedup_key . clone (),
+			self . dedup_window
+		)
+	}
+}
+
+impl <IF, OF, R, IS, OS, D, K> SignallableFallibleServiceFactory
+for WebSocketRedundantClientNode <IF, OF, R, IS, OS, D>
+where
+	IF: Clone + InputFormat + Send + 'static,
+	OF: Clone + OutputFormat + Send + 'static,
+	R: Clone + IntoClientRequest + Unpin + Send + Sync + 'static,
+	IS: Clone + StreamExt + Unpin + Send + 'static,
+	IS::Item: Into <IF::Intermediate> + Send + 'static,
+	OS: Clone + SinkExt <OF::External> + Unpin + Send + 'static,
+	OF::External: Debug + Send + 'static,
+	OS::Error: Display,
+	D: Fn (&OF::External) -> K + Send + Sync + 'static,
+	K: Clone + Hash + Eq + Send + 'static
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <SignallableServiceHandle <ExitStatus>>
+	{
+		Construction::Built (self . start ())
+	}
+}
+
+struct DedupWindow <K>
+{
+	seen: HashSet <K>,
+	// Oldest first.
+	order: VecDeque <K>,
+	capacity: usize
+}
+
+impl <K> DedupWindow <K>
+where K: Clone + Hash + Eq
+{
+	fn new (capacity: usize) -> Self
+	{
+		Self {seen: HashSet::new (), order: VecDeque::new (), capacity}
+	}
+
+	fn first_sighting (&mut self, key: K) -> bool
+	{
+		if self . seen . contains (&key)
+		{
+			return false;
+		}
+
+		if self . order . len () >= self . capacity
+		{
+			if let Some (forgotten) = self . order . pop_front ()
+			{
+				self . seen . remove (&forgotten);
+			}
+		}
+
+		self . seen . insert (key . clone ());
+		self . order . push_back (key);
+
+		true
+	}
+}
+
+struct HandoffState <T>
+{
+	input: Option <T>,
+	waker: Option <Waker>
+}
+
+// Passes inputs to one connection at a time.  Unlike a channel, an input the
+// connection hasn't picked up yet can be taken back if it goes down.
+struct Handoff <T>
+{
+	state: Arc <Mutex <HandoffState <T>>>,
+	taken: Arc <Notify>
+}
+
+impl <T> Handoff <T>
+{
+	fn new () -> Self
+	{
+		Self
+		{
+			state: Arc::new
+			(
+				Mutex::new (HandoffState {input: None, waker: None})
+			),
+			taken: Arc::new (Notify::new ())
+		}
+	}
+
+	fn lock_state (&self) -> MutexGuard <'_, HandoffState <T>>
+	{
+		self . state . lock () . unwrap_or_else (PoisonError::into_inner)
+	}
+
+	async fn wait_until_empty (&self)
+	{
+		loop
+		{
+			let taken = self . taken . notified ();
+
+			if self . lock_state () . input . is_none ()
+			{
+				return;
+			}
+
+			taken . await;
+		}
+	}
+
+	// Only called once the handoff is empty.
+	fn give (&self, input: T)
+	{
+		let mut state = self . lock_state ();
+
+		state . input = Some (input);
+
+		if let Some (waker) = state . waker . take ()
+		{
+			waker . wake ();
+		}
+	}
+
+	fn take_back (&self) -> Option <T>
+	{
+		self . lock_state () . input . take ()
+	}
+}
+
+impl <T> Clone for Handoff <T>
+{
+	fn clone (&self) -> Self
+	{
+		Self {state: self . state . clone (), taken: self . taken . clone ()}
+	}
+}
+
+impl <T> Debug for Handoff <T>
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		f . debug_struct ("Handoff") . finish_non_exhaustive ()
+	}
+}
+
+// Never ends, as the connections are shut down along with the arbiter.
+impl <T> Stream for Handoff <T>
+{
+	type Item = T;
+
+	fn poll_next (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Option <T>>
+	{
+		let mut state = self . lock_state ();
+
+		match state . input . take ()
+		{
+			Some (input) =>
+			{
+				drop (state);
+				self . taken . notify_waiters ();
+				Poll::Ready (Some (input))
+			},
+			None =>
+			{
+				state . waker = Some (cx . waker () . clone ());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+struct Leg <T>
+{
+	handoff: Handoff <T>,
+	handle: SignallableServiceHandle <ExitStatus>,
+	status: watch::Receiver <ServiceStatus>,
+	endpoint: watch::Receiver <Option <ActiveEndpoint>>,
+	stats: watch::Receiver <ConnectionStats>
+}
+
+enum LegEvent
+{
+	Status (ServiceStatus),
+	// The connection's endpoint or stats changed.
+	Report
+}
+
+fn leg_events <T> (legs: &[Leg <T>])
+-> Vec <BoxStream <'static, (usize, LegEvent)>>
+{
+	let mut events = Vec::new ();
+
+	for (leg_index, leg) in legs . iter () . enumerate ()
+	{
+		events . push
+		(
+			WatchStream::new (leg . status . clone ())
+				. map (move |status| (leg_index, LegEvent::Status (status)))
+				. boxed ()
+		);
+
+		events . push
+		(
+			WatchStream::new (leg . endpoint . clone ())
+				. map (move |_| (leg_index, LegEvent::Report))
+				. boxed ()
+		);
+
+		events . push
+		(
+			WatchStream::new (leg . stats . clone ())
+				. map (move |_| (leg_index, LegEvent::Report))
+				. boxed ()
+		);
+	}
+
+	events
+}
+
+// Where the redundant client reports the endpoint and stats of the connection
+// it routes input to.
+struct Reporting
+{
+	endpoint_sender: Option <watch::Sender <Option <ActiveEndpoint>>>,
+	stats_sender: Option <watch::Sender <ConnectionStats>>
+}
+
+impl Reporting
+{
+	fn report <T> (&self, legs: &[Leg <T>], route: Option <usize>)
+	{
+		if let Some (endpoint_sender) = &self . endpoint_sender
+		{
+			let endpoint = route . and_then
+			(
+				|leg_index|
+				legs [leg_index] . endpoint . borrow () . clone () . map
+				(
+					|endpoint| ActiveEndpoint {index: leg_index, .. endpoint}
+				)
+			);
+
+			endpoint_sender . send_if_modified
+			(
+				|current| match *current == endpoint
+				{
+					true => false,
+					false =>
+					{
+						*current = endpoint;
+						true
+					}
+				}
+			);
+		}
+
+		if let (Some (stats_sender), Some (leg_index)) =
+			(&self . stats_sender, route)
+		{
+			stats_sender . send_replace (*legs [leg_index] . stats . borrow ());
+		}
+	}
+}
+
+// The connection that has been up the longest, if any is up.
+fn healthiest (statuses: &[ServiceStatus]) -> Option <usize>
+{
+	statuses
+		. iter ()
+		. enumerate ()
+		. filter (|(_, status)| status . is_up ())
+		. min_by_key (|(_, status)| status . since)
+		. map (|(leg, _)| leg)
+}
+
+async fn wait_for_handoff <T> (legs: &[Leg <T>], route: Option <usize>)
+{
+	match route
+	{
+		Some (leg) => legs [leg] . handoff . wait_until_empty () . await,
+		None => std::future::pending () . await
+	}
+}
+
+#[service (shutdown = shutdown)]
+async fn arbitrate <IS, OS, LS, T, D, K>
+(
+	mut inputs: IS,
+	mut legs: Vec <Leg <IS::Item>>,
+	mut leg_outputs: LS,
+	mut outputs: OS,
+	reporting: Reporting,
+	dedup_key: Arc <D>,
+	dedup_window: usize
+)
+-> ExitStatus
+where
+	IS: Stream + Unpin,
+	IS::Item: Send,
+	OS: SinkExt <T> + Unpin,
+	OS::Error: Display,
+	LS: Stream <Item = T> + Unpin,
+	T: Send,
+	D: Fn (&T) -> K,
+	K: Clone + Hash + Eq + Send
+{
+	let mut statuses = vec! [ServiceStatus::default (); legs . len ()];
+	let mut leg_events = select_all (leg_events (&legs));
+
+	let mut route = None;
+	// Inputs waiting for a connection to take them, oldest first.
+	let mut unrouted = VecDeque::new ();
+	let mut dedup = DedupWindow::new (dedup_window);
+
+	let exit_status = loop
+	{
+		tokio::select!
+		{
+			biased;
+			_ = &mut shutdown => break ExitStatus::Clean,
+			Some ((leg, leg_event)) = leg_events . next () =>
+			{
+				let status = match leg_event
+				{
+					LegEvent::Status (status) => status,
+					LegEvent::Report =>
+					{
+						if Some (leg) == route
+						{
+							reporting . report (&legs, route);
+						}
+
+						continue;
+					}
+				};
+
+				// Whatever a connection that went down hadn't picked up yet
+				// goes to another one.
+				if statuses [leg] . is_up () && ! status . is_up ()
+				{
+					if let Some (input) = legs [leg] . handoff . take_back ()
+					{
+						unrouted . push_front (input);
+					}
+				}
+
+				statuses [leg] = status;
+
+				let failed = statuses
+					. iter ()
+					. all (|status| status . state == ServiceState::Failed);
+
+				if failed
+				{
+					break ExitStatus::spurious
+					(
+						FailureKind::Connection,
+						"every redundant connection failed"
+					);
+				}
+
+				let new_route = healthiest (&statuses);
+
+				if new_route != route
+				{
+					event!
+					(
+						Level::INFO,
+						leg = ?new_route,
+						"routing websocket input to another connection"
+					);
+
+					route = new_route;
+					reporting . report (&legs, route);
+				}
+			},
+			Some (output) = leg_outputs . next () =>
+			{
+				if ! dedup . first_sighting (dedup_key (&output))
+				{
+					continue;
+				}
+
+				check_break!
+				(
+					send! (outputs, output) . map_break (|_| ExitStatus::Clean)
+				);
+			},
+			_ = wait_for_handoff (&legs, route), if ! unrouted . is_empty () =>
+			{
+				if let (Some (leg), Some (input)) =
+					(route, unrouted . pop_front ())
+				{
+					legs [leg] . handoff . give (input);
+				}
+			},
+			input = inputs . next (),
+				if route . is_some () && unrouted . is_empty () => match input
+			{
+				Some (input) => unrouted . push_back (input),
+				None => break ExitStatus::Clean
+			},
+			else => break ExitStatus::spurious
+			(
+				FailureKind::Connection,
+				"every redundant connection exited"
+			)
+		}
+	};
+
+	for leg in &mut legs
+	{
+		leg . handle . shutdown ();
+	}
+
+	join_all (legs . into_iter () . map (|leg| leg . handle)) . await;
+
+	exit_status
+}
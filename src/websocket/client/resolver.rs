@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, SocketAddr};
+
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time::{Duration, sleep, timeout};
+use tracing::{Level, event};
+
+pub trait Resolver: Send + Sync
+{
+	fn resolve <'a> (&'a self, host: &'a str, port: u16)
+	-> BoxFuture <'a, Result <Vec <SocketAddr>>>;
+}
+
+// Resolves hostnames through the operating system.
+#[derive (Copy, Clone, Default, Debug)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver
+{
+	fn resolve <'a> (&'a self, host: &'a str, port: u16)
+	-> BoxFuture <'a, Result <Vec <SocketAddr>>>
+	{
+		Box::pin
+		(
+			async move
+			{
+				Ok (lookup_host ((host, port)) . await? . collect ())
+			}
+		)
+	}
+}
+
+// Answers for the hosts it was given, and hands every other host to the
+// system resolver.
+#[derive (Clone, Default, Debug)]
+pub struct StaticResolver
+{
+	overrides: HashMap <String, Vec <IpAddr>>
+}
+
+impl StaticResolver
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn with_override <I> (mut self, host: impl Into <String>, addresses: I)
+	-> Self
+	where I: IntoIterator <Item = IpAddr>
+	{
+		self . overrides . insert
+		(
+			host . into (),
+			addresses . into_iter () . collect ()
+		);
+
+		self
+	}
+}
+
+impl Resolver for StaticResolver
+{
+	fn resolve <'a> (&'a self, host: &'a str, port: u16)
+	-> BoxFuture <'a, Result <Vec <SocketAddr>>>
+	{
+		match self . overrides . get (host)
+		{
+			Some (addresses) =>
+			{
+				let addresses = addresses
+					. iter ()
+					. map (|&address| SocketAddr::new (address, port))
+					. collect ();
+
+				Box::pin (async move {Ok (addresses)})
+			},
+			None => SystemResolver . resolve (host, port)
+		}
+	}
+}
+
+// Alternates between address families, starting with whichever family the
+// resolver listed first, as RFC 8305 suggests.
+fn interleave_families (addresses: Vec <SocketAddr>) -> Vec <SocketAddr>
+{
+	let Some (first) = addresses . first ()
+	else
+	{
+		return addresses;
+	};
+
+	let first_is_ipv6 = first . is_ipv6 ();
+	let (preferred, other): (Vec <_>, Vec <_>) = addresses
+		. into_iter ()
+		. partition (|address| address . is_ipv6 () == first_is_ipv6);
+
+	let mut interleaved =
+		Vec::with_capacity (preferred . len () + other . len ());
+	let mut preferred = preferred . into_iter ();
+	let mut other = other . into_iter ();
+
+	loop
+	{
+		match (preferred . next (), other . next ())
+		{
+			(None, None) => return interleaved,
+			(preferred, other) =>
+				interleaved . extend (preferred . into_iter () . chain (other))
+		}
+	}
+}
+
+async fn connect_address
+(
+	address: SocketAddr,
+	connect_timeout: Option <Duration>
+)
+-> Result <TcpStream>
+{
+	let Some (connect_timeout) = connect_timeout
+	else
+	{
+		return TcpStream::connect (address) . await;
+	};
+
+	timeout (connect_timeout, TcpStream::connect (address))
+		. await
+		. unwrap_or_else
+		(
+			|_| Err
+			(
+				Error::new
+				(
+					ErrorKind::TimedOut,
+					format! ("connecting to {} timed out", address)
+				)
+			)
+		)
+}
+
+// Starts a connection attempt to each address in turn, without waiting for
+// the previous attempt to fail for longer than attempt_delay, and keeps the
+// first connection to succeed.
+pub (in crate::websocket::client) async fn race_connections
+(
+	addresses: Vec <SocketAddr>,
+	attempt_delay: Duration,
+	connect_timeout: Option <Duration>
+)
+-> Result <TcpStream>
+{
+	let mut remaining = interleave_families (addresses) . into_iter ();
+	let mut attempts = FuturesUnordered::new ();
+	let mut last_error = None;
+
+	loop
+	{
+		if attempts . is_empty ()
+		{
+			match remaining . next ()
+			{
+				Some (address) => attempts
+					. push (connect_address (address, connect_timeout)),
+				None => return Err
+				(
+					last_error . unwrap_or_else
+					(
+						||
+						Error::new (ErrorKind::NotFound, "no addresses to try")
+					)
+				)
+			}
+		}
+
+		tokio::select!
+		{
+			Some (result) = attempts . next () => match result
+			{
+				Ok (tcp_stream) => return Ok (tcp_stream),
+				Err (connect_error) =>
+				{
+					event!
+					(
+						Level::DEBUG,
+						%connect_error,
+						"connection attempt failed"
+					);
+
+					last_error = Some (connect_error);
+
+					if let Some (address) = remaining . next ()
+					{
+						attempts
+							. push (connect_address (address, connect_timeout));
+					}
+				}
+			},
+			_ = sleep (attempt_delay), if remaining . len () > 0 =>
+			{
+				if let Some (address) = remaining . next ()
+				{
+					attempts
+						. push (connect_address (address, connect_timeout));
+				}
+			}
+		}
+	}
+}
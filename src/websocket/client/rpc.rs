@@ -0,0 +1,321 @@
+use std::fmt::Display;
+
+use futures::{SinkExt, Stream, StreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Level, event};
+use tungstenite::client::IntoClientRequest;
+
+use crate::{service, task, check_break, send};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::json::JSON;
+use crate::pending_calls::PendingCalls;
+use crate::robust_service::{
+	Construction,
+	SignallableFallibleServiceFactory,
+	SignallableRobustService
+};
+use crate::service_handle::{ServiceHandle, SignallableServiceHandle};
+use crate::service_state::ServiceStatus;
+use crate::stream::SharedStream;
+
+use super::{ConnectionConfig, WebSocketClientNode};
+
+use crate as compute_graph;
+
+// Makes calls through a WebSocketRpcClientNode.  Each request is sent as a JSON
+// object with a fresh id added to it, and the reply is the first message to
+// echo that id.
+#[derive (Clone)]
+pub struct RpcClient
+{
+	requests: mpsc::Sender <Value>,
+	pending_calls: PendingCalls,
+	id_field: String,
+	call_timeout: Duration
+}
+
+impl RpcClient
+{
+	pub async fn call <Q, P> (&self, request: Q) -> Result <P, Failure>
+	where
+		Q: Serialize,
+		P: DeserializeOwned
+	{
+		self . call_with_timeout (request, self . call_timeout) . await
+	}
+
+	pub async fn call_with_timeout <Q, P>
+	(
+		&self,
+		request: Q,
+		call_timeout: Duration
+	)
+	-> Result <P, Failure>
+	where
+		Q: Serialize,
+		P: DeserializeOwned
+	{
+		let mut request = serde_json::to_value (request) . map_err
+		(
+			|serde_error| Failure::from_error (FailureKind::Encode, serde_error)
+		)?;
+
+		let Value::Object (fields) = &mut request
+		else
+		{
+			return Err
+			(
+				Failure::new
+				(
+					FailureKind::Encode,
+					"rpc requests must be JSON objects"
+				)
+			);
+		};
+
+		let (id, reply_receiver) = self . pending_calls . register ();
+
+		fields . insert (self . id_field . clone (), id . into ());
+
+		let send = async
+		{
+			self
+				. requests
+				. send (request)
+				. await
+				. map_err (|_| Self::stopped ())
+		};
+
+		let reply = self
+			. pending_calls
+			. exchange
+			(
+				send,
+				vec! [(id, reply_receiver)],
+				call_timeout,
+				Self::stopped
+			)
+			. await?
+			. remove (0)?;
+
+		serde_json::from_value (reply) . map_err
+		(
+			|serde_error| Failure::from_error (FailureKind::Decode, serde_error)
+		)
+	}
+
+	fn stopped () -> Failure
+	{
+		Failure::new (FailureKind::Connection, "rpc client stopped")
+	}
+}
+
+// Sends requests from its RpcClient and matches up their replies.  Every other
+// message goes to the output, decoded as O.  Pending calls fail as soon as the
+// connection drops.
+pub struct WebSocketRpcClientNode <R, O, OS>
+{
+	connection_config: ConnectionConfig <R>,
+	requests: SharedStream <ReceiverStream <Value>>,
+	client: RpcClient,
+	output: OS,
+	decode: fn (Value) -> serde_json::Result <O>
+}
+
+impl <R, O, OS> WebSocketRpcClientNode <R, O, OS>
+where O: DeserializeOwned
+{
+	pub fn new (connection_config: ConnectionConfig <R>, output: OS) -> Self
+	{
+		let (request_sender, request_receiver) = mpsc::channel (64);
+		let requests =
+			SharedStream::new (ReceiverStream::new (request_receiver));
+
+		Self
+		{
+			connection_config,
+			requests,
+			client: RpcClient
+			{
+				requests: request_sender,
+				pending_calls: PendingCalls::new (),
+				id_field: "id" . to_string (),
+				call_timeout: Duration::from_secs (30)
+			},
+			output,
+			decode: serde_json::from_value
+		}
+	}
+
+	// Only affects clients taken afterwards.
+	pub fn with_call_timeout (mut self, call_timeout: Duration) -> Self
+	{
+		self . client . call_timeout = call_timeout;
+		self
+	}
+
+	// Only affects clients taken afterwards.
+	pub fn with_id_field (mut self, id_field: impl Into <String>) -> Self
+	{
+		self . client . id_field = id_field . into ();
+		self
+	}
+
+	pub fn client (&self) -> RpcClient
+	{
+		self . client . clone ()
+	}
+}
+
+impl <R, O, OS> WebSocketRpcClientNode <R, O, OS>
+where
+	R: Clone + IntoClientRequest + Unpin + Send + Sync + 'static,
+	O: Send + 'static,
+	OS: Clone + SinkExt <O> + Unpin + Send + 'static,
+	OS::Error: Display
+{
+	pub fn start (&self) -> SignallableServiceHandle <ExitStatus>
+	{
+		let (reply_sink, replies) = crate::stream::mpsc (1);
+		let (status_sender, node_status) =
+			watch::channel (ServiceStatus::default ());
+
+		let node_handle = WebSocketClientNode::new
+		(
+			JSON::<Value>::default (),
+			JSON::<Value>::default (),
+			self . connection_config . clone (),
+			self . requests . clone (),
+			reply_sink
+		)
+			. into_robust_service_with_status_reporting (status_sender);
+
+		dispatch
+		(
+			node_handle,
+			node_status,
+			replies,
+			self . output . clone (),
+			self . client . pending_calls . clone (),
+			self . client . id_field . clone (),
+			self . decode
+		)
+	}
+}
+
+impl <R, O, OS> SignallableFallibleServiceFactory
+for WebSocketRpcClientNode <R, O, OS>
+where
+	R: Clone + IntoClientRequest + Unpin + Send + Sync + 'static,
+	O: Send + 'static,
+	OS: Clone + SinkExt <O> + Unpin + Send + 'static,
+	OS::Error: Display
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <SignallableServiceHandle <ExitStatus>>
+	{
+		Construction::Built (self . start ())
+	}
+}
+
+#[service (shutdown = shutdown)]
+async fn dispatch <LS, OS, O>
+(
+	mut node_handle: SignallableServiceHandle <ExitStatus>,
+	mut node_status: watch::Receiver <ServiceStatus>,
+	mut replies: LS,
+	mut outputs: OS,
+	pending_calls: PendingCalls,
+	id_field: String,
+	decode: fn (Value) -> serde_json::Result <O>
+)
+-> ExitStatus
+where
+	LS: Stream <Item = Value> + Unpin,
+	OS: SinkExt <O> + Unpin,
+	OS::Error: Display,
+	O: Send
+{
+	// A restart can hide behind a single update, so a change in the restart
+	// count also means the calls in flight were lost.
+	let mut last_status = node_status . borrow () . clone ();
+
+	let exit_status = loop
+	{
+		tokio::select!
+		{
+			biased;
+			_ = &mut shutdown => break ExitStatus::Clean,
+			node_exit_status = node_handle . exit_status () =>
+				break node_exit_status . unwrap_or_default (),
+			Ok (()) = node_status . changed () =>
+			{
+				let status = node_status . borrow_and_update () . clone ();
+
+				let restarted =
+					status . restart_count != last_status . restart_count;
+
+				if last_status . is_up () && (restarted || ! status . is_up ())
+				{
+					pending_calls . fail_all
+					(
+						Failure::new
+						(
+							FailureKind::Connection,
+							"connection dropped"
+						)
+					);
+				}
+
+				last_status = status;
+			},
+			Some (message) = replies . next () =>
+			{
+				let reply_sender = message
+					. get (&id_field)
+					. and_then (Value::as_u64)
+					. and_then (|id| pending_calls . take (id));
+
+				if let Some (reply_sender) = reply_sender
+				{
+					let _ = reply_sender . send (Ok (message));
+					continue;
+				}
+
+				let output = match decode (message)
+				{
+					Ok (output) => output,
+					Err (serde_error) =>
+					{
+						event!
+						(
+							Level::ERROR,
+							error = %serde_error,
+							"failed to decode unsolicited message"
+						);
+
+						continue;
+					}
+				};
+
+				check_break!
+				(
+					send! (outputs, output) . map_break (|_| ExitStatus::Clean)
+				);
+			}
+		}
+	};
+
+	node_handle . shutdown ();
+	node_handle . await;
+
+	pending_calls . fail_all (RpcClient::stopped ());
+
+	exit_status
+}
@@ -6,12 +6,12 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
 use crate::websocket::connection::websocket_sink;
 use crate::websocket::io_format::InputFormat;
 
-use super::{ConnectionConfig, connect_with_retry};
+use super::{ConnectionConfig, ReconnectState, connect_with_retry};
 
 use crate as compute_graph;
 
@@ -19,6 +19,7 @@ pub struct WebSocketClientSink <IF, R, IS>
 {
 	input_format: IF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
 	input: IS,
 	_if: PhantomData <IF>
 }
@@ -36,6 +37,10 @@ impl <IF, R, IS> WebSocketClientSink <IF, R, IS>
 		Self
 		{
 			input_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
 			input,
 			_if: PhantomData::default ()
@@ -53,18 +58,26 @@ where
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
+		connect_with_retry
+		(
+			&self . connection_config,
+			&mut self . reconnect_state,
+			&mut shutdown
+		)
 			. await
 			. map
 		(
 			|websocket_stream|
-			websocket_sink
+			self . reconnect_state . watch_close
 			(
-				self . input_format . clone (),
-				self . input . clone (),
-				websocket_stream
+				websocket_sink
+				(
+					self . input_format . clone (),
+					self . input . clone (),
+					websocket_stream
+				)
 			)
 		)
 	}
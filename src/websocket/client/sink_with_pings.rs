@@ -6,12 +6,13 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
-use crate::websocket::connection::websocket_sink_with_pings;
+use crate::websocket::PingPong;
+use crate::websocket::connection::websocket_sink_with_heartbeat;
 use crate::websocket::io_format::InputFormat;
 
-use super::{ConnectionConfig, PingConfig, connect_with_retry};
+use super::{ConnectionConfig, ReconnectState, PingConfig, connect_with_retry};
 
 use crate as compute_graph;
 
@@ -19,6 +20,7 @@ pub struct WebSocketClientSinkWithPings <IF, R, IS>
 {
 	input_format: IF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
 	input: IS,
 	ping_config: PingConfig,
 	_if: PhantomData <IF>
@@ -38,6 +40,10 @@ impl <IF, R, IS> WebSocketClientSinkWithPings <IF, R, IS>
 		Self
 		{
 			input_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
 			input,
 			ping_config,
@@ -56,20 +62,27 @@ where
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
+		connect_with_retry
+		(
+			&self . connection_config,
+			&mut self . reconnect_state,
+			&mut shutdown
+		)
 			. await
 			. map
 		(
 			|websocket_stream|
-			websocket_sink_with_pings
+			self . reconnect_state . watch_close
 			(
-				self . input_format . clone (),
-				self . input . clone (),
-				websocket_stream,
-				self . ping_config . ping_interval,
-				self . ping_config . ping_timeout
+				websocket_sink_with_heartbeat
+				(
+					self . input_format . clone (),
+					self . input . clone (),
+					websocket_stream,
+					PingPong::from (self . ping_config)
+				)
 			)
 		)
 	}
@@ -6,12 +6,12 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
 use crate::websocket::connection::websocket_source;
 use crate::websocket::io_format::OutputFormat;
 
-use super::{ConnectionConfig, connect_with_retry};
+use super::{ConnectionConfig, ReconnectState, connect_with_retry};
 
 use crate as compute_graph;
 
@@ -19,6 +19,7 @@ pub struct WebSocketClientSource <OF, R, OS>
 {
 	output_format: OF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
 	output: OS,
 	_of: PhantomData <OF>
 }
@@ -36,6 +37,10 @@ impl <OF, R, OS> WebSocketClientSource <OF, R, OS>
 		Self
 		{
 			output_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
 			output,
 			_of: PhantomData::default ()
@@ -54,18 +59,26 @@ where
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
+		connect_with_retry
+		(
+			&self . connection_config,
+			&mut self . reconnect_state,
+			&mut shutdown
+		)
 			. await
 			. map
 		(
 			|websocket_stream|
-			websocket_source
+			self . reconnect_state . watch_close
 			(
-				self . output_format . clone (),
-				self . output . clone (),
-				websocket_stream
+				websocket_source
+				(
+					self . output_format . clone (),
+					self . output . clone (),
+					websocket_stream
+				)
 			)
 		)
 	}
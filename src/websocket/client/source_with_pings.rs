@@ -6,12 +6,13 @@ use tungstenite::client::IntoClientRequest;
 
 use crate::task;
 use crate::exit_status::ExitStatus;
-use crate::robust_service::SignallableFallibleServiceFactory;
+use crate::robust_service::{Construction, SignallableFallibleServiceFactory};
 use crate::service_handle::SignallableServiceHandle;
-use crate::websocket::connection::websocket_source_with_pings;
+use crate::websocket::PingPong;
+use crate::websocket::connection::websocket_source_with_heartbeat;
 use crate::websocket::io_format::OutputFormat;
 
-use super::{ConnectionConfig, PingConfig, connect_with_retry};
+use super::{ConnectionConfig, ReconnectState, PingConfig, connect_with_retry};
 
 use crate as compute_graph;
 
@@ -19,6 +20,7 @@ pub struct WebSocketClientSourceWithPings <OF, R, OS>
 {
 	output_format: OF,
 	connection_config: ConnectionConfig <R>,
+	reconnect_state: ReconnectState,
 	output: OS,
 	ping_config: PingConfig,
 	_of: PhantomData <OF>
@@ -38,6 +40,10 @@ impl <OF, R, OS> WebSocketClientSourceWithPings <OF, R, OS>
 		Self
 		{
 			output_format,
+			reconnect_state: ReconnectState::new
+			(
+				&connection_config . reconnect_policy
+			),
 			connection_config,
 			output,
 			ping_config,
@@ -57,20 +63,27 @@ where
 {
 	#[task (shutdown = shutdown)]
 	async fn construct (&mut self)
-	-> Option <SignallableServiceHandle <ExitStatus>>
+	-> Construction <SignallableServiceHandle <ExitStatus>>
 	{
-		connect_with_retry (&self . connection_config, &mut shutdown)
+		connect_with_retry
+		(
+			&self . connection_config,
+			&mut self . reconnect_state,
+			&mut shutdown
+		)
 			. await
 			. map
 		(
 			|websocket_stream|
-			websocket_source_with_pings
+			self . reconnect_state . watch_close
 			(
-				self . output_format . clone (),
-				self . output . clone (),
-				websocket_stream,
-				self . ping_config . ping_interval,
-				self . ping_config . ping_timeout
+				websocket_source_with_heartbeat
+				(
+					self . output_format . clone (),
+					self . output . clone (),
+					websocket_stream,
+					PingPong::from (self . ping_config)
+				)
 			)
 		)
 	}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_tungstenite::{Connector, MaybeTlsStream};
+use tungstenite::client::uri_mode;
+use tungstenite::error::{Error, TlsError, UrlError};
+use tungstenite::http::Uri;
+use tungstenite::stream::Mode;
+
+// Sets up TLS the way tokio-tungstenite would, which it only does as part of
+// the upgrade handshake.  Doing it here leaves room for permessage-deflate
+// between TLS and the websocket.
+pub (in crate::websocket::client) async fn wrap_stream
+(
+	tcp_stream: TcpStream,
+	uri: &Uri,
+	connector: Option <Connector>
+)
+-> Result <MaybeTlsStream <TcpStream>, Error>
+{
+	let client_config = match (uri_mode (uri)?, connector)
+	{
+		(Mode::Plain, _) => return Ok (MaybeTlsStream::Plain (tcp_stream)),
+		(Mode::Tls, None) => default_client_config (),
+		(Mode::Tls, Some (Connector::Rustls (client_config))) => client_config,
+		(Mode::Tls, Some (_)) =>
+			return Err (Error::Url (UrlError::TlsFeatureNotEnabled))
+	};
+
+	let domain = uri
+		. host ()
+		. unwrap_or_default ()
+		. trim_start_matches ('[')
+		. trim_end_matches (']');
+
+	let server_name = ServerName::try_from (domain)
+		. map_err (|_| TlsError::InvalidDnsName)?
+		. to_owned ();
+
+	let tls_stream = TlsConnector::from (client_config)
+		. connect (server_name, tcp_stream)
+		. await?;
+
+	Ok (MaybeTlsStream::Rustls (tls_stream))
+}
+
+fn default_client_config () -> Arc <ClientConfig>
+{
+	let mut root_store = RootCertStore::empty ();
+	root_store . extend (webpki_roots::TLS_SERVER_ROOTS . iter () . cloned ());
+
+	Arc::new
+	(
+		ClientConfig::builder ()
+			. with_root_certificates (root_store)
+			. with_no_client_auth ()
+	)
+}
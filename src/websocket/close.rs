@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use tracing::{Level, event};
+use tungstenite::protocol::CloseFrame;
+use tungstenite::protocol::frame::coding::CloseCode;
+
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+
+// The close frame a peer sent before we shut down.  A connection's exit status
+// carries it as the source of its Closed failure.
+#[derive (Clone, PartialEq, Eq, Debug)]
+pub struct PeerClosed
+{
+	pub code: CloseCode,
+	pub reason: String
+}
+
+impl PeerClosed
+{
+	pub fn from_failure (failure: &Failure) -> Option <&Self>
+	{
+		failure . error () ?. downcast_ref ()
+	}
+
+	pub fn from_exit_status (exit_status: &ExitStatus) -> Option <&Self>
+	{
+		Self::from_failure (exit_status . failure ()?)
+	}
+}
+
+impl From <Option <CloseFrame>> for PeerClosed
+{
+	fn from (close_frame: Option <CloseFrame>) -> Self
+	{
+		match close_frame
+		{
+			// The peer sent an empty close frame.
+			None => Self {code: CloseCode::Status, reason: String::new ()},
+			Some (close_frame) => Self
+			{
+				code: close_frame . code,
+				reason: close_frame . reason . to_string ()
+			}
+		}
+	}
+}
+
+impl Display for PeerClosed
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		if self . reason . is_empty ()
+		{
+			write! (f, "closed by peer with code {}", self . code)
+		}
+		else
+		{
+			write!
+			(
+				f,
+				"closed by peer with code {}: {}",
+				self . code,
+				self . reason
+			)
+		}
+	}
+}
+
+impl Error for PeerClosed {}
+
+pub (in crate::websocket) fn closed_by_peer
+(
+	close_frame: Option <CloseFrame>
+)
+-> ExitStatus
+{
+	let peer_closed = PeerClosed::from (close_frame);
+
+	event!
+	(
+		Level::INFO,
+		code = %peer_closed . code,
+		reason = %peer_closed . reason,
+		"websocket connection was closed before shutdown"
+	);
+
+	Failure::from_error (FailureKind::Closed, peer_closed) . into ()
+}
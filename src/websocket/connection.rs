@@ -1,108 +1,172 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
-use futures::StreamExt;
-use tokio::io::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use futures::sink::drain;
 use tokio::time::Duration;
-use tokio_tungstenite::WebSocketStream;
-use tungstenite::Message;
+use tracing::{Level, event};
+use tungstenite::{Message, Utf8Bytes};
 
 use crate::{expand_streams, service, join_services, send};
-use crate::exit_status::{ExitStatus, ServiceExitStatus};
+use crate::exit_status::{ExitStatus, Failure, ServiceExitStatus};
+use crate::service_handle::SignallableServiceHandle;
 use crate::stream::mpsc;
 
+use super::{Heartbeat, PingPong};
+use super::client::Outbox;
 use super::io_format::{InputFormat, OutputFormat};
 use super::shuttle::*;
-use super::keepalive::*;
 
 use crate as compute_graph;
 
+// Carries websocket messages both ways, like a WebSocketStream.
+pub trait MessageStream:
+	Stream <Item = Result <Message, tungstenite::Error>>
+	+ Sink <Message, Error = tungstenite::Error>
+	+ Unpin
+	+ Debug
+{
+}
+
+impl <T> MessageStream for T
+where
+	T: Stream <Item = Result <Message, tungstenite::Error>>
+		+ Sink <Message, Error = tungstenite::Error>
+		+ Unpin
+		+ Debug
+{
+}
+
+// Stands in for the output format of connections that only send.
+struct Superfluous;
+
+impl OutputFormat for Superfluous
+{
+	type External = ();
+
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <(), Failure>
+	{
+		event! (Level::INFO, ?utf8_bytes, "received superfluous text message");
+		Ok (())
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <(), Failure>
+	{
+		event! (Level::INFO, ?bytes, "received superfluous binary message");
+		Ok (())
+	}
+}
+
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_node_with_pings <IF, OF, IS, OS, S>
+pub async fn websocket_node_with_heartbeat <IF, OF, IS, OS, WS, H>
 (
 	input_format: IF,
 	output_format: OF,
 	inputs: input! (IS -> impl Into <IF::Intermediate>),
 	outputs: output! (OS <- OF::External),
-	websocket: WebSocketStream <S>,
-	ping_interval: Duration,
-	ping_timeout: Duration
+	websocket: WS,
+	heartbeat: H
 )
 -> ExitStatus
 where
 	IF: InputFormat + Send,
 	OF: OutputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream,
+	H: Heartbeat
 {
 	let (websocket_sink, websocket_stream) = websocket . split ();
 
 	// Buffer size doesn't need to be big here.
-	let (ping_sink, ping_stream) = mpsc (1);
-	let (pong_sink, pong_stream) = mpsc (1);
+	let (heartbeat_sink, heartbeat_stream) = mpsc (1);
 
-	let shuttle_input_handle = shuttle_input_with_pings
+	let shuttle_input_handle = shuttle_input_with_heartbeats
 	(
 		input_format,
 		inputs,
-		ping_stream,
+		heartbeat_stream,
 		websocket_sink
 	);
 
-	let shuttle_output_handle = shuttle_output_with_pongs
+	let shuttle_output_handle = shuttle_output_with_heartbeat
 	(
 		output_format,
+		heartbeat,
 		websocket_stream,
 		outputs,
-		pong_sink,
-	);
-
-	let keepalive_handle = keepalive
-	(
-		ping_sink,
-		pong_stream,
-		ping_interval,
-		ping_timeout
+		heartbeat_sink
 	);
 
-	let (input_report, output_report, keepalive_report) = join_services!
+	let (input_report, output_report) = join_services!
 	(
 		?shutdown,
 		shuttle_input_handle,
-		shuttle_output_handle,
-		keepalive_handle
+		shuttle_output_handle
 	);
 
-	if input_report . status_spurious ()
-		|| output_report . status_spurious ()
-		|| keepalive_report . status_spurious ()
-	{
-		ExitStatus::Spurious
-	}
-	else
+	let exit_status = input_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
 		let mut websocket_sink = input_report . into_value ();
 
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
+}
+
+// The ping-only predecessors of the *_with_heartbeat services, kept so older
+// callers still build.
+#[deprecated (note = "use websocket_node_with_heartbeat with a PingPong")]
+pub fn websocket_node_with_pings <IF, OF, IS, OS, WS>
+(
+	input_format: IF,
+	output_format: OF,
+	inputs: IS,
+	outputs: OS,
+	websocket: WS,
+	ping_interval: Duration,
+	ping_timeout: Duration
+)
+-> SignallableServiceHandle <ExitStatus>
+where
+	IF: InputFormat + Send + 'static,
+	OF: OutputFormat + Send + 'static,
+	OF::External: Send,
+	IS: StreamExt + Unpin + Debug + Send + 'static,
+	IS::Item: Into <IF::Intermediate> + Send,
+	OS: SinkExt <OF::External> + Unpin + Debug + Send + 'static,
+	OS::Error: Display,
+	WS: MessageStream + Send + 'static
+{
+	websocket_node_with_heartbeat
+	(
+		input_format,
+		output_format,
+		inputs,
+		outputs,
+		websocket,
+		PingPong::new (ping_interval, ping_timeout)
+	)
 }
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_node <IF, OF, IS, OS, S>
+pub async fn websocket_node <IF, OF, IS, OS, WS>
 (
 	input_format: IF,
 	output_format: OF,
 	inputs: input! (IS -> impl Into <IF::Intermediate>),
 	outputs: output! (OS <- OF::External),
-	websocket: WebSocketStream <S>
+	websocket: WS
 )
 -> ExitStatus
 where
 	IF: InputFormat + Send,
 	OF: OutputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream
 {
 	let (websocket_sink, websocket_stream) = websocket . split ();
 
@@ -118,88 +182,154 @@ where
 		shuttle_output_handle
 	);
 
-	if input_report . status_spurious () || output_report . status_spurious ()
+	let exit_status = input_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
-		ExitStatus::Spurious
+		let mut websocket_sink = input_report . into_value ();
+
+		let _ = send! (websocket_sink, Message::Close (None));
 	}
-	else
+
+	exit_status
+}
+
+#[expand_streams]
+#[service (shutdown = shutdown)]
+pub async fn websocket_node_with_outbox <IF, OF, IS, OS, WS>
+(
+	input_format: IF,
+	output_format: OF,
+	inputs: IS,
+	outbox: Outbox,
+	outputs: output! (OS <- OF::External),
+	websocket: WS
+)
+-> ExitStatus
+where
+	IF: InputFormat + Send,
+	OF: OutputFormat + Send,
+	IS: Stream + Unpin + Send + 'static,
+	IS::Item: Into <IF::Intermediate>,
+	WS: MessageStream
+{
+	let (websocket_sink, websocket_stream) = websocket . split ();
+
+	let shuttle_input_handle =
+		shuttle_outbox (input_format, inputs, outbox, websocket_sink);
+	let shuttle_output_handle =
+		shuttle_output (output_format, websocket_stream, outputs);
+
+	let (input_report, output_report) = join_services!
+	(
+		?shutdown,
+		shuttle_input_handle,
+		shuttle_output_handle
+	);
+
+	let exit_status = input_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
 		let mut websocket_sink = input_report . into_value ();
 
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
 }
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_source_with_pings <OF, OS, S>
+pub async fn websocket_source_with_heartbeat <OF, OS, WS, H>
 (
 	output_format: OF,
 	outputs: output! (OS <- OF::External),
-	websocket: WebSocketStream <S>,
-	ping_interval: Duration,
-	ping_timeout: Duration
+	websocket: WS,
+	heartbeat: H
 )
 -> ExitStatus
 where
 	OF: OutputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream,
+	H: Heartbeat
 {
 	let (websocket_sink, websocket_stream) = websocket . split ();
 
-	let (pong_sink, pong_stream) = mpsc (1);
+	let (heartbeat_sink, heartbeat_stream) = mpsc (1);
+
+	let shuttle_heartbeats_handle =
+		shuttle_heartbeats (heartbeat_stream, websocket_sink);
 
-	let shuttle_output_handle = shuttle_output_with_pongs
+	let shuttle_output_handle = shuttle_output_with_heartbeat
 	(
 		output_format,
+		heartbeat,
 		websocket_stream,
 		outputs,
-		pong_sink
-	);
-
-	let keepalive_handle = keepalive_direct_pings
-	(
-		websocket_sink,
-		pong_stream,
-		ping_interval,
-		ping_timeout
+		heartbeat_sink
 	);
 
-	let (output_report, keepalive_report) = join_services!
+	let (heartbeats_report, output_report) = join_services!
 	(
 		?shutdown,
-		shuttle_output_handle,
-		keepalive_handle
+		shuttle_heartbeats_handle,
+		shuttle_output_handle
 	);
 
-	if output_report . status_spurious () || keepalive_report . status_spurious ()
-	{
-		ExitStatus::Spurious
-	}
-	else
+	let exit_status = heartbeats_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
-		let mut websocket_sink = keepalive_report . into_value ();
+		let mut websocket_sink = heartbeats_report . into_value ();
 
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
+}
+
+#[deprecated (note = "use websocket_source_with_heartbeat with a PingPong")]
+pub fn websocket_source_with_pings <OF, OS, WS>
+(
+	output_format: OF,
+	outputs: OS,
+	websocket: WS,
+	ping_interval: Duration,
+	ping_timeout: Duration
+)
+-> SignallableServiceHandle <ExitStatus>
+where
+	OF: OutputFormat + Send + 'static,
+	OF::External: Send,
+	OS: SinkExt <OF::External> + Unpin + Debug + Send + 'static,
+	OS::Error: Display,
+	WS: MessageStream + Send + 'static
+{
+	websocket_source_with_heartbeat
+	(
+		output_format,
+		outputs,
+		websocket,
+		PingPong::new (ping_interval, ping_timeout)
+	)
 }
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_source <OF, OS, S>
+pub async fn websocket_source <OF, OS, WS>
 (
 	output_format: OF,
 	outputs: output! (OS <- OF::External),
-	websocket: WebSocketStream <S>
+	websocket: WS
 )
 -> ExitStatus
 where
 	OF: OutputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream
 {
 	let (mut websocket_sink, websocket_stream) = websocket . split ();
 
@@ -208,86 +338,111 @@ where
 
 	let (output_report,) = join_services! (?shutdown, shuttle_output_handle);
 
-	if output_report . status_spurious ()
-	{
-		ExitStatus::Spurious
-	}
-	else
+	let exit_status = output_report . exit_status ();
+
+	if exit_status . is_clean ()
 	{
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
 }
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_sink_with_pings <IF, IS, S>
+pub async fn websocket_sink_with_heartbeat <IF, IS, WS, H>
 (
 	input_format: IF,
 	inputs: input! (IS -> impl Into <IF::Intermediate>),
-	websocket: WebSocketStream <S>,
-	ping_interval: Duration,
-	ping_timeout: Duration
+	websocket: WS,
+	heartbeat: H
 )
 -> ExitStatus
 where
 	IF: InputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream,
+	H: Heartbeat
 {
 	let (websocket_sink, websocket_stream) = websocket . split ();
 
-	let (ping_sink, ping_stream) = mpsc (1);
+	let (heartbeat_sink, heartbeat_stream) = mpsc (1);
 
-	let shuttle_input_handle = shuttle_input_with_pings
+	let shuttle_input_handle = shuttle_input_with_heartbeats
 	(
 		input_format,
 		inputs,
-		ping_stream,
+		heartbeat_stream,
 		websocket_sink
 	);
 
-	let keepalive_handle = keepalive_direct_pongs
+	// Anything but heartbeat replies is logged and dropped, like drain_output
+	// does.
+	let drain_handle = shuttle_output_with_heartbeat
 	(
-		ping_sink,
+		Superfluous,
+		heartbeat,
 		websocket_stream,
-		ping_interval,
-		ping_timeout
+		drain (),
+		heartbeat_sink
 	);
 
-	let (input_report, keepalive_report) = join_services!
+	let (input_report, output_report) = join_services!
 	(
 		?shutdown,
 		shuttle_input_handle,
-		keepalive_handle
+		drain_handle
 	);
 
-	if input_report . status_spurious () || keepalive_report . status_spurious ()
-	{
-		ExitStatus::Spurious
-	}
-	else
+	let exit_status = input_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
 		let mut websocket_sink = input_report . into_value ();
 
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
+}
+
+#[deprecated (note = "use websocket_sink_with_heartbeat with a PingPong")]
+pub fn websocket_sink_with_pings <IF, IS, WS>
+(
+	input_format: IF,
+	inputs: IS,
+	websocket: WS,
+	ping_interval: Duration,
+	ping_timeout: Duration
+)
+-> SignallableServiceHandle <ExitStatus>
+where
+	IF: InputFormat + Send + 'static,
+	IS: StreamExt + Unpin + Debug + Send + 'static,
+	IS::Item: Into <IF::Intermediate> + Send,
+	WS: MessageStream + Send + 'static
+{
+	websocket_sink_with_heartbeat
+	(
+		input_format,
+		inputs,
+		websocket,
+		PingPong::new (ping_interval, ping_timeout)
+	)
 }
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn websocket_sink <IF, IS, S>
+pub async fn websocket_sink <IF, IS, WS>
 (
 	input_format: IF,
 	inputs: input! (IS -> impl Into <IF::Intermediate>),
-	websocket: WebSocketStream <S>
+	websocket: WS
 )
 -> ExitStatus
 where
 	IF: InputFormat + Send,
-	S: AsyncRead + AsyncWrite + Unpin + Debug
+	WS: MessageStream
 {
 	let (websocket_sink, websocket_stream) = websocket . split ();
 
@@ -302,16 +457,15 @@ where
 		drain_handle
 	);
 
-	if input_report . status_spurious () || output_report . status_spurious ()
-	{
-		ExitStatus::Spurious
-	}
-	else
+	let exit_status = input_report . exit_status ()
+		. and (output_report . exit_status ());
+
+	if exit_status . is_clean ()
 	{
 		let mut websocket_sink = input_report . into_value ();
 
 		let _ = send! (websocket_sink, Message::Close (None));
-
-		ExitStatus::Clean
 	}
+
+	exit_status
 }
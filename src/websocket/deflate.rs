@@ -0,0 +1,1101 @@
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{
+	Compress,
+	Compression,
+	Decompress,
+	FlushCompress,
+	FlushDecompress,
+	Status
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tungstenite::http::{HeaderMap, HeaderValue};
+use tungstenite::http::header::SEC_WEBSOCKET_EXTENSIONS;
+use tungstenite::protocol::WebSocketConfig;
+
+use crate::exit_status::{Failure, FailureKind};
+
+// Negotiates the permessage-deflate extension (RFC 7692).  Window bits are
+// held to 9 through 15, since zlib can't compress with the 8 bit window the
+// RFC also allows.
+#[derive (Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DeflateConfig
+{
+	pub server_max_window_bits: u8,
+	pub client_max_window_bits: u8,
+	// Resetting the compressor after every message costs compression but
+	// saves keeping the window between messages.
+	pub server_no_context_takeover: bool,
+	pub client_no_context_takeover: bool
+}
+
+impl Default for DeflateConfig
+{
+	fn default () -> Self
+	{
+		Self
+		{
+			server_max_window_bits: 15,
+			client_max_window_bits: 15,
+			server_no_context_takeover: false,
+			client_no_context_takeover: false
+		}
+	}
+}
+
+impl DeflateConfig
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn with_server_max_window_bits (mut self, window_bits: u8) -> Self
+	{
+		self . server_max_window_bits = window_bits;
+		self
+	}
+
+	pub fn with_client_max_window_bits (mut self, window_bits: u8) -> Self
+	{
+		self . client_max_window_bits = window_bits;
+		self
+	}
+
+	pub fn with_server_no_context_takeover (mut self) -> Self
+	{
+		self . server_no_context_takeover = true;
+		self
+	}
+
+	pub fn with_client_no_context_takeover (mut self) -> Self
+	{
+		self . client_no_context_takeover = true;
+		self
+	}
+
+	fn server_window_bits (&self) -> u8
+	{
+		self . server_max_window_bits . clamp (9, 15)
+	}
+
+	fn client_window_bits (&self) -> u8
+	{
+		self . client_max_window_bits . clamp (9, 15)
+	}
+
+	// Adds the client's offer to the upgrade request.
+	pub (in crate::websocket) fn offer (&self, headers: &mut HeaderMap)
+	{
+		let mut offer =
+			String::from ("permessage-deflate; client_max_window_bits");
+
+		if self . client_window_bits () < 15
+		{
+			offer . push_str (&format! ("={}", self . client_window_bits ()));
+		}
+
+		if self . server_window_bits () < 15
+		{
+			offer . push_str
+			(
+				&format!
+				(
+					"; server_max_window_bits={}",
+					self . server_window_bits ()
+				)
+			);
+		}
+
+		if self . server_no_context_takeover
+		{
+			offer . push_str ("; server_no_context_takeover");
+		}
+
+		if self . client_no_context_takeover
+		{
+			offer . push_str ("; client_no_context_takeover");
+		}
+
+		if let Ok (offer) = HeaderValue::from_str (&offer)
+		{
+			headers . append (SEC_WEBSOCKET_EXTENSIONS, offer);
+		}
+	}
+
+	// Checks the server's answer to the client's offer.  None means the server
+	// turned compression down.
+	pub (in crate::websocket) fn accept (&self, headers: &HeaderMap)
+	-> std::result::Result <Option <Negotiated>, Failure>
+	{
+		let invalid_answer = |reason: &str| Failure::new
+		(
+			FailureKind::Protocol,
+			format! ("invalid permessage-deflate answer: {}", reason)
+		);
+
+		let mut answer = None;
+
+		for (name, params) in extensions (headers)
+		{
+			if name != "permessage-deflate" || answer . is_some ()
+			{
+				return Err
+				(
+					invalid_answer (&format! ("unexpected extension {}", name))
+				);
+			}
+
+			answer = Some
+			(
+				Params::parse (params)
+					. ok_or_else (|| invalid_answer ("bad parameters"))?
+			);
+		}
+
+		let Some (answer) = answer
+		else
+		{
+			return Ok (None);
+		};
+
+		let server_window_bits = self . server_window_bits ();
+
+		if answer
+			. server_max_window_bits
+			. is_some_and (|window_bits| window_bits > server_window_bits)
+		{
+			return Err (invalid_answer ("server window too large"));
+		}
+
+		let window_bits = match answer . client_max_window_bits
+		{
+			None => self . client_window_bits (),
+			Some (Some (window_bits)) if window_bits >= 9 =>
+				window_bits . min (self . client_window_bits ()),
+			Some (_) => return Err (invalid_answer ("unusable client window"))
+		};
+
+		Ok
+		(
+			Some
+			(
+				Negotiated
+				{
+					window_bits,
+					no_context_takeover: answer . client_no_context_takeover
+						|| self . client_no_context_takeover,
+					peer_no_context_takeover:
+						answer . server_no_context_takeover
+				}
+			)
+		)
+	}
+
+	// Takes up the first of the client's offers that the server can honour,
+	// adding the answer to the upgrade response.
+	pub (in crate::websocket) fn answer
+	(
+		&self,
+		offer_headers: &HeaderMap,
+		answer_headers: &mut HeaderMap
+	)
+	-> Option <Negotiated>
+	{
+		let (answer, negotiated) = extensions (offer_headers)
+			. filter (|(name, _)| *name == "permessage-deflate")
+			. find_map
+		(
+			|(_, params)| self . answer_offer (Params::parse (params)?)
+		)?;
+
+		let answer = HeaderValue::from_str (&answer) . ok ()?;
+		answer_headers . insert (SEC_WEBSOCKET_EXTENSIONS, answer);
+
+		Some (negotiated)
+	}
+
+	fn answer_offer (&self, offer: Params) -> Option <(String, Negotiated)>
+	{
+		let window_bits = self
+			. server_window_bits ()
+			. min (offer . server_max_window_bits . unwrap_or (15));
+
+		if window_bits < 9
+		{
+			return None;
+		}
+
+		let no_context_takeover = self . server_no_context_takeover
+			|| offer . server_no_context_takeover;
+
+		let mut answer = String::from ("permessage-deflate");
+
+		if offer . server_max_window_bits . is_some () || window_bits < 15
+		{
+			answer . push_str
+			(
+				&format! ("; server_max_window_bits={}", window_bits)
+			);
+		}
+
+		// Only an offer that mentions the client's window lets us limit it.
+		if let Some (offered_window_bits) = offer . client_max_window_bits
+		{
+			let client_window_bits = self
+				. client_window_bits ()
+				. min (offered_window_bits . unwrap_or (15));
+
+			if client_window_bits < 15
+			{
+				answer . push_str
+				(
+					&format! ("; client_max_window_bits={}", client_window_bits)
+				);
+			}
+		}
+
+		if no_context_takeover
+		{
+			answer . push_str ("; server_no_context_takeover");
+		}
+
+		if self . client_no_context_takeover
+		{
+			answer . push_str ("; client_no_context_takeover");
+		}
+
+		let negotiated = Negotiated
+		{
+			window_bits,
+			no_context_takeover,
+			peer_no_context_takeover: self . client_no_context_takeover
+		};
+
+		Some ((answer, negotiated))
+	}
+}
+
+// What the two ends agreed on, from this end's side.
+#[derive (Copy, Clone, PartialEq, Eq, Debug)]
+pub (in crate::websocket) struct Negotiated
+{
+	// For this end's compressor.  Decompressing always uses the full window,
+	// which copes with anything smaller.
+	window_bits: u8,
+	no_context_takeover: bool,
+	peer_no_context_takeover: bool
+}
+
+#[derive (Default)]
+struct Params
+{
+	server_max_window_bits: Option <u8>,
+	// Offers may leave out the value.
+	client_max_window_bits: Option <Option <u8>>,
+	server_no_context_takeover: bool,
+	client_no_context_takeover: bool
+}
+
+impl Params
+{
+	// Turns down unknown, repeated or malformed parameters.
+	fn parse <'a> (params: impl Iterator <Item = &'a str>) -> Option <Self>
+	{
+		let mut parsed = Self::default ();
+
+		for param in params
+		{
+			let (name, value) = match param . split_once ('=')
+			{
+				Some ((name, value)) =>
+				(
+					name . trim (),
+					Some (value . trim () . trim_matches ('"'))
+				),
+				None => (param . trim (), None)
+			};
+
+			// None if there's no value, Some (None) if it's out of range.
+			let window_bits = value . map
+			(
+				|value| value
+					. parse::<u8> ()
+					. ok ()
+					. filter (|window_bits| (8 ..= 15) . contains (window_bits))
+			);
+
+			match (name, window_bits)
+			{
+				("server_max_window_bits", Some (Some (window_bits)))
+					if parsed . server_max_window_bits . is_none () =>
+					parsed . server_max_window_bits = Some (window_bits),
+				("client_max_window_bits", None | Some (Some (_)))
+					if parsed . client_max_window_bits . is_none () =>
+					parsed . client_max_window_bits =
+						Some (window_bits . flatten ()),
+				("server_no_context_takeover", None)
+					if ! parsed . server_no_context_takeover =>
+					parsed . server_no_context_takeover = true,
+				("client_no_context_takeover", None)
+					if ! parsed . client_no_context_takeover =>
+					parsed . client_no_context_takeover = true,
+				_ => return None
+			}
+		}
+
+		Some (parsed)
+	}
+}
+
+// Splits Sec-WebSocket-Extensions headers into extension names and their
+// parameters.
+fn extensions (headers: &HeaderMap)
+-> impl Iterator <Item = (&str, impl Iterator <Item = &str>)>
+{
+	headers
+		. get_all (SEC_WEBSOCKET_EXTENSIONS)
+		. iter ()
+		. flat_map (|value| value . to_str () . unwrap_or ("?") . split (','))
+		. map
+	(
+		|extension|
+		{
+			let mut parts = extension . split (';');
+			let name = parts . next () . unwrap_or_default () . trim ();
+
+			(name, parts . filter (|param| ! param . trim () . is_empty ()))
+		}
+	)
+}
+
+const HEADER_END: &[u8] = b"\r\n\r\n";
+
+// Sits between the websocket and the connection underneath, compressing and
+// decompressing messages once permessage-deflate has been negotiated.  Frames
+// pass through untouched until then, and tungstenite never sees the RSV1 bit
+// that marks compressed messages.
+#[derive (Debug)]
+pub struct DeflateStream <S>
+{
+	inner: S,
+	// How much of the blank line ending the peer's handshake headers has been
+	// read, until it has all been read.  Reads stop at that line, so that no
+	// frame after it gets to tungstenite before the codec is in place.
+	header_end_matched: Option <usize>,
+	codec: Option <Box <Codec>>,
+	read_buffer: BytesMut,
+	readable: BytesMut,
+	write_buffer: BytesMut,
+	writable: BytesMut
+}
+
+impl <S> DeflateStream <S>
+{
+	pub (in crate::websocket) fn new (inner: S) -> Self
+	{
+		Self
+		{
+			inner,
+			header_end_matched: Some (0),
+			codec: None,
+			read_buffer: BytesMut::new (),
+			readable: BytesMut::new (),
+			write_buffer: BytesMut::new (),
+			writable: BytesMut::new ()
+		}
+	}
+
+	// Only to be called between the handshake and the first frame.
+	pub (in crate::websocket) fn enable
+	(
+		&mut self,
+		negotiated: Negotiated,
+		stream_config: Option <WebSocketConfig>
+	)
+	{
+		let stream_config = stream_config . unwrap_or_default ();
+
+		self . codec = Some
+		(
+			Box::new
+			(
+				Codec::new
+				(
+					negotiated,
+					stream_config . max_frame_size . unwrap_or (usize::MAX),
+					stream_config . max_message_size . unwrap_or (usize::MAX)
+				)
+			)
+		);
+	}
+
+	pub fn is_enabled (&self) -> bool
+	{
+		self . codec . is_some ()
+	}
+
+	pub fn get_ref (&self) -> &S
+	{
+		&self . inner
+	}
+
+	pub fn get_mut (&mut self) -> &mut S
+	{
+		&mut self . inner
+	}
+
+	// Moves what can be handed on from the read buffer.
+	fn decode (&mut self) -> Result <()>
+	{
+		if let Some (mut matched) = self . header_end_matched
+		{
+			let mut header_length = self . read_buffer . len ();
+
+			for (index, byte) in self . read_buffer . iter () . enumerate ()
+			{
+				matched = match *byte
+				{
+					byte if byte == HEADER_END [matched] => matched + 1,
+					b'\r' => 1,
+					_ => 0
+				};
+
+				if matched == HEADER_END . len ()
+				{
+					header_length = index + 1;
+					break;
+				}
+			}
+
+			self . header_end_matched = Some (matched)
+				. filter (|matched| *matched < HEADER_END . len ());
+
+			let header = self . read_buffer . split_to (header_length);
+			self . readable . unsplit (header);
+
+			return Ok (());
+		}
+
+		let Some (codec) = &mut self . codec
+		else
+		{
+			let read = self . read_buffer . split ();
+			self . readable . unsplit (read);
+
+			return Ok (());
+		};
+
+		while let Some (frame) =
+			parse_frame (&self . read_buffer, codec . max_frame_size)?
+		{
+			let frame_bytes =
+				self . read_buffer . split_to (frame . payload . end);
+			codec . decode (frame, &frame_bytes, &mut self . readable)?;
+		}
+
+		Ok (())
+	}
+
+	// Moves what can be sent on from the write buffer.
+	fn encode (&mut self) -> Result <()>
+	{
+		let Some (codec) = &mut self . codec
+		else
+		{
+			let written = self . write_buffer . split ();
+			self . writable . unsplit (written);
+
+			return Ok (());
+		};
+
+		while let Some (frame) = parse_frame (&self . write_buffer, usize::MAX)?
+		{
+			let frame_bytes =
+				self . write_buffer . split_to (frame . payload . end);
+			codec . encode (frame, &frame_bytes, &mut self . writable)?;
+		}
+
+		Ok (())
+	}
+}
+
+impl <S> DeflateStream <S>
+where S: AsyncWrite + Unpin
+{
+	fn poll_drain (&mut self, cx: &mut Context <'_>) -> Poll <Result <()>>
+	{
+		while ! self . writable . is_empty ()
+		{
+			let written = ready!
+			(
+				Pin::new (&mut self . inner) . poll_write (cx, &self . writable)
+			)?;
+
+			if written == 0
+			{
+				return Poll::Ready (Err (ErrorKind::WriteZero . into ()));
+			}
+
+			self . writable . advance (written);
+		}
+
+		Poll::Ready (Ok (()))
+	}
+}
+
+// Compressed frames queued beyond this hold up further writes.
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+impl <S> AsyncRead for DeflateStream <S>
+where S: AsyncRead + Unpin
+{
+	fn poll_read
+	(
+		self: Pin <&mut Self>,
+		cx: &mut Context <'_>,
+		buf: &mut ReadBuf <'_>
+	)
+	-> Poll <Result <()>>
+	{
+		let this = self . get_mut ();
+
+		let passing_through = this . header_end_matched . is_none ()
+			&& this . codec . is_none ()
+			&& this . read_buffer . is_empty ()
+			&& this . readable . is_empty ();
+
+		if passing_through
+		{
+			return Pin::new (&mut this . inner) . poll_read (cx, buf);
+		}
+
+		loop
+		{
+			if ! this . readable . is_empty ()
+			{
+				let length =
+					this . readable . len () . min (buf . remaining ());
+				buf . put_slice (&this . readable . split_to (length));
+
+				return Poll::Ready (Ok (()));
+			}
+
+			this . decode ()?;
+
+			if ! this . readable . is_empty ()
+			{
+				continue;
+			}
+
+			let mut chunk = [0; 8192];
+			let mut chunk = ReadBuf::new (&mut chunk);
+
+			ready! (Pin::new (&mut this . inner) . poll_read (cx, &mut chunk))?;
+
+			if chunk . filled () . is_empty ()
+			{
+				return Poll::Ready (Ok (()));
+			}
+
+			this . read_buffer . extend_from_slice (chunk . filled ());
+		}
+	}
+}
+
+impl <S> AsyncWrite for DeflateStream <S>
+where S: AsyncWrite + Unpin
+{
+	fn poll_write
+	(
+		self: Pin <&mut Self>,
+		cx: &mut Context <'_>,
+		buf: &[u8]
+	)
+	-> Poll <Result <usize>>
+	{
+		let this = self . get_mut ();
+
+		if this . codec . is_none ()
+		{
+			return Pin::new (&mut this . inner) . poll_write (cx, buf);
+		}
+
+		if this . writable . len () >= WRITE_BUFFER_SIZE
+		{
+			ready! (this . poll_drain (cx))?;
+		}
+
+		this . write_buffer . extend_from_slice (buf);
+		this . encode ()?;
+
+		Poll::Ready (Ok (buf . len ()))
+	}
+
+	fn poll_flush (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Result <()>>
+	{
+		let this = self . get_mut ();
+
+		ready! (this . poll_drain (cx))?;
+		Pin::new (&mut this . inner) . poll_flush (cx)
+	}
+
+	fn poll_shutdown (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Result <()>>
+	{
+		let this = self . get_mut ();
+
+		ready! (this . poll_drain (cx))?;
+		Pin::new (&mut this . inner) . poll_shutdown (cx)
+	}
+}
+
+struct Frame
+{
+	fin: bool,
+	rsv1: bool,
+	// RSV2 or RSV3, which we leave for tungstenite to turn down.
+	other_reserved: bool,
+	opcode: u8,
+	mask: Option <[u8; 4]>,
+	payload: Range <usize>
+}
+
+// Reads the frame at the start of the buffer, once all of it is there.
+fn parse_frame (buffer: &[u8], max_frame_size: usize) -> Result <Option <Frame>>
+{
+	let [first, second, ..] = *buffer
+	else
+	{
+		return Ok (None);
+	};
+
+	let (payload_length, mut offset) = match second & 0x7f
+	{
+		126 => (buffer . get (2 .. 4) . map (read_length), 4),
+		127 => (buffer . get (2 .. 10) . map (read_length), 10),
+		payload_length => (Some (u64::from (payload_length)), 2)
+	};
+
+	let Some (payload_length) = payload_length
+	else
+	{
+		return Ok (None);
+	};
+
+	if payload_length > max_frame_size as u64
+	{
+		return Err (invalid_data ("websocket frame too large"));
+	}
+
+	let mask = match second & 0x80
+	{
+		0 => None,
+		_ =>
+		{
+			let Some (&[a, b, c, d]) = buffer . get (offset .. offset + 4)
+			else
+			{
+				return Ok (None);
+			};
+
+			offset += 4;
+			Some ([a, b, c, d])
+		}
+	};
+
+	let end = offset + payload_length as usize;
+
+	if buffer . len () < end
+	{
+		return Ok (None);
+	}
+
+	let frame = Frame
+	{
+		fin: first & 0x80 != 0,
+		rsv1: first & 0x40 != 0,
+		other_reserved: first & 0x30 != 0,
+		opcode: first & 0x0f,
+		mask,
+		payload: offset .. end
+	};
+
+	Ok (Some (frame))
+}
+
+fn read_length (bytes: &[u8]) -> u64
+{
+	bytes . iter () . fold (0, |length, byte| length << 8 | u64::from (*byte))
+}
+
+fn push_frame
+(
+	out: &mut BytesMut,
+	fin: bool,
+	rsv1: bool,
+	opcode: u8,
+	mask: Option <[u8; 4]>,
+	payload: &[u8]
+)
+{
+	out . put_u8 (u8::from (fin) << 7 | u8::from (rsv1) << 6 | opcode);
+
+	let mask_bit = match mask
+	{
+		Some (_) => 0x80,
+		None => 0
+	};
+
+	match payload . len ()
+	{
+		length @ 0 ..= 125 => out . put_u8 (mask_bit | length as u8),
+		length @ 126 ..= 0xffff =>
+		{
+			out . put_u8 (mask_bit | 126);
+			out . put_u16 (length as u16);
+		},
+		length =>
+		{
+			out . put_u8 (mask_bit | 127);
+			out . put_u64 (length as u64);
+		}
+	}
+
+	let start = out . len () + mask . map_or (0, |mask| mask . len ());
+
+	if let Some (mask) = mask
+	{
+		out . extend_from_slice (&mask);
+	}
+
+	out . extend_from_slice (payload);
+
+	if let Some (mask) = mask
+	{
+		apply_mask (&mut out [start ..], mask);
+	}
+}
+
+fn apply_mask (payload: &mut [u8], mask: [u8; 4])
+{
+	for (index, byte) in payload . iter_mut () . enumerate ()
+	{
+		*byte ^= mask [index % 4];
+	}
+}
+
+fn invalid_data (message: &str) -> Error
+{
+	Error::new (ErrorKind::InvalidData, message)
+}
+
+// A message whose frames are still arriving.
+struct Fragments
+{
+	opcode: u8,
+	mask: Option <[u8; 4]>,
+	payload: Vec <u8>
+}
+
+impl Fragments
+{
+	fn start (frame: &Frame) -> Self
+	{
+		Self {opcode: frame . opcode, mask: frame . mask, payload: Vec::new ()}
+	}
+
+	fn push (&mut self, frame: &Frame, frame_bytes: &[u8])
+	{
+		let start = self . payload . len ();
+		self
+			. payload
+			. extend_from_slice (&frame_bytes [frame . payload . clone ()]);
+
+		if let Some (mask) = frame . mask
+		{
+			apply_mask (&mut self . payload [start ..], mask);
+		}
+	}
+}
+
+struct Codec
+{
+	negotiated: Negotiated,
+	compress: Compress,
+	decompress: Decompress,
+	max_frame_size: usize,
+	max_message_size: usize,
+	incoming: Option <Fragments>,
+	outgoing: Option <Fragments>
+}
+
+impl std::fmt::Debug for Codec
+{
+	fn fmt (&self, f: &mut std::fmt::Formatter <'_>) -> std::fmt::Result
+	{
+		f
+			. debug_struct ("Codec")
+			. field ("negotiated", &self . negotiated)
+			. finish ()
+	}
+}
+
+impl Codec
+{
+	fn new
+	(
+		negotiated: Negotiated,
+		max_frame_size: usize,
+		max_message_size: usize
+	)
+	-> Self
+	{
+		Self
+		{
+			negotiated,
+			compress: Compress::new_with_window_bits
+			(
+				Compression::default (),
+				false,
+				negotiated . window_bits
+			),
+			decompress: Decompress::new_with_window_bits (false, 15),
+			max_frame_size,
+			max_message_size,
+			incoming: None,
+			outgoing: None
+		}
+	}
+
+	// Inflates compressed messages once their last frame is in, and hands
+	// everything else on as it is.
+	fn decode (&mut self, frame: Frame, frame_bytes: &[u8], out: &mut BytesMut)
+	-> Result <()>
+	{
+		// Control frames may come between the frames of a message.
+		if frame . other_reserved || frame . opcode >= 8
+		{
+			out . extend_from_slice (frame_bytes);
+			return Ok (());
+		}
+
+		let incoming = self . incoming . take ();
+
+		let mut fragments = match (frame . opcode, frame . rsv1, incoming)
+		{
+			(1 | 2, true, None) => Fragments::start (&frame),
+			(0, false, Some (fragments)) => fragments,
+			(0 ..= 2, false, None) =>
+			{
+				out . extend_from_slice (frame_bytes);
+				return Ok (());
+			},
+			_ => return Err (invalid_data ("unexpected websocket frame"))
+		};
+
+		fragments . push (&frame, frame_bytes);
+
+		if fragments . payload . len () > self . max_message_size
+		{
+			return Err (invalid_data ("compressed message too large"));
+		}
+
+		if ! frame . fin
+		{
+			self . incoming = Some (fragments);
+			return Ok (());
+		}
+
+		let message = inflate
+		(
+			&mut self . decompress,
+			fragments . payload,
+			self . max_message_size
+		)?;
+
+		if self . negotiated . peer_no_context_takeover
+		{
+			self . decompress . reset (false);
+		}
+
+		// Frames from clients have to be masked.  A zero mask keeps them masked
+		// without changing the payload.
+		let mask = fragments . mask . map (|_| [0; 4]);
+		let mut opcode = fragments . opcode;
+		let mut chunks =
+			message . chunks (self . max_frame_size . max (1)) . peekable ();
+
+		if chunks . peek () . is_none ()
+		{
+			push_frame (out, true, false, opcode, mask, &[]);
+		}
+
+		while let Some (chunk) = chunks . next ()
+		{
+			let fin = chunks . peek () . is_none ();
+			push_frame (out, fin, false, opcode, mask, chunk);
+			opcode = 0;
+		}
+
+		Ok (())
+	}
+
+	// Deflates each text or binary message into a single frame, keeping the
+	// mask of its first frame.
+	fn encode (&mut self, frame: Frame, frame_bytes: &[u8], out: &mut BytesMut)
+	-> Result <()>
+	{
+		if frame . rsv1 || frame . other_reserved || frame . opcode >= 8
+		{
+			out . extend_from_slice (frame_bytes);
+			return Ok (());
+		}
+
+		let mut fragments = match (frame . opcode, self . outgoing . take ())
+		{
+			(1 | 2, None) => Fragments::start (&frame),
+			(0, Some (fragments)) => fragments,
+			(0, None) =>
+			{
+				out . extend_from_slice (frame_bytes);
+				return Ok (());
+			},
+			_ => return Err (invalid_data ("unexpected websocket frame"))
+		};
+
+		fragments . push (&frame, frame_bytes);
+
+		if ! frame . fin
+		{
+			self . outgoing = Some (fragments);
+			return Ok (());
+		}
+
+		let compressed = deflate (&mut self . compress, &fragments . payload)?;
+
+		if self . negotiated . no_context_takeover
+		{
+			self . compress . reset ();
+		}
+
+		push_frame
+		(
+			out,
+			true,
+			true,
+			fragments . opcode,
+			fragments . mask,
+			&compressed
+		);
+
+		Ok (())
+	}
+}
+
+// The empty block that ends every compressed message, which is left off the
+// wire.
+const EMPTY_BLOCK: [u8; 4] = [0, 0, 0xff, 0xff];
+
+fn deflate (compress: &mut Compress, message: &[u8]) -> Result <Vec <u8>>
+{
+	let mut compressed = Vec::with_capacity (message . len () / 2 + 64);
+	let started_at = compress . total_in ();
+
+	loop
+	{
+		let consumed = (compress . total_in () - started_at) as usize;
+
+		compress
+			. compress_vec
+			(
+				&message [consumed ..],
+				&mut compressed,
+				FlushCompress::Sync
+			)
+			. map_err (|error| Error::new (ErrorKind::InvalidData, error))?;
+
+		let consumed = (compress . total_in () - started_at) as usize;
+		let room_left = compressed . len () < compressed . capacity ();
+
+		// A flush isn't done until it has left room to spare.
+		if consumed == message . len () && room_left
+		{
+			break;
+		}
+
+		compressed . reserve (compressed . capacity ());
+	}
+
+	if compressed . ends_with (&EMPTY_BLOCK)
+	{
+		compressed . truncate (compressed . len () - EMPTY_BLOCK . len ());
+	}
+
+	Ok (compressed)
+}
+
+fn inflate
+(
+	decompress: &mut Decompress,
+	mut compressed: Vec <u8>,
+	max_message_size: usize
+)
+-> Result <Vec <u8>>
+{
+	compressed . extend_from_slice (&EMPTY_BLOCK);
+
+	let mut message =
+		Vec::with_capacity ((compressed . len () * 4) . min (max_message_size));
+	let started_at = decompress . total_in ();
+
+	loop
+	{
+		let consumed = (decompress . total_in () - started_at) as usize;
+		let produced = message . len ();
+
+		let status = decompress
+			. decompress_vec
+			(
+				&compressed [consumed ..],
+				&mut message,
+				FlushDecompress::Sync
+			)
+			. map_err (|error| Error::new (ErrorKind::InvalidData, error))?;
+
+		if message . len () > max_message_size
+		{
+			return Err (invalid_data ("decompressed message too large"));
+		}
+
+		// The peer may have ended the stream, which leaves nothing to take
+		// over for the next message.
+		if status == Status::StreamEnd
+		{
+			decompress . reset (false);
+			break;
+		}
+
+		let now_consumed = (decompress . total_in () - started_at) as usize;
+		let room_left = message . len () < message . capacity ();
+
+		if now_consumed == compressed . len () && room_left
+		{
+			break;
+		}
+
+		if room_left && now_consumed == consumed && message . len () == produced
+		{
+			return Err (invalid_data ("corrupt compressed message"));
+		}
+
+		message . reserve (message . capacity () . max (64));
+	}
+
+	Ok (message)
+}
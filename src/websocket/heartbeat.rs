@@ -0,0 +1,282 @@
+use bytes::Bytes;
+use serde_json::Value;
+use tokio::time::{Duration, Instant};
+use tungstenite::Message;
+
+use crate::exit_status::{Failure, FailureKind};
+
+use super::PingConfig;
+
+// What a connection has seen since it was established.
+#[derive (Copy, Clone, Debug)]
+pub struct Liveness
+{
+	pub connected_at: Instant,
+	pub heartbeat_sent_at: Option <Instant>,
+	pub reply_received_at: Option <Instant>,
+	pub message_received_at: Option <Instant>
+}
+
+impl Liveness
+{
+	pub (in crate::websocket) fn new () -> Self
+	{
+		Self
+		{
+			connected_at: Instant::now (),
+			heartbeat_sent_at: None,
+			reply_received_at: None,
+			message_received_at: None
+		}
+	}
+
+	// When the heartbeat still waiting for a reply was sent, if there is one.
+	pub fn awaiting_reply_since (&self) -> Option <Instant>
+	{
+		let heartbeat_sent_at = self . heartbeat_sent_at?;
+
+		match self . reply_received_at
+		{
+			Some (reply_received_at) if reply_received_at < heartbeat_sent_at =>
+				Some (heartbeat_sent_at),
+			Some (_) => None,
+			None => Some (heartbeat_sent_at)
+		}
+	}
+
+	pub fn last_message_at (&self) -> Instant
+	{
+		self . message_received_at . unwrap_or (self . connected_at)
+	}
+}
+
+// Keeps a connection alive and decides when it has died.  Each connection gets
+// a fresh clone.
+pub trait Heartbeat: Clone + Send + 'static
+{
+	fn interval (&self) -> Duration;
+
+	// The message to send every interval, or None to skip this one.
+	fn heartbeat (&mut self) -> Option <Message>;
+
+	// Replies are kept from the output.  An error fails the connection.
+	fn is_reply (&mut self, message: &Message) -> Result <bool, Failure>;
+
+	// When the connection should be given up on, if ever.
+	fn stale_at (&self, liveness: &Liveness) -> Option <Instant>;
+}
+
+// Protocol level pings carrying a counter, each of which must be answered by a
+// matching pong within the timeout.
+#[derive (Clone, Debug)]
+pub struct PingPong
+{
+	ping_interval: Duration,
+	ping_timeout: Duration,
+	ping_counter: u32,
+	outstanding: Option <Bytes>
+}
+
+impl PingPong
+{
+	pub fn new (ping_interval: Duration, ping_timeout: Duration) -> Self
+	{
+		Self
+		{
+			ping_interval,
+			ping_timeout,
+			ping_counter: 0,
+			outstanding: None
+		}
+	}
+}
+
+impl From <PingConfig> for PingPong
+{
+	fn from (ping_config: PingConfig) -> Self
+	{
+		Self::new (ping_config . ping_interval, ping_config . ping_timeout)
+	}
+}
+
+impl Heartbeat for PingPong
+{
+	fn interval (&self) -> Duration
+	{
+		self . ping_interval
+	}
+
+	fn heartbeat (&mut self) -> Option <Message>
+	{
+		if self . outstanding . is_some ()
+		{
+			return None;
+		}
+
+		let ping_bytes =
+			Bytes::from_owner (self . ping_counter . to_be_bytes ());
+		self . ping_counter += 1;
+		self . outstanding = Some (ping_bytes . clone ());
+
+		Some (Message::Ping (ping_bytes))
+	}
+
+	fn is_reply (&mut self, message: &Message) -> Result <bool, Failure>
+	{
+		let Message::Pong (pong_bytes) = message
+		else
+		{
+			return Ok (false);
+		};
+
+		match self . outstanding . take ()
+		{
+			Some (ping_bytes) if ping_bytes == pong_bytes => Ok (true),
+			_ => Err
+			(
+				Failure::new
+				(
+					FailureKind::Protocol,
+					"pong frame bytes did not match ping frame bytes"
+				)
+			)
+		}
+	}
+
+	fn stale_at (&self, liveness: &Liveness) -> Option <Instant>
+	{
+		Some (liveness . awaiting_reply_since ()? + self . ping_timeout)
+	}
+}
+
+// Application level pings such as {"op":"ping"}.  A text message is a reply if
+// it is a JSON object holding every field of the pong, so {"op":"pong"}
+// matches {"op":"pong","ts":1700000000}.
+#[derive (Clone, Debug)]
+pub struct JsonHeartbeat
+{
+	ping: String,
+	pong: Value,
+	interval: Duration,
+	timeout: Duration,
+	outstanding: bool
+}
+
+impl JsonHeartbeat
+{
+	pub fn new
+	(
+		ping: Value,
+		pong: Value,
+		interval: Duration,
+		timeout: Duration
+	)
+	-> Self
+	{
+		Self
+		{
+			ping: ping . to_string (),
+			pong,
+			interval,
+			timeout,
+			outstanding: false
+		}
+	}
+
+	fn matches_pong (&self, text: &str) -> bool
+	{
+		let Ok (value) = serde_json::from_str::<Value> (text)
+		else
+		{
+			return false;
+		};
+
+		match (&self . pong, &value)
+		{
+			(Value::Object (pong), Value::Object (fields)) => pong
+				. iter ()
+				. all (|(key, expected)| fields . get (key) == Some (expected)),
+			(pong, value) => pong == value
+		}
+	}
+}
+
+impl Heartbeat for JsonHeartbeat
+{
+	fn interval (&self) -> Duration
+	{
+		self . interval
+	}
+
+	fn heartbeat (&mut self) -> Option <Message>
+	{
+		if self . outstanding
+		{
+			return None;
+		}
+
+		self . outstanding = true;
+
+		Some (Message::Text (self . ping . clone () . into ()))
+	}
+
+	fn is_reply (&mut self, message: &Message) -> Result <bool, Failure>
+	{
+		let is_reply = match message
+		{
+			Message::Text (text) => self . matches_pong (text . as_str ()),
+			_ => false
+		};
+
+		if is_reply
+		{
+			self . outstanding = false;
+		}
+
+		Ok (is_reply)
+	}
+
+	fn stale_at (&self, liveness: &Liveness) -> Option <Instant>
+	{
+		Some (liveness . awaiting_reply_since ()? + self . timeout)
+	}
+}
+
+// Sends nothing, and gives up on a connection that goes quiet for longer than
+// the timeout.
+#[derive (Copy, Clone, Debug)]
+pub struct IdleTimeout
+{
+	pub timeout: Duration
+}
+
+impl IdleTimeout
+{
+	pub fn new (timeout: Duration) -> Self
+	{
+		Self {timeout}
+	}
+}
+
+impl Heartbeat for IdleTimeout
+{
+	fn interval (&self) -> Duration
+	{
+		self . timeout
+	}
+
+	fn heartbeat (&mut self) -> Option <Message>
+	{
+		None
+	}
+
+	fn is_reply (&mut self, _message: &Message) -> Result <bool, Failure>
+	{
+		Ok (false)
+	}
+
+	fn stale_at (&self, liveness: &Liveness) -> Option <Instant>
+	{
+		Some (liveness . last_message_at () + self . timeout)
+	}
+}
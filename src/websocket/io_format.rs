@@ -1,7 +1,11 @@
+use std::ops::ControlFlow;
+
 use bytes::Bytes;
 use tracing::{Level, event};
 use tungstenite::{Message, Utf8Bytes};
 
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+
 pub trait InputFormat
 {
 	type Intermediate;
@@ -9,13 +13,191 @@ pub trait InputFormat
 	fn convert (i: Self::Intermediate) -> Option <Message>;
 }
 
+// Messages that fail to convert are logged and handed to dead_letter, which
+// drops them unless the format says otherwise.
 pub trait OutputFormat
 {
 	type External;
 
-	fn convert_text (utf8_bytes: Utf8Bytes) -> Option <Self::External>;
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>;
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>;
+
+	fn dead_letter (&self, _dead_letter: DeadLetter) -> Option <Self::External>
+	{
+		None
+	}
+
+	// How many messages in a row may fail to convert before the connection
+	// fails, if there is a limit.
+	fn max_consecutive_failures (&self) -> Option <u32>
+	{
+		None
+	}
+}
+
+// A message that failed to convert, and why.
+#[derive (Clone, PartialEq, Debug)]
+pub struct DeadLetter
+{
+	pub message: Message,
+	pub failure: Failure
+}
+
+// Outputs messages that fail to convert as errors instead of dropping them.
+#[derive (Copy, Clone, Default, Debug)]
+pub struct DeadLetters <F> (pub F);
+
+impl <F> OutputFormat for DeadLetters <F>
+where F: OutputFormat
+{
+	type External = Result <F::External, DeadLetter>;
+
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
+	{
+		F::convert_text (utf8_bytes) . map (Ok)
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
+	{
+		F::convert_binary (bytes) . map (Ok)
+	}
+
+	fn dead_letter (&self, dead_letter: DeadLetter) -> Option <Self::External>
+	{
+		Some (Err (dead_letter))
+	}
+
+	fn max_consecutive_failures (&self) -> Option <u32>
+	{
+		self . 0 . max_consecutive_failures ()
+	}
+}
+
+// Fails the connection as soon as this many messages in a row fail to convert,
+// which usually means the peer's schema has changed.
+#[derive (Copy, Clone, Debug)]
+pub struct FailureLimit <F>
+{
+	pub format: F,
+	pub max_consecutive_failures: u32
+}
+
+impl <F> FailureLimit <F>
+{
+	pub fn new (format: F, max_consecutive_failures: u32) -> Self
+	{
+		Self {format, max_consecutive_failures}
+	}
+}
+
+impl <F> OutputFormat for FailureLimit <F>
+where F: OutputFormat
+{
+	type External = F::External;
+
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
+	{
+		F::convert_text (utf8_bytes)
+	}
+
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
+	{
+		F::convert_binary (bytes)
+	}
+
+	fn dead_letter (&self, dead_letter: DeadLetter) -> Option <Self::External>
+	{
+		self . format . dead_letter (dead_letter)
+	}
+
+	fn max_consecutive_failures (&self) -> Option <u32>
+	{
+		Some (self . max_consecutive_failures)
+	}
+}
 
-	fn convert_binary (bytes: Bytes) -> Option <Self::External>;
+// Converts incoming messages for an output format, counting how many in a row
+// have failed.
+pub (crate) struct Decoder <F>
+{
+	output_format: F,
+	consecutive_failures: u32
+}
+
+impl <F> Decoder <F>
+where F: OutputFormat
+{
+	pub (crate) fn new (output_format: F) -> Self
+	{
+		Self {output_format, consecutive_failures: 0}
+	}
+
+	pub (crate) fn decode (&mut self, message: Message) -> Option <F::External>
+	{
+		// Both are cheap to clone, and the original goes in any dead letter.
+		let result = match &message
+		{
+			Message::Text (text) => F::convert_text (text . clone ()),
+			Message::Binary (bytes) => F::convert_binary (bytes . clone ()),
+			_ => return None
+		};
+
+		match result
+		{
+			Ok (output) =>
+			{
+				self . consecutive_failures = 0;
+				Some (output)
+			},
+			Err (failure) =>
+			{
+				self . consecutive_failures += 1;
+
+				event!
+				(
+					Level::ERROR,
+					%failure,
+					consecutive_failures = self . consecutive_failures,
+					"failed to convert message"
+				);
+
+				let dead_letter = DeadLetter {message, failure};
+				self . output_format . dead_letter (dead_letter)
+			}
+		}
+	}
+
+	// Checked after any dead letter has been output.
+	pub (crate) fn check_failures (&self) -> ControlFlow <ExitStatus>
+	{
+		let max_failures = self . output_format . max_consecutive_failures ();
+
+		let Some (max_failures) = max_failures
+		else
+		{
+			return ControlFlow::Continue (());
+		};
+
+		if self . consecutive_failures == 0
+			|| self . consecutive_failures < max_failures
+		{
+			return ControlFlow::Continue (());
+		}
+
+		ControlFlow::Break
+		(
+			ExitStatus::spurious
+			(
+				FailureKind::Decode,
+				format!
+				(
+					"{} messages in a row failed to convert",
+					self . consecutive_failures
+				)
+			)
+		)
+	}
 }
 
 #[derive (Copy, Clone, Debug)]
@@ -35,21 +217,14 @@ impl OutputFormat for Text
 {
 	type External = Utf8Bytes;
 
-	fn convert_text (utf8_bytes: Utf8Bytes) -> Option <Self::External>
+	fn convert_text (utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
 	{
-		Some (utf8_bytes)
+		Ok (utf8_bytes)
 	}
 
-	fn convert_binary (bytes: Bytes) -> Option <Self::External>
+	fn convert_binary (_bytes: Bytes) -> Result <Self::External, Failure>
 	{
-		event!
-		(
-			Level::ERROR,
-			message_bytes = ?bytes,
-			"received binary message in text-only protocol"
-		);
-
-		None
+		Err (binary_in_text_protocol ())
 	}
 }
 
@@ -70,20 +245,31 @@ impl OutputFormat for Binary
 {
 	type External = Bytes;
 
-	fn convert_text (utf8_bytes: Utf8Bytes) -> Option <Self::External>
+	fn convert_text (_utf8_bytes: Utf8Bytes) -> Result <Self::External, Failure>
 	{
-		event!
-		(
-			Level::ERROR,
-			message_bytes = ?utf8_bytes,
-			"received text message in binary-only protocol"
-		);
-
-		None
+		Err (text_in_binary_protocol ())
 	}
 
-	fn convert_binary (bytes: Bytes) -> Option <Self::External>
+	fn convert_binary (bytes: Bytes) -> Result <Self::External, Failure>
 	{
-		Some (bytes)
+		Ok (bytes)
 	}
 }
+
+pub (crate) fn binary_in_text_protocol () -> Failure
+{
+	Failure::new
+	(
+		FailureKind::Decode,
+		"received binary message in text-only protocol"
+	)
+}
+
+pub (crate) fn text_in_binary_protocol () -> Failure
+{
+	Failure::new
+	(
+		FailureKind::Decode,
+		"received text message in binary-only protocol"
+	)
+}
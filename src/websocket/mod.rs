@@ -1,7 +1,29 @@
+use tokio::time::Duration;
+
 pub mod io_format;
 
+mod close;
+pub use close::PeerClosed;
+
+mod heartbeat;
+pub use heartbeat::{Heartbeat, IdleTimeout, JsonHeartbeat, Liveness, PingPong};
+
+mod stats;
+pub use stats::ConnectionStats;
+use stats::MeteredStream;
+
+mod deflate;
+pub use deflate::{DeflateConfig, DeflateStream};
+
 mod shuttle;
-mod keepalive;
 
 pub mod connection;
 pub mod client;
+pub mod server;
+
+#[derive (Copy, Clone, Debug)]
+pub struct PingConfig
+{
+	pub ping_interval: Duration,
+	pub ping_timeout: Duration
+}
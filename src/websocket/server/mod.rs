@@ -0,0 +1,108 @@
+mod node;
+pub use node::websocket_server_node;
+
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::PollSender;
+use tungstenite::protocol::WebSocketConfig;
+
+pub use super::PingConfig;
+use super::DeflateConfig;
+
+#[derive (Copy, Clone, Debug)]
+pub struct ServerConfig
+{
+	pub stream_config: Option <WebSocketConfig>,
+	// Accepted when clients offer it.
+	pub deflate: Option <DeflateConfig>,
+	pub disable_nagle: bool,
+	pub ping_config: Option <PingConfig>,
+	pub connection_buffer_size: usize
+}
+
+impl Default for ServerConfig
+{
+	fn default () -> Self
+	{
+		Self
+		{
+			stream_config: None,
+			deflate: None,
+			disable_nagle: false,
+			ping_config: None,
+			connection_buffer_size: 64
+		}
+	}
+}
+
+impl ServerConfig
+{
+	pub fn new () -> Self
+	{
+		Self::default ()
+	}
+
+	pub fn with_stream_config (mut self, stream_config: WebSocketConfig) -> Self
+	{
+		self . stream_config = Some (stream_config);
+		self
+	}
+
+	pub fn with_deflate (mut self, deflate_config: DeflateConfig) -> Self
+	{
+		self . deflate = Some (deflate_config);
+		self
+	}
+
+	pub fn disable_nagle (mut self) -> Self
+	{
+		self . disable_nagle = true;
+		self
+	}
+
+	pub fn with_ping_config (mut self, ping_config: PingConfig) -> Self
+	{
+		self . ping_config = Some (ping_config);
+		self
+	}
+
+	pub fn with_connection_buffer_size (mut self, connection_buffer_size: usize)
+	-> Self
+	{
+		self . connection_buffer_size = connection_buffer_size;
+		self
+	}
+}
+
+#[derive (Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ConnectionId (pub (in crate::websocket::server) u64);
+
+impl ConnectionId
+{
+	pub fn into_inner (self) -> u64
+	{
+		self . 0
+	}
+}
+
+impl Display for ConnectionId
+{
+	fn fmt (&self, f: &mut Formatter <'_>) -> std::fmt::Result
+	{
+		write! (f, "{}", self . 0)
+	}
+}
+
+// Handed out once per accepted connection.  Items fed into `input` are sent to
+// the peer, and items received from the peer come out of `output`.  Dropping
+// `input` closes the connection cleanly.
+#[derive (Debug)]
+pub struct ServerConnection <II, OE>
+{
+	pub id: ConnectionId,
+	pub peer_address: SocketAddr,
+	pub input: PollSender <II>,
+	pub output: ReceiverStream <OE>
+}
@@ -0,0 +1,284 @@
+use std::fmt::Debug;
+use std::io;
+use std::net::SocketAddr;
+
+use futures::StreamExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{Duration, Instant, sleep_until};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::accept_hdr_async_with_config;
+use tokio_util::sync::PollSender;
+use tracing::{Level, event};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+use crate::{expand_streams, service, event_loop, send};
+use crate::backoff::{Backoff, BackoffPolicy, Jitter};
+use crate::exit_status::{ExitStatus, Failure, FailureKind};
+use crate::service_handle::ServiceHandle;
+use crate::service_set::ServiceSet;
+use crate::websocket::{DeflateStream, PingPong};
+use crate::websocket::connection::{
+	websocket_node,
+	websocket_node_with_heartbeat
+};
+use crate::websocket::io_format::{InputFormat, OutputFormat};
+
+use super::{ServerConfig, ConnectionId, ServerConnection};
+
+use crate as compute_graph;
+
+#[expand_streams]
+#[service (shutdown = shutdown)]
+async fn serve_connection <IF, OF, CS>
+(
+	input_format: IF,
+	output_format: OF,
+	tcp_stream: TcpStream,
+	connection_id: ConnectionId,
+	peer_address: SocketAddr,
+	server_config: ServerConfig,
+	connections: output! (CS <- ServerConnection <IF::Intermediate, OF::External>)
+)
+-> ExitStatus
+where
+	IF: InputFormat + Send,
+	OF: OutputFormat + Send,
+	IF::Intermediate: Debug + Send,
+	OF::External: Debug + Send
+{
+	if server_config . disable_nagle
+	{
+		if let Err (io_error) = tcp_stream . set_nodelay (true)
+		{
+			event!
+			(
+				Level::WARN,
+				%connection_id,
+				%io_error,
+				"failed to disable nagle's algorithm"
+			);
+		}
+	}
+
+	let mut negotiated = None;
+
+	// Tungstenite decides the error type.
+	#[allow (clippy::result_large_err)]
+	let answer_offer = |request: &Request, mut response: Response|
+	-> Result <Response, ErrorResponse>
+	{
+		if let Some (deflate_config) = &server_config . deflate
+		{
+			negotiated = deflate_config
+				. answer (request . headers (), response . headers_mut ());
+		}
+
+		Ok (response)
+	};
+
+	let mut websocket = tokio::select!
+	{
+		biased;
+		_ = &mut shutdown => return ExitStatus::Clean,
+		accept_result = accept_hdr_async_with_config
+		(
+			DeflateStream::new (tcp_stream),
+			answer_offer,
+			server_config . stream_config
+		) => match accept_result
+		{
+			Ok (websocket) => websocket,
+			Err (ws_error) =>
+			{
+				event!
+				(
+					Level::WARN,
+					%connection_id,
+					%peer_address,
+					%ws_error,
+					"failed to complete websocket handshake"
+				);
+
+				return ExitStatus::Spurious
+				(
+					Failure::from_error (FailureKind::Connection, ws_error)
+				);
+			}
+		}
+	};
+
+	if let Some (negotiated) = negotiated
+	{
+		websocket
+			. get_mut ()
+			. enable (negotiated, server_config . stream_config);
+	}
+
+	let (input_sender, input_receiver) =
+		tokio::sync::mpsc::channel (server_config . connection_buffer_size);
+	let (output_sender, output_receiver) =
+		tokio::sync::mpsc::channel (server_config . connection_buffer_size);
+
+	let connection = ServerConnection
+	{
+		id: connection_id,
+		peer_address,
+		input: PollSender::new (input_sender),
+		output: ReceiverStream::new (output_receiver)
+	};
+
+	// Nobody is listening for new connections, so there's nobody to serve this
+	// one to either.
+	if send! (connections, connection) . is_break ()
+	{
+		return ExitStatus::Clean;
+	}
+
+	event!
+	(
+		Level::INFO,
+		%connection_id,
+		%peer_address,
+		"accepted websocket connection"
+	);
+
+	let inputs = ReceiverStream::new (input_receiver);
+	let outputs = PollSender::new (output_sender);
+
+	let mut node_handle = match server_config . ping_config
+	{
+		Some (ping_config) => websocket_node_with_heartbeat
+		(
+			input_format,
+			output_format,
+			inputs,
+			outputs,
+			websocket,
+			PingPong::from (ping_config)
+		),
+		None => websocket_node
+		(
+			input_format,
+			output_format,
+			inputs,
+			outputs,
+			websocket
+		)
+	};
+
+	let exit_status = tokio::select!
+	{
+		biased;
+		_ = &mut shutdown =>
+		{
+			node_handle . shutdown ();
+			node_handle . await
+		},
+		exit_status = &mut node_handle => exit_status
+	};
+
+	event!
+	(
+		Level::INFO,
+		%connection_id,
+		spurious = exit_status . is_spurious (),
+		"websocket connection exited"
+	);
+
+	exit_status
+}
+
+// Accept errors like running out of file descriptors tend to repeat until
+// something else closes, so accepting waits out a backoff after each one.
+async fn accept_after
+(
+	listener: &TcpListener,
+	retry_at: Option <Instant>
+)
+-> io::Result <(TcpStream, SocketAddr)>
+{
+	if let Some (retry_at) = retry_at
+	{
+		sleep_until (retry_at) . await;
+	}
+
+	listener . accept () . await
+}
+
+#[expand_streams]
+#[service (shutdown = shutdown)]
+pub async fn websocket_server_node <IF, OF, CS>
+(
+	input_format: IF,
+	output_format: OF,
+	listener: TcpListener,
+	server_config: ServerConfig,
+	connections: output! (CS <- ServerConnection <IF::Intermediate, OF::External>)
+)
+-> ExitStatus
+where
+	IF: Clone + InputFormat + Send + 'static,
+	OF: Clone + OutputFormat + Send + 'static,
+	IF::Intermediate: Debug + Send + 'static,
+	OF::External: Debug + Send + 'static,
+	CS: Clone + Send + 'static
+{
+	let mut connection_handles = ServiceSet::new ();
+	let mut next_connection_id: u64 = 0;
+
+	let mut accept_backoff = Backoff::new
+	(
+		BackoffPolicy::new (Duration::from_millis (10), Duration::from_secs (1))
+			. with_jitter (Jitter::None)
+	);
+	let mut retry_at = None;
+
+	event_loop!
+	{
+		?&mut shutdown,
+		accepted = accept_after (&listener, retry_at) => match accepted
+		{
+			Ok ((tcp_stream, peer_address)) =>
+			{
+				accept_backoff . reset ();
+				retry_at = None;
+
+				let connection_id = ConnectionId (next_connection_id);
+				next_connection_id += 1;
+
+				connection_handles . insert
+				(
+					connection_id,
+					serve_connection
+					(
+						input_format . clone (),
+						output_format . clone (),
+						tcp_stream,
+						connection_id,
+						peer_address,
+						server_config,
+						connections . clone ()
+					)
+				);
+			},
+			Err (io_error) =>
+			{
+				let retry_delay = accept_backoff . next_delay ();
+				retry_at = Some (Instant::now () + retry_delay);
+
+				event!
+				(
+					Level::WARN,
+					%io_error,
+					?retry_delay,
+					"failed to accept tcp connection"
+				);
+			}
+		},
+		_ = connection_handles . next () => ()
+	};
+
+	connection_handles . shutdown () . await;
+
+	ExitStatus::Clean
+}
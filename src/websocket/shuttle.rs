@@ -1,4 +1,7 @@
-use bytes::Bytes;
+use std::fmt::Display;
+
+use futures::{Sink, Stream, StreamExt};
+use tokio::time::{Instant, MissedTickBehavior, interval, sleep_until};
 use tracing::{Level, event};
 use tungstenite::Message;
 use tungstenite::error::Result;
@@ -10,21 +13,25 @@ use crate::{
 	check_break,
 	feed,
 	flush,
+	handle_sink_result,
 	send
 };
-use crate::exit_status::{ExitStatus, WithStatus};
+use crate::exit_status::{ExitStatus, Failure, FailureKind, WithStatus};
 
-use super::io_format::{InputFormat, OutputFormat};
+use super::client::Outbox;
+use super::close::closed_by_peer;
+use super::heartbeat::{Heartbeat, Liveness};
+use super::io_format::{Decoder, InputFormat, OutputFormat};
 
 use crate as compute_graph;
 
 #[expand_streams]
 #[service (shutdown = shutdown)]
-pub async fn shuttle_input_with_pings <F, IS, PS, WS>
+pub async fn shuttle_input_with_heartbeats <F, IS, HS, WS>
 (
 	_input_format: F,
 	inputs: input! (IS -> impl Into <F::Intermediate>),
-	ping_bytess: input! (PS -> Bytes),
+	heartbeats: input! (HS -> Message),
 	websocket: output! (WS <- Message)
 )
 -> WithStatus <WS>
@@ -33,8 +40,8 @@ where F: InputFormat
 	let status = event_loop_fallible!
 	{
 		?&mut shutdown,
-		ping_bytess -> ping_bytes =>
-			check_break! (send! (websocket?, Message::Ping (ping_bytes))),
+		heartbeats -> heartbeat =>
+			check_break! (send! (websocket?, heartbeat)),
 		inputs -> input.. =>
 		{
 			if let Some (message) = F::convert (input . into ())
@@ -48,6 +55,26 @@ where F: InputFormat
 	WithStatus::new (websocket, status)
 }
 
+// For connections that only receive, but still need to send heartbeats.
+#[expand_streams]
+#[service (shutdown = shutdown)]
+pub async fn shuttle_heartbeats <HS, WS>
+(
+	heartbeats: input! (HS -> Message),
+	websocket: output! (WS <- Message)
+)
+-> WithStatus <WS>
+{
+	let status = event_loop_fallible!
+	{
+		?&mut shutdown,
+		heartbeats -> heartbeat =>
+			check_break! (send! (websocket?, heartbeat))
+	};
+
+	WithStatus::new (websocket, status)
+}
+
 #[expand_streams]
 #[service (shutdown = shutdown)]
 pub async fn shuttle_input <F, IS, WS>
@@ -75,24 +102,142 @@ where F: InputFormat
 	WithStatus::new (websocket, status)
 }
 
-#[expand_streams]
+// Like shuttle_input, but messages stay in the outbox until they have been
+// flushed.  The outbox is drained before any more input is pulled, so it only
+// overflows while there is no connection.
 #[service (shutdown = shutdown)]
-pub async fn shuttle_output_with_pongs <F, WS, OS, PS>
+pub async fn shuttle_outbox <F, IS, WS>
 (
-	_output_format: F,
-	websocket: input! (WS -> Result <Message>),
-	outputs: output! (OS <- F::External),
-	pong_bytess: output! (PS <- Bytes)
+	_input_format: F,
+	mut inputs: IS,
+	outbox: Outbox,
+	mut websocket: WS
 )
 -> WithStatus <WS>
-where F: OutputFormat
+where
+	F: InputFormat,
+	IS: Stream + Unpin,
+	IS::Item: Into <F::Intermediate>,
+	WS: Sink <Message> + Unpin,
+	WS::Error: Display
 {
-	let status = event_loop_fallible!
+	let _sender_guard = outbox . register_sender ();
+	let mut unqueued = None;
+
+	let status = loop
 	{
-		?&mut shutdown,
-		websocket? -> message => match message
+		// Another connection sharing the outbox may have filled it since this
+		// one drained it.
+		if let Some (message) = unqueued . take ()
 		{
-			Err (ws_error) =>
+			unqueued = outbox . try_push (message) . err ();
+		}
+
+		if ! outbox . is_empty ()
+		{
+			tokio::select!
+			{
+				biased;
+				_ = &mut shutdown => break ExitStatus::Clean,
+				result = outbox . send_queued (&mut websocket) =>
+					check_break! (handle_sink_result! (?result))
+			}
+
+			continue;
+		}
+
+		tokio::select!
+		{
+			biased;
+			_ = &mut shutdown => break ExitStatus::Clean,
+			// Everything queued is in flight on the other connection.
+			_ = outbox . wait_for_room (), if unqueued . is_some () => (),
+			input = inputs . next (), if unqueued . is_none () => match input
+			{
+				Some (input) => unqueued = F::convert (input . into ()),
+				None => break ExitStatus::Clean
+			}
+		}
+	};
+
+	WithStatus::new (websocket, status)
+}
+
+// Like shuttle_output, but the heartbeat decides what to send, which incoming
+// messages are replies, and when the connection has gone stale.
+#[service (shutdown = shutdown)]
+pub async fn shuttle_output_with_heartbeat <F, H, WS, OS, HS>
+(
+	output_format: F,
+	mut heartbeat: H,
+	mut websocket: WS,
+	mut outputs: OS,
+	mut heartbeats: HS
+)
+-> WithStatus <WS>
+where
+	F: OutputFormat,
+	F::External: Send,
+	H: Heartbeat,
+	WS: Stream <Item = Result <Message>> + Unpin,
+	OS: Sink <F::External> + Unpin,
+	OS::Error: Display,
+	HS: Sink <Message> + Unpin,
+	HS::Error: Display
+{
+	let mut decoder = Decoder::new (output_format);
+	let mut liveness = Liveness::new ();
+
+	let mut heartbeat_clock = interval (heartbeat . interval ());
+	heartbeat_clock . set_missed_tick_behavior (MissedTickBehavior::Delay);
+
+	let status = loop
+	{
+		let stale_at = heartbeat . stale_at (&liveness);
+
+		let message = tokio::select!
+		{
+			biased;
+			_ = &mut shutdown => break ExitStatus::Clean,
+			_ = sleep_until (stale_at . unwrap_or_else (Instant::now)),
+				if stale_at . is_some () =>
+			{
+				event! (Level::ERROR, "websocket connection went stale");
+
+				break ExitStatus::spurious
+				(
+					FailureKind::Timeout,
+					"no heartbeat reply within timeout"
+				);
+			},
+			_ = heartbeat_clock . tick () =>
+			{
+				let Some (message) = heartbeat . heartbeat ()
+				else
+				{
+					continue;
+				};
+
+				check_break!
+				(
+					send! (heartbeats, message)
+						. map_break (|_| ExitStatus::Clean)
+				);
+
+				liveness . heartbeat_sent_at = Some (Instant::now ());
+				continue;
+			},
+			message = websocket . next () => message
+		};
+
+		let message = match message
+		{
+			None => break ExitStatus::spurious
+			(
+				FailureKind::StreamEnded,
+				"websocket"
+			),
+			Some (Err (ws_error)) =>
 			{
 				event!
 				(
@@ -101,51 +246,69 @@ where F: OutputFormat
 					"websocket connection encountered an error"
 				);
 
-				break ExitStatus::Spurious;
-			}
-			Ok (Message::Text (utf8_bytes)) =>
-			{
-				if let Some (output) = F::convert_text (utf8_bytes)
-				{
-					check_break!
-					(
-						send! (outputs, output)
-							. map_break (|_| ExitStatus::Clean)
-					);
-				}
+				break ExitStatus::Spurious
+				(
+					Failure::from_error (FailureKind::Connection, ws_error)
+				);
 			},
-			Ok (Message::Binary (bytes)) =>
+			Some (Ok (message)) => message
+		};
+
+		liveness . message_received_at = Some (Instant::now ());
+
+		match heartbeat . is_reply (&message)
+		{
+			Ok (false) => (),
+			Ok (true) =>
 			{
-				if let Some (output) = F::convert_binary (bytes)
-				{
-					check_break!
-					(
-						send! (outputs, output)
-							. map_break (|_| ExitStatus::Clean)
-					);
-				}
+				liveness . reply_received_at = liveness . message_received_at;
+				continue;
 			},
-			Ok (Message::Ping (_)) => (),
-			Ok (Message::Pong (bytes)) => check_break!
-			(
-				send! (pong_bytess, bytes) . map_break (|_| ExitStatus::Clean)
-			),
-			Ok (Message::Close (close_frame)) =>
+			Err (failure) =>
 			{
 				event!
 				(
-					Level::INFO,
-					?close_frame,
-					"websocket connection was closed before shutdown"
+					Level::ERROR,
+					%failure,
+					"received invalid heartbeat reply"
 				);
 
-				break ExitStatus::Spurious;
+				break ExitStatus::Spurious (failure);
+			}
+		}
+
+		let output = match message
+		{
+			Message::Text (_) | Message::Binary (_) =>
+				decoder . decode (message),
+			Message::Ping (_) => None,
+			Message::Pong (bytes) =>
+			{
+				event!
+				(
+					Level::WARN,
+					pong_bytes = ?bytes,
+					"received unsolicited pong frame"
+				);
+
+				None
 			},
-			Ok (Message::Frame (_)) => unreachable!
+			Message::Close (close_frame) => break closed_by_peer (close_frame),
+			Message::Frame (_) => unreachable!
 			(
 				"websocket stream returned a raw frame"
 			)
+		};
+
+		if let Some (output) = output
+		{
+			check_break!
+			(
+				send! (outputs, output) . map_break (|_| ExitStatus::Clean)
+			);
 		}
+
+		check_break! (decoder . check_failures ());
 	};
 
 	WithStatus::new (websocket, status)
@@ -155,13 +318,15 @@ where F: OutputFormat
 #[service (shutdown = shutdown)]
 pub async fn shuttle_output <F, WS, OS>
 (
-	_output_format: F,
+	output_format: F,
 	websocket: input! (WS -> Result <Message>),
 	outputs: output! (OS <- F::External)
 )
 -> WithStatus <WS>
 where F: OutputFormat
 {
+	let mut decoder = Decoder::new (output_format);
+
 	let status = event_loop_fallible!
 	{
 		?&mut shutdown,
@@ -176,22 +341,14 @@ where F: OutputFormat
 					"websocket connection encountered an error"
 				);
 
-				break ExitStatus::Spurious;
+				break ExitStatus::Spurious
+				(
+					Failure::from_error (FailureKind::Connection, ws_error)
+				);
 			}
-			Ok (Message::Text (utf8_bytes)) =>
+			Ok (message @ (Message::Text (_) | Message::Binary (_))) =>
 			{
-				if let Some (output) = F::convert_text (utf8_bytes)
-				{
-					check_break!
-					(
-						send! (outputs, output)
-							. map_break (|_| ExitStatus::Clean)
-					);
-				}
-			},
-			Ok (Message::Binary (bytes)) =>
-			{
-				if let Some (output) = F::convert_binary (bytes)
+				if let Some (output) = decoder . decode (message)
 				{
 					check_break!
 					(
@@ -199,6 +356,8 @@ where F: OutputFormat
 							. map_break (|_| ExitStatus::Clean)
 					);
 				}
+
+				check_break! (decoder . check_failures ());
 			},
 			Ok (Message::Ping (_)) => (),
 			Ok (Message::Pong (bytes)) => event!
@@ -208,16 +367,7 @@ where F: OutputFormat
 				"received unsolicited pong frame"
 			),
 			Ok (Message::Close (close_frame)) =>
-			{
-				event!
-				(
-					Level::INFO,
-					?close_frame,
-					"websocket connection was closed before shutdown"
-				);
-
-				break ExitStatus::Spurious;
-			},
+				break closed_by_peer (close_frame),
 			Ok (Message::Frame (_)) => unreachable!
 			(
 				"websocket stream returned a raw frame"
@@ -250,7 +400,10 @@ pub async fn drain_output <WS>
 					"websocket connection encountered an error"
 				);
 
-				break ExitStatus::Spurious;
+				break ExitStatus::Spurious
+				(
+					Failure::from_error (FailureKind::Connection, ws_error)
+				);
 			},
 			Ok (Message::Text (utf8_bytes)) => event!
 			(
@@ -272,16 +425,7 @@ pub async fn drain_output <WS>
 				"received unsolicited pong frame"
 			),
 			Ok (Message::Close (close_frame)) =>
-			{
-				event!
-				(
-					Level::INFO,
-					?close_frame,
-					"websocket connection was closed before shutdown"
-				);
-
-				break ExitStatus::Spurious;
-			},
+				break closed_by_peer (close_frame),
 			Ok (Message::Frame (_)) => unreachable!
 			(
 				"websocket stream returned a raw frame"
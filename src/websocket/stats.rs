@@ -0,0 +1,236 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::{Sink, Stream};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+use tracing::{Level, event};
+use tungstenite::Message;
+
+// Counters for a single connection, starting from zero each time it is
+// established.  Messages count text and binary frames, while bytes count the
+// payloads of every frame.
+#[derive (Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct ConnectionStats
+{
+	pub connected_at: Option <Instant>,
+	// As of the most recently answered ping.
+	pub round_trip_time: Option <Duration>,
+	pub missed_pongs: u64,
+	pub messages_sent: u64,
+	pub messages_received: u64,
+	pub bytes_sent: u64,
+	pub bytes_received: u64
+}
+
+impl ConnectionStats
+{
+	pub fn uptime (&self) -> Option <Duration>
+	{
+		Some (self . connected_at? . elapsed ())
+	}
+}
+
+// Traffic alone doesn't publish stats more often than this.  Pings, pongs and
+// the end of the connection always do.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis (100);
+
+// Keeps a connection's stats up to date as messages pass through it.
+#[derive (Debug)]
+pub (in crate::websocket) struct MeteredStream <S>
+{
+	inner: S,
+	stats: ConnectionStats,
+	outstanding_ping: Option <(Bytes, Instant)>,
+	stats_sender: Option <watch::Sender <ConnectionStats>>,
+	published_at: Instant
+}
+
+impl <S> MeteredStream <S>
+{
+	pub (in crate::websocket) fn new
+	(
+		inner: S,
+		stats_sender: Option <watch::Sender <ConnectionStats>>
+	)
+	-> Self
+	{
+		let connected_at = Instant::now ();
+
+		let mut metered_stream = Self
+		{
+			inner,
+			stats: ConnectionStats
+			{
+				connected_at: Some (connected_at),
+				..ConnectionStats::default ()
+			},
+			outstanding_ping: None,
+			stats_sender,
+			published_at: connected_at
+		};
+
+		metered_stream . publish ();
+		metered_stream
+	}
+
+	fn publish (&mut self)
+	{
+		if let Some (stats_sender) = &self . stats_sender
+		{
+			stats_sender . send_replace (self . stats);
+			self . published_at = Instant::now ();
+		}
+	}
+
+	fn publish_if_due (&mut self)
+	{
+		if self . published_at . elapsed () >= PUBLISH_INTERVAL
+		{
+			self . publish ();
+		}
+	}
+
+	fn record_received (&mut self, message: &Message)
+	{
+		if matches! (message, Message::Text (_) | Message::Binary (_))
+		{
+			self . stats . messages_received += 1;
+		}
+
+		self . stats . bytes_received += message . len () as u64;
+
+		if let (Message::Pong (pong_bytes), Some ((ping_bytes, sent_at))) =
+			(message, &self . outstanding_ping)
+		{
+			if ping_bytes == pong_bytes
+			{
+				let round_trip_time = sent_at . elapsed ();
+				self . stats . round_trip_time = Some (round_trip_time);
+				self . outstanding_ping = None;
+
+				event!
+				(
+					Level::DEBUG,
+					?round_trip_time,
+					"websocket pong received"
+				);
+
+				self . publish ();
+				return;
+			}
+		}
+
+		self . publish_if_due ();
+	}
+
+	fn record_sent (&mut self, message: &Message)
+	{
+		if matches! (message, Message::Text (_) | Message::Binary (_))
+		{
+			self . stats . messages_sent += 1;
+		}
+
+		self . stats . bytes_sent += message . len () as u64;
+
+		if let Message::Ping (ping_bytes) = message
+		{
+			// The previous ping was never answered.
+			if self . outstanding_ping . is_some ()
+			{
+				self . stats . missed_pongs += 1;
+			}
+
+			self . outstanding_ping =
+				Some ((ping_bytes . clone (), Instant::now ()));
+
+			self . publish ();
+			return;
+		}
+
+		self . publish_if_due ();
+	}
+}
+
+impl <S> Stream for MeteredStream <S>
+where S: Stream <Item = Result <Message, tungstenite::Error>> + Unpin
+{
+	type Item = S::Item;
+
+	fn poll_next (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Option <Self::Item>>
+	{
+		let this = self . get_mut ();
+		let poll = Pin::new (&mut this . inner) . poll_next (cx);
+
+		if let Poll::Ready (Some (Ok (message))) = &poll
+		{
+			this . record_received (message);
+		}
+
+		poll
+	}
+}
+
+impl <S> Sink <Message> for MeteredStream <S>
+where S: Sink <Message> + Unpin
+{
+	type Error = S::Error;
+
+	fn poll_ready (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Result <(), Self::Error>>
+	{
+		Pin::new (&mut self . get_mut () . inner) . poll_ready (cx)
+	}
+
+	fn start_send (self: Pin <&mut Self>, message: Message)
+	-> Result <(), Self::Error>
+	{
+		let this = self . get_mut ();
+		this . record_sent (&message);
+		Pin::new (&mut this . inner) . start_send (message)
+	}
+
+	fn poll_flush (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Result <(), Self::Error>>
+	{
+		Pin::new (&mut self . get_mut () . inner) . poll_flush (cx)
+	}
+
+	fn poll_close (self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Result <(), Self::Error>>
+	{
+		Pin::new (&mut self . get_mut () . inner) . poll_close (cx)
+	}
+}
+
+impl <S> Drop for MeteredStream <S>
+{
+	fn drop (&mut self)
+	{
+		// A ping still waiting for its pong when the connection goes counts as
+		// missed.
+		if self . outstanding_ping . take () . is_some ()
+		{
+			self . stats . missed_pongs += 1;
+		}
+
+		self . publish ();
+
+		let stats = &self . stats;
+
+		event!
+		(
+			Level::INFO,
+			uptime = ?stats . uptime (),
+			round_trip_time = ?stats . round_trip_time,
+			missed_pongs = stats . missed_pongs,
+			messages_sent = stats . messages_sent,
+			messages_received = stats . messages_received,
+			bytes_sent = stats . bytes_sent,
+			bytes_received = stats . bytes_received,
+			"websocket connection ended"
+		);
+	}
+}
@@ -0,0 +1,91 @@
+use compute_graph::backoff::{Backoff, BackoffPolicy, Jitter};
+use tokio::time::Duration;
+
+fn policy (jitter: Jitter) -> BackoffPolicy
+{
+	BackoffPolicy::new (Duration::from_millis (100), Duration::from_secs (1))
+		. with_jitter (jitter)
+}
+
+#[test]
+fn exponential_without_jitter ()
+{
+	let mut backoff = Backoff::new (policy (Jitter::None));
+
+	let delays: Vec <Duration> = (0..6) . map (|_| backoff . next_delay ()) . collect ();
+
+	assert_eq!
+	(
+		delays,
+		vec!
+		[
+			Duration::from_millis (100),
+			Duration::from_millis (200),
+			Duration::from_millis (400),
+			Duration::from_millis (800),
+			Duration::from_secs (1),
+			Duration::from_secs (1)
+		]
+	);
+
+	backoff . reset ();
+
+	assert_eq! (backoff . attempts (), 0);
+	assert_eq! (backoff . next_delay (), Duration::from_millis (100));
+}
+
+#[test]
+fn jitter_stays_in_bounds ()
+{
+	let mut full = Backoff::new (policy (Jitter::Full));
+	let mut equal = Backoff::new (policy (Jitter::Equal));
+	let mut decorrelated = Backoff::new (policy (Jitter::Decorrelated));
+
+	for attempt in 0..20
+	{
+		let cap = (Duration::from_millis (100) * 2u32 . pow (attempt . min (10)))
+			. min (Duration::from_secs (1));
+
+		let full_delay = full . next_delay ();
+		assert! (full_delay <= cap);
+
+		let equal_delay = equal . next_delay ();
+		assert! (equal_delay >= cap / 2 && equal_delay <= cap);
+
+		let decorrelated_delay = decorrelated . next_delay ();
+		assert! (decorrelated_delay >= Duration::from_millis (100));
+		assert! (decorrelated_delay <= Duration::from_secs (1));
+	}
+}
+
+#[test]
+fn unusable_multipliers_dont_panic ()
+{
+	let max_delay = Duration::from_secs (1);
+
+	for multiplier in [-2.0, 0.5, f64::NAN, f64::INFINITY, 1e300]
+	{
+		let mut backoff = Backoff::new
+		(
+			policy (Jitter::None) . with_multiplier (multiplier)
+		);
+
+		let delays: Vec <Duration> =
+			(0..4) . map (|_| backoff . next_delay ()) . collect ();
+
+		assert_eq! (delays [0], Duration::from_millis (100));
+		assert! (delays . iter () . all (|delay| *delay <= max_delay));
+		assert! (delays . windows (2) . all (|pair| pair [0] <= pair [1]));
+	}
+
+	// A zero initial delay times an infinite multiplier is NaN.
+	let mut backoff = Backoff::new
+	(
+		BackoffPolicy::new (Duration::ZERO, max_delay)
+			. with_multiplier (f64::INFINITY)
+			. with_jitter (Jitter::None)
+	);
+
+	backoff . next_delay ();
+	assert_eq! (backoff . next_delay (), max_delay);
+}
@@ -0,0 +1,147 @@
+#![cfg (any (feature = "msgpack", feature = "cbor", feature = "postcard"))]
+
+use std::fmt::Debug;
+
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::connection::websocket_node;
+use compute_graph::websocket::io_format::{InputFormat, OutputFormat};
+use futures::{SinkExt, StreamExt};
+use futures::stream::{iter, pending};
+use tokio::io::duplex;
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::protocol::Role;
+
+type Item = (String, u32, Vec <i64>);
+
+fn item () -> Item
+{
+	("reading" . to_string (), 7, vec! [-1, 0, 1])
+}
+
+// Sends an item through a websocket node to a peer that echoes it back.
+async fn round_trip <F> (format: F)
+where F: InputFormat <Intermediate = Item>
+	+ OutputFormat <External = Item>
+	+ Copy
+	+ Send
+	+ 'static
+{
+	let (client_io, server_io) = duplex (4096);
+	let client_websocket =
+		WebSocketStream::from_raw_socket (client_io, Role::Client, None) . await;
+	let mut server_websocket =
+		WebSocketStream::from_raw_socket (server_io, Role::Server, None) . await;
+
+	let (output_sink, mut output_stream) = mpsc::<Item> (1);
+
+	let mut node_handle = websocket_node
+	(
+		format,
+		format,
+		iter ([item ()]) . chain (pending ()),
+		output_sink,
+		client_websocket
+	);
+
+	let message = server_websocket . next () . await . unwrap () . unwrap ();
+	assert! (message . is_binary ());
+	server_websocket . send (message) . await . unwrap ();
+
+	let output = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (output, item ());
+
+	node_handle . shutdown ();
+	timeout (Duration::from_secs (1), node_handle) . await . unwrap ();
+}
+
+fn rejects_text <F> (_format: F)
+where F: OutputFormat <External: Debug>
+{
+	assert! (F::convert_text ("[\"reading\",7,[-1,0,1]]" . into ()) . is_err ());
+}
+
+#[cfg (feature = "msgpack")]
+#[tokio::main]
+#[test]
+async fn msgpack_round_trips ()
+{
+	use compute_graph::msgpack::{
+		MessagePack,
+		msgpack_deserialize,
+		msgpack_serialize
+	};
+
+	round_trip (MessagePack::<Item>::default ()) . await;
+	rejects_text (MessagePack::<Item>::default ());
+
+	let (bytes_sink, bytes_stream) = mpsc (1);
+	let (item_sink, mut item_stream) = mpsc::<Item> (1);
+
+	let _serialize_handle = msgpack_serialize (iter ([item ()]), bytes_sink);
+	let _deserialize_handle = msgpack_deserialize
+	(
+		MessagePack::<Item>::default (),
+		bytes_stream,
+		item_sink
+	);
+
+	assert_eq! (item_stream . next () . await, Some (item ()));
+}
+
+#[cfg (feature = "cbor")]
+#[tokio::main]
+#[test]
+async fn cbor_round_trips ()
+{
+	use compute_graph::cbor::{CBOR, cbor_deserialize, cbor_serialize};
+
+	round_trip (CBOR::<Item>::default ()) . await;
+	rejects_text (CBOR::<Item>::default ());
+
+	let (bytes_sink, bytes_stream) = mpsc (1);
+	let (item_sink, mut item_stream) = mpsc::<Item> (1);
+
+	let _serialize_handle = cbor_serialize (iter ([item ()]), bytes_sink);
+	let _deserialize_handle = cbor_deserialize
+	(
+		CBOR::<Item>::default (),
+		bytes_stream,
+		item_sink
+	);
+
+	assert_eq! (item_stream . next () . await, Some (item ()));
+}
+
+#[cfg (feature = "postcard")]
+#[tokio::main]
+#[test]
+async fn postcard_round_trips ()
+{
+	use compute_graph::postcard::{
+		Postcard,
+		postcard_deserialize,
+		postcard_serialize
+	};
+
+	round_trip (Postcard::<Item>::default ()) . await;
+	rejects_text (Postcard::<Item>::default ());
+
+	let (bytes_sink, bytes_stream) = mpsc (1);
+	let (item_sink, mut item_stream) = mpsc::<Item> (1);
+
+	let _serialize_handle = postcard_serialize (iter ([item ()]), bytes_sink);
+	let _deserialize_handle = postcard_deserialize
+	(
+		Postcard::<Item>::default (),
+		bytes_stream,
+		item_sink
+	);
+
+	assert_eq! (item_stream . next () . await, Some (item ()));
+}
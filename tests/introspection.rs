@@ -0,0 +1,157 @@
+use compute_graph::{join_services, service, task};
+use compute_graph::exit_status::{ExitStatus, FailureKind};
+use compute_graph::introspection::{self, NodeKind, NodeState};
+use compute_graph::service_handle::ServiceHandle;
+use futures::future::ready;
+use tokio::time::{Duration, sleep};
+
+#[service (shutdown = shutdown)]
+async fn decoder () -> ExitStatus
+{
+	let _ = shutdown . await;
+	ExitStatus::Clean
+}
+
+#[service]
+async fn persister () -> ExitStatus
+{
+	ExitStatus::spurious (FailureKind::Sink, "disk full")
+}
+
+#[service]
+async fn archiver () -> ExitStatus
+{
+	ExitStatus::Clean
+}
+
+#[service]
+async fn indexer () -> ExitStatus
+{
+	ExitStatus::spurious (FailureKind::Sink, "index full")
+}
+
+#[task]
+async fn lookup () -> u32
+{
+	7
+}
+
+#[service (shutdown = shutdown)]
+async fn pipeline () -> ExitStatus
+{
+	assert_eq! (lookup () . await, 7);
+	assert! (persister () . await . is_spurious ());
+
+	let (indexer_report,) = join_services! (indexer ());
+	assert! (indexer_report . is_spurious ());
+
+	let (decoder_report, archiver_report) =
+		join_services! (?shutdown, decoder (), ready (archiver ()) . await);
+
+	decoder_report . and (archiver_report)
+}
+
+// The registry is global, so everything is checked in a single test.
+#[tokio::main]
+#[test]
+async fn records_service_graph ()
+{
+	// Nothing is recorded until the registry is enabled.
+	let _ = persister () . await;
+	assert! (introspection::snapshot () . is_empty ());
+
+	introspection::enable ();
+
+	let mut pipeline_handle = pipeline ();
+	sleep (Duration::from_millis (20)) . await;
+
+	let nodes = introspection::snapshot ();
+	let node =
+		|name| nodes . iter () . find (|node| node . name == name) . unwrap ();
+
+	let pipeline_node = node ("pipeline");
+	let lookup_node = node ("lookup");
+	let join_node = nodes
+		. iter ()
+		. find
+		(
+			|node|
+				node . kind == NodeKind::Join
+					&& node . state == NodeState::Running
+		)
+		. unwrap ();
+	let indexer_node = node ("indexer");
+	let indexer_join_node = nodes
+		. iter ()
+		. find (|node| Some (node . id) == indexer_node . parent)
+		. unwrap ();
+	let decoder_node = node ("decoder");
+	let persister_node = node ("persister");
+	let archiver_node = node ("archiver");
+
+	assert_eq! (pipeline_node . parent, None);
+	assert_eq! (pipeline_node . state, NodeState::Running);
+	assert_eq! (lookup_node . kind, NodeKind::Task);
+	assert_eq! (lookup_node . parent, Some (pipeline_node . id));
+	assert_eq! (lookup_node . state, NodeState::Exited (None));
+	assert_eq! (join_node . parent, Some (pipeline_node . id));
+	assert_eq! (decoder_node . parent, Some (join_node . id));
+	assert_eq! (archiver_node . parent, Some (join_node . id));
+	assert_eq!
+	(
+		archiver_node . state,
+		NodeState::Exited (Some (ExitStatus::Clean))
+	);
+	assert_eq! (persister_node . parent, Some (pipeline_node . id));
+	assert_eq!
+	(
+		persister_node . state,
+		NodeState::Exited
+		(
+			Some (ExitStatus::spurious (FailureKind::Sink, "disk full"))
+		)
+	);
+
+	assert_eq! (indexer_join_node . kind, NodeKind::Join);
+	assert_eq!
+	(
+		indexer_join_node . state,
+		NodeState::Exited
+		(
+			Some (ExitStatus::spurious (FailureKind::Sink, "index full"))
+		)
+	);
+
+	let tree = introspection::dump_tree ();
+	assert! (tree . starts_with ("pipeline "));
+	assert! (tree . contains ("\n    decoder "));
+
+	let dot = introspection::dump_dot ();
+	assert! (dot . starts_with ("digraph services {"));
+	assert!
+	(
+		dot . contains
+		(
+			&format!
+			(
+				"n{} -> n{};",
+				join_node . id . into_inner (),
+				decoder_node . id . into_inner ()
+			)
+		)
+	);
+
+	pipeline_handle . shutdown ();
+	assert! (pipeline_handle . await . is_clean ());
+
+	let join_state = introspection::snapshot ()
+		. into_iter ()
+		. find (|node| node . id == join_node . id)
+		. unwrap ()
+		. state;
+
+	assert_eq! (join_state, NodeState::Exited (Some (ExitStatus::Clean)));
+
+	introspection::disable ();
+	assert! (introspection::snapshot () . is_empty ());
+}
@@ -0,0 +1,290 @@
+use compute_graph::exit_status::{Failure, FailureKind};
+use compute_graph::json_rpc::{Dispatcher, ErrorObject, JsonRpcPeer, Request};
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use futures::{SinkExt, StreamExt};
+use futures::sink::drain;
+use serde_json::{Value, json};
+use tokio::io::{DuplexStream, duplex};
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::Message;
+use tungstenite::protocol::Role;
+
+async fn websocket_pair ()
+-> (WebSocketStream <DuplexStream>, WebSocketStream <DuplexStream>)
+{
+	let (client_io, server_io) = duplex (4096);
+
+	(
+		WebSocketStream::from_raw_socket (client_io, Role::Client, None) . await,
+		WebSocketStream::from_raw_socket (server_io, Role::Server, None) . await
+	)
+}
+
+fn calculator () -> Dispatcher
+{
+	Dispatcher::new ()
+		. with_method
+		(
+			"add",
+			|params| async move
+			{
+				let [a, b]: [i64; 2] = serde_json::from_value
+				(
+					params . unwrap_or_default ()
+				)
+					. map_err
+				(
+					|error| ErrorObject::invalid_params (error . to_string ())
+				)?;
+
+				Ok (json! (a + b))
+			}
+		)
+		. with_method
+		(
+			"divide_by_zero",
+			|_| async
+			{
+				let error = ErrorObject::new (-1, "division by zero");
+				Err (error . with_data (json! (0)))
+			}
+		)
+}
+
+fn remote_error (failure: &Failure) -> &ErrorObject
+{
+	assert_eq! (failure . kind (), FailureKind::Remote);
+	ErrorObject::from_failure (failure) . unwrap ()
+}
+
+async fn next_reply (websocket: &mut WebSocketStream <DuplexStream>) -> Value
+{
+	match timeout (Duration::from_secs (1), websocket . next ()) . await
+	{
+		Ok (Some (Ok (Message::Text (text)))) =>
+			serde_json::from_str (text . as_str ()) . unwrap (),
+		other => panic! ("unexpected message: {:?}", other)
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn calls_are_dispatched_to_handlers ()
+{
+	let (client_websocket, server_websocket) = websocket_pair () . await;
+
+	let mut server_handle = JsonRpcPeer::new (calculator ())
+		. start (server_websocket, drain ());
+
+	let client_peer = JsonRpcPeer::new (Dispatcher::new ());
+	let client = client_peer . client ();
+	let mut client_handle = client_peer . start (client_websocket, drain ());
+
+	let sum: i64 = client . call ("add", [1, 2]) . await . unwrap ();
+	assert_eq! (sum, 3);
+
+	let failure = client
+		. call::<_, Value> ("divide_by_zero", ())
+		. await
+		. unwrap_err ();
+
+	let error = remote_error (&failure);
+	assert_eq! (error . code, -1);
+	assert_eq! (error . data, Some (json! (0)));
+
+	let failure =
+		client . call::<_, Value> ("add", ["one"]) . await . unwrap_err ();
+	assert_eq! (remote_error (&failure) . code, ErrorObject::INVALID_PARAMS);
+
+	let failure =
+		client . call::<_, Value> ("subtract", ()) . await . unwrap_err ();
+	assert_eq! (remote_error (&failure) . code, ErrorObject::METHOD_NOT_FOUND);
+
+	let failure = client . call::<_, Value> ("add", 1) . await . unwrap_err ();
+	assert_eq! (failure . kind (), FailureKind::Encode);
+
+	client_handle . shutdown ();
+	timeout (Duration::from_secs (1), client_handle) . await . unwrap ();
+	server_handle . shutdown ();
+	timeout (Duration::from_secs (1), server_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn batches_and_notifications ()
+{
+	let (client_websocket, server_websocket) = websocket_pair () . await;
+	let (logged_sender, mut logged) = tokio::sync::mpsc::channel (1);
+
+	let dispatcher = calculator () . with_method
+	(
+		"log",
+		move |params|
+		{
+			let logged_sender = logged_sender . clone ();
+
+			async move
+			{
+				logged_sender . send (params) . await . unwrap ();
+				Ok (Value::Null)
+			}
+		}
+	);
+
+	let server_peer = JsonRpcPeer::new (dispatcher);
+	let server = server_peer . client ();
+	let mut server_handle = server_peer . start (server_websocket, drain ());
+
+	let (notification_sink, mut notifications) = mpsc::<Request> (1);
+	let client_peer = JsonRpcPeer::new (Dispatcher::new ());
+	let client = client_peer . client ();
+	let mut client_handle =
+		client_peer . start (client_websocket, notification_sink);
+
+	let results = client
+		. batch::<_, _, i64> ([("add", [1, 2]), ("sub", [3, 4]), ("add", [5, 6])])
+		. await
+		. unwrap ();
+
+	assert_eq! (results . len (), 3);
+	assert_eq! (results [0], Ok (3));
+	let failure = results [1] . as_ref () . unwrap_err ();
+	assert_eq! (remote_error (failure) . code, ErrorObject::METHOD_NOT_FOUND);
+	assert_eq! (results [2], Ok (11));
+
+	let failure = client
+		. batch::<_, _, i64> ([("add", json! ([1, 2])), ("add", json! (1))])
+		. await
+		. unwrap_err ();
+
+	assert_eq! (failure . kind (), FailureKind::Encode);
+
+	client . notify ("log", ["hello"]) . await . unwrap ();
+
+	let params = timeout (Duration::from_secs (1), logged . recv ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (params, Some (json! (["hello"])));
+
+	// The client has no handler for these, so they go to its output.
+	server . notify ("tick", json! ({"height": 1})) . await . unwrap ();
+
+	let notification = timeout (Duration::from_secs (1), notifications . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (notification . method, "tick");
+	assert_eq! (notification . params, Some (json! ({"height": 1})));
+
+	client_handle . shutdown ();
+	timeout (Duration::from_secs (1), client_handle) . await . unwrap ();
+	server_handle . shutdown ();
+	timeout (Duration::from_secs (1), server_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn invalid_requests_get_error_responses ()
+{
+	let (mut client_websocket, server_websocket) = websocket_pair () . await;
+
+	let mut server_handle = JsonRpcPeer::new (calculator ())
+		. start (server_websocket, drain ());
+
+	for (request, id) in
+	[
+		(json! ([]), Value::Null),
+		(json! ({"jsonrpc": "1.0", "method": "add", "id": 1}), json! (1)),
+		(json! ({"jsonrpc": "2.0", "method": 7, "id": "a"}), json! ("a")),
+		(json! ({"jsonrpc": "2.0", "method": "add", "params": 1}), Value::Null)
+	]
+	{
+		let request = Message::text (request . to_string ());
+		client_websocket . send (request) . await . unwrap ();
+
+		let reply = next_reply (&mut client_websocket) . await;
+		assert_eq! (reply ["id"], id);
+		assert_eq! (reply ["error"] ["code"], ErrorObject::INVALID_REQUEST);
+	}
+
+	client_websocket
+		. send (Message::text ("{\"jsonrpc\": "))
+		. await
+		. unwrap ();
+
+	let reply = next_reply (&mut client_websocket) . await;
+	assert_eq! (reply ["id"], Value::Null);
+	assert_eq! (reply ["error"] ["code"], ErrorObject::PARSE_ERROR);
+
+	// Notifications in a batch get no responses.
+	let batch = json!
+	([
+		{"jsonrpc": "2.0", "method": "add", "params": [1, 1]},
+		{"jsonrpc": "2.0", "method": "add", "params": [2, 2], "id": 9},
+		1
+	]);
+
+	client_websocket
+		. send (Message::text (batch . to_string ()))
+		. await
+		. unwrap ();
+
+	let reply = next_reply (&mut client_websocket) . await;
+
+	assert_eq!
+	(
+		reply,
+		json!
+		([
+			{"jsonrpc": "2.0", "result": 4, "id": 9},
+			{
+				"jsonrpc": "2.0",
+				"error": {"code": -32600, "message": "expected an object"},
+				"id": null
+			}
+		])
+	);
+
+	server_handle . shutdown ();
+	timeout (Duration::from_secs (1), server_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn pending_calls_fail_when_peer_disconnects ()
+{
+	let (client_websocket, server_websocket) = websocket_pair () . await;
+
+	let client_peer = JsonRpcPeer::new (Dispatcher::new ());
+	let client = client_peer . client ();
+	let client_handle = client_peer . start (client_websocket, drain ());
+
+	let call = tokio::spawn
+	(
+		async move { client . call::<_, Value> ("add", [1, 2]) . await }
+	);
+
+	// The server reads the request, then hangs up without answering.
+	let mut server_websocket = server_websocket;
+	next_reply (&mut server_websocket) . await;
+	drop (server_websocket);
+
+	let failure = timeout (Duration::from_secs (1), call)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. unwrap_err ();
+
+	assert_eq! (failure . kind (), FailureKind::Connection);
+
+	let exit_status = timeout (Duration::from_secs (1), client_handle)
+		. await
+		. unwrap ();
+
+	assert! (! exit_status . is_clean ());
+}
@@ -0,0 +1,138 @@
+#![cfg (feature = "protobuf")]
+
+use bytes::Bytes;
+use compute_graph::protobuf::{
+	DelimitedProtobuf,
+	Protobuf,
+	protobuf_decode,
+	protobuf_decode_delimited,
+	protobuf_encode,
+	protobuf_encode_delimited
+};
+use compute_graph::exit_status::FailureKind;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::connection::websocket_node;
+use compute_graph::websocket::io_format::{
+	DeadLetter,
+	DeadLetters,
+	OutputFormat
+};
+use futures::{SinkExt, StreamExt};
+use futures::stream::{iter, pending};
+use tokio::io::duplex;
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::protocol::Role;
+
+#[derive (Clone, PartialEq, prost::Message)]
+struct Reading
+{
+	#[prost (string, tag = "1")]
+	sensor: String,
+	#[prost (sint64, tag = "2")]
+	value: i64
+}
+
+fn reading (value: i64) -> Reading
+{
+	Reading {sensor: "thermometer" . to_string (), value}
+}
+
+#[tokio::main]
+#[test]
+async fn delimited_frames_round_trip_over_websocket ()
+{
+	let (client_io, server_io) = duplex (4096);
+	let client_websocket =
+		WebSocketStream::from_raw_socket (client_io, Role::Client, None) . await;
+	let mut server_websocket =
+		WebSocketStream::from_raw_socket (server_io, Role::Server, None) . await;
+
+	let (output_sink, mut output_stream) = mpsc::<Vec <Reading>> (1);
+	let readings = vec! [reading (-3), reading (0), reading (21)];
+
+	let mut node_handle = websocket_node
+	(
+		DelimitedProtobuf::<Reading>::default (),
+		DelimitedProtobuf::<Reading>::default (),
+		iter ([readings . clone ()]) . chain (pending ()),
+		output_sink,
+		client_websocket
+	);
+
+	// The peer receives them all in one binary frame and echoes it.
+	let message = server_websocket . next () . await . unwrap () . unwrap ();
+	assert! (message . is_binary ());
+	server_websocket . send (message) . await . unwrap ();
+
+	let output = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (output, readings);
+
+	node_handle . shutdown ();
+	timeout (Duration::from_secs (1), node_handle) . await . unwrap ();
+
+	assert! (Protobuf::<Reading>::convert_text ("{}" . into ()) . is_err ());
+}
+
+#[tokio::main]
+#[test]
+async fn services_encode_and_decode ()
+{
+	let (bytes_sink, bytes_stream) = mpsc (1);
+	let (item_sink, mut item_stream) = mpsc::<Reading> (1);
+
+	let _encode_handle = protobuf_encode (iter ([reading (1)]), bytes_sink);
+	let _decode_handle =
+		protobuf_decode (Protobuf::default (), bytes_stream, item_sink);
+
+	assert_eq! (item_stream . next () . await, Some (reading (1)));
+
+	// Frames that fail to decode can be kept as dead letters.
+	let (item_sink, mut item_stream) =
+		mpsc::<Result <Reading, DeadLetter>> (1);
+
+	let frames =
+	[
+		Bytes::from_static (b"\xff"),
+		Bytes::from (prost::Message::encode_to_vec (&reading (4)))
+	];
+
+	let _decode_handle = protobuf_decode
+	(
+		DeadLetters (Protobuf::default ()),
+		iter (frames),
+		item_sink
+	);
+
+	let dead_letter = item_stream . next () . await . unwrap () . unwrap_err ();
+	assert_eq! (dead_letter . failure . kind (), FailureKind::Decode);
+	assert_eq! (item_stream . next () . await, Some (Ok (reading (4))));
+
+	let (bytes_sink, mut bytes_stream) = mpsc::<Bytes> (1);
+	let (item_sink, mut item_stream) = mpsc::<Reading> (1);
+
+	let _encode_handle = protobuf_encode_delimited
+	(
+		iter ([vec! [reading (2), reading (3)]]),
+		bytes_sink
+	);
+
+	let items_bytes = bytes_stream . next () . await . unwrap ();
+
+	// A truncated frame is dropped whole, and the next one still decodes.
+	let truncated_bytes = items_bytes . slice (.. items_bytes . len () - 1);
+
+	let _decode_handle = protobuf_decode_delimited
+	(
+		iter ([truncated_bytes, items_bytes]),
+		item_sink
+	);
+
+	assert_eq! (item_stream . next () . await, Some (reading (2)));
+	assert_eq! (item_stream . next () . await, Some (reading (3)));
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use compute_graph::{service, task};
+use compute_graph::backoff::{BackoffPolicy, Jitter};
+use compute_graph::exit_status::{ExitStatus, FailureKind};
+use compute_graph::robust_service::{
+	Construction,
+	RestartPolicy,
+	RobustServiceConfig,
+	SignallableFallibleServiceFactory,
+	SignallableRobustService
+};
+use compute_graph::service_handle::{CancellableServiceHandle, ServiceHandle};
+use compute_graph::service_state::{ServiceState, ServiceStatus};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant, sleep, timeout};
+
+#[service]
+async fn crash () -> ExitStatus
+{
+	ExitStatus::spurious (FailureKind::Other, "crashed")
+}
+
+#[service]
+async fn idle () -> ExitStatus
+{
+	sleep (Duration::from_secs (60)) . await;
+	ExitStatus::Clean
+}
+
+struct Factory
+{
+	constructions: Arc <AtomicU32>,
+	crashing: bool
+}
+
+impl SignallableFallibleServiceFactory for Factory
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <CancellableServiceHandle <ExitStatus>>
+	{
+		self . constructions . fetch_add (1, Ordering::SeqCst);
+
+		match self . crashing
+		{
+			true => Construction::Built (crash ()),
+			false => Construction::Built (idle ())
+		}
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn restart_budget_is_enforced ()
+{
+	let constructions = Arc::new (AtomicU32::new (0));
+
+	let restart_policy = RestartPolicy::new ()
+		. with_backoff
+		(
+			BackoffPolicy::new
+			(
+				Duration::from_millis (10),
+				Duration::from_millis (40)
+			)
+				. with_jitter (Jitter::None)
+		)
+		. with_budget (3, Duration::from_secs (60));
+
+	let started_at = Instant::now ();
+
+	let exit_status = timeout
+	(
+		Duration::from_secs (5),
+		Factory {constructions: constructions . clone (), crashing: true}
+			. into_robust_service_with_config
+			(
+				RobustServiceConfig::new () . with_restart_policy (restart_policy)
+			)
+	)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_spurious ());
+	assert_eq! (constructions . load (Ordering::SeqCst), 4);
+	assert! (started_at . elapsed () >= Duration::from_millis (10 + 20 + 40));
+}
+
+#[tokio::main]
+#[test]
+async fn shutdown_is_clean ()
+{
+	let constructions = Arc::new (AtomicU32::new (0));
+
+	let mut robust_handle =
+		Factory {constructions: constructions . clone (), crashing: false}
+			. into_robust_service ();
+
+	sleep (Duration::from_millis (50)) . await;
+
+	robust_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), robust_handle)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_clean ());
+	assert_eq! (constructions . load (Ordering::SeqCst), 1);
+}
+
+#[tokio::main]
+#[test]
+async fn status_is_reported ()
+{
+	let constructions = Arc::new (AtomicU32::new (0));
+
+	let (status_sender, mut status_receiver) =
+		watch::channel (ServiceStatus::default ());
+
+	let restart_policy = RestartPolicy::new ()
+		. with_backoff
+		(
+			BackoffPolicy::new
+			(
+				Duration::from_millis (10),
+				Duration::from_millis (10)
+			)
+				. with_jitter (Jitter::None)
+		)
+		. with_budget (1, Duration::from_secs (60));
+
+	let robust_handle =
+		Factory {constructions: constructions . clone (), crashing: true}
+			. into_robust_service_with_config
+			(
+				RobustServiceConfig::new ()
+					. with_restart_policy (restart_policy)
+					. with_status_reporting (status_sender)
+			);
+
+	let mut states = Vec::new ();
+
+	while status_receiver . changed () . await . is_ok ()
+	{
+		let status = status_receiver . borrow_and_update () . clone ();
+		states . push (status . state);
+
+		if status . state == ServiceState::Failed
+		{
+			assert_eq! (status . restart_count, 1);
+			assert_eq!
+			(
+				status . last_exit_status,
+				Some (ExitStatus::spurious (FailureKind::Other, "crashed"))
+			);
+		}
+	}
+
+	let exit_status = robust_handle . await;
+	let failure = exit_status . failure () . unwrap ();
+
+	assert_eq! (failure . kind (), FailureKind::RestartBudget);
+	assert_eq!
+	(
+		failure . error () . unwrap () . to_string (),
+		"failure: crashed"
+	);
+
+	// The watch channel may coalesce intermediate states, but it always ends
+	// up failed.
+	assert_eq! (states . last (), Some (&ServiceState::Failed));
+}
+
+#[tokio::main]
+#[test]
+async fn replacements_count_against_restart_budget ()
+{
+	let constructions = Arc::new (AtomicU32::new (0));
+
+	let exit_status = timeout
+	(
+		Duration::from_secs (5),
+		Factory {constructions: constructions . clone (), crashing: false}
+			. into_robust_service_with_config
+			(
+				RobustServiceConfig::new ()
+					. with_preemptive_replacement (Duration::from_millis (10))
+					. with_restart_policy
+					(
+						RestartPolicy::new ()
+							. with_budget (2, Duration::from_secs (60))
+					)
+			)
+	)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+
+	assert_eq! (failure . kind (), FailureKind::RestartBudget);
+	assert_eq! (constructions . load (Ordering::SeqCst), 3);
+}
+
+struct GivingUpFactory;
+
+impl SignallableFallibleServiceFactory for GivingUpFactory
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <CancellableServiceHandle <ExitStatus>>
+	{
+		Construction::GaveUp
+		(
+			ExitStatus::spurious (FailureKind::Connection, "feed unreachable")
+		)
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn constructor_giving_up_is_reported ()
+{
+	let exit_status = timeout
+	(
+		Duration::from_secs (1),
+		GivingUpFactory . into_robust_service ()
+	)
+		. await
+		. unwrap ();
+
+	assert_eq!
+	(
+		exit_status,
+		ExitStatus::spurious (FailureKind::Connection, "feed unreachable")
+	);
+}
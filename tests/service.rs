@@ -1,5 +1,6 @@
-use compute_graph::service;
+use compute_graph::{join_services, service};
 use compute_graph::service_handle::ServiceHandle;
+use futures::future::ready;
 use tokio::time::{error::Elapsed, Duration, sleep, timeout};
 
 #[service (shutdown = shutdown)]
@@ -62,3 +63,31 @@ async fn multi_service () -> Result <(), Elapsed>
 		}
 	) . await
 }
+
+#[tokio::main]
+#[test]
+async fn join_services_await_and_propagate_errors () -> Result <(), Elapsed>
+{
+	join_services!
+	(
+		timeout (Duration::from_millis (200), ready (may_be_cancelled ()))
+			. await?
+	);
+
+	let failed: Result <(), Elapsed> = async
+	{
+		join_services!
+		(
+			timeout (Duration::ZERO, sleep (Duration::from_secs (1)))
+				. await
+				. map (|_| may_be_cancelled ())?
+		);
+
+		Ok (())
+	}
+		. await;
+
+	assert! (failed . is_err ());
+
+	Ok (())
+}
@@ -0,0 +1,58 @@
+use compute_graph::service;
+use compute_graph::exit_status::{ExitStatus, FailureKind};
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::service_set::ServiceSet;
+use futures::StreamExt;
+use tokio::time::{Duration, sleep, timeout};
+
+#[service (shutdown = shutdown)]
+async fn subscription (lifetime: Duration) -> ExitStatus
+{
+	tokio::select!
+	{
+		_ = shutdown => ExitStatus::Clean,
+		_ = sleep (lifetime) =>
+			ExitStatus::spurious (FailureKind::Closed, "subscription ended")
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn yields_services_as_they_exit ()
+{
+	let mut subscriptions = ServiceSet::new ();
+
+	subscriptions . insert ("ETH", subscription (Duration::from_millis (40)));
+	subscriptions . insert ("BTC", subscription (Duration::from_millis (10)));
+
+	let (instrument, exit_status) = subscriptions . next () . await . unwrap ();
+	assert_eq! (instrument, "BTC");
+	assert! (exit_status . is_spurious ());
+
+	let (instrument, _) = subscriptions . next () . await . unwrap ();
+	assert_eq! (instrument, "ETH");
+	assert! (subscriptions . is_empty ());
+}
+
+#[tokio::main]
+#[test]
+async fn shutdown_waits_for_every_service ()
+{
+	let mut subscriptions = ServiceSet::new ();
+
+	subscriptions . insert ("BTC", subscription (Duration::from_secs (60)));
+	subscriptions . insert ("ETH", subscription (Duration::from_secs (60)));
+	subscriptions . insert ("SOL", subscription (Duration::from_secs (60)));
+
+	let mut removed = subscriptions . remove (&"SOL") . unwrap ();
+	removed . shutdown ();
+	assert! (removed . await . is_clean ());
+
+	let outputs = timeout (Duration::from_secs (1), subscriptions . shutdown ())
+		. await
+		. unwrap ();
+
+	assert_eq! (outputs . len (), 2);
+	assert! (outputs . values () . all (ExitStatus::is_clean));
+	assert! (subscriptions . is_empty ());
+}
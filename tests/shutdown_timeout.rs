@@ -0,0 +1,182 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use compute_graph::{join_services, service, task};
+use compute_graph::exit_status::{ExitStatus, ServiceExitStatus};
+use compute_graph::robust_service::{
+	Construction,
+	RobustServiceConfig,
+	SignallableFallibleServiceFactory,
+	SignallableRobustService
+};
+use compute_graph::service_handle::{
+	ServiceHandle,
+	ShutdownOutcome,
+	SignallableServiceHandle
+};
+use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant, sleep, timeout};
+
+// Stands in for a service stuck flushing to a dead socket.
+#[service (shutdown = shutdown)]
+async fn stuck () -> ExitStatus
+{
+	// Ignores the shutdown signal.
+	drop (shutdown);
+	sleep (Duration::from_secs (60)) . await;
+	ExitStatus::Clean
+}
+
+#[service (shutdown = shutdown)]
+async fn polite () -> ExitStatus
+{
+	let _ = shutdown . await;
+	ExitStatus::Clean
+}
+
+#[tokio::main]
+#[test]
+async fn stuck_service_is_aborted ()
+{
+	let mut stuck_handle = stuck ();
+	let mut polite_handle = polite ();
+
+	let started_at = Instant::now ();
+
+	assert_eq!
+	(
+		stuck_handle
+			. shutdown_with_timeout (Duration::from_millis (50))
+			. await,
+		ShutdownOutcome::Escalated
+	);
+	assert! (started_at . elapsed () < Duration::from_secs (1));
+
+	assert_eq!
+	(
+		polite_handle
+			. shutdown_with_timeout (Duration::from_millis (50))
+			. await,
+		ShutdownOutcome::Graceful (ExitStatus::Clean)
+	);
+}
+
+// A handle from outside the crate, which only implements what it has to.
+struct Wrapped (SignallableServiceHandle <ExitStatus>);
+
+impl Future for Wrapped
+{
+	type Output = ExitStatus;
+
+	fn poll (mut self: Pin <&mut Self>, cx: &mut Context <'_>)
+	-> Poll <Self::Output>
+	{
+		Pin::new (&mut self . 0) . poll (cx)
+	}
+}
+
+impl ServiceHandle for Wrapped
+{
+	fn shutdown (&mut self)
+	{
+		self . 0 . shutdown ();
+	}
+
+	async fn exit_status (&mut self) -> Option <ExitStatus>
+	{
+		self . 0 . exit_status () . await
+	}
+
+	fn take_output (&mut self) -> Option <Self::Output>
+	{
+		self . 0 . take_output ()
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn handles_without_abort_can_time_out ()
+{
+	let mut polite_handle = Wrapped (polite ());
+	let mut stuck_handle = Wrapped (stuck ());
+
+	assert_eq!
+	(
+		polite_handle
+			. shutdown_with_timeout (Duration::from_millis (50))
+			. await,
+		ShutdownOutcome::Graceful (ExitStatus::Clean)
+	);
+
+	assert_eq!
+	(
+		stuck_handle
+			. shutdown_with_timeout (Duration::from_millis (50))
+			. await,
+		ShutdownOutcome::Escalated
+	);
+}
+
+#[tokio::main]
+#[test]
+async fn join_services_with_deadline ()
+{
+	let (shutdown_trigger, shutdown) = oneshot::channel ();
+
+	let joined = tokio::spawn
+	(
+		async move
+		{
+			join_services!
+			(
+				?shutdown,
+				deadline = Duration::from_millis (50),
+				stuck (),
+				polite ()
+			)
+		}
+	);
+
+	sleep (Duration::from_millis (10)) . await;
+	shutdown_trigger . send (()) . unwrap ();
+
+	let (stuck_outcome, polite_outcome) =
+		timeout (Duration::from_secs (1), joined) . await . unwrap () . unwrap ();
+
+	assert! (stuck_outcome . is_escalated ());
+	assert! (stuck_outcome . status_spurious ());
+	assert_eq! (polite_outcome, ShutdownOutcome::Graceful (ExitStatus::Clean));
+}
+
+struct StuckFactory;
+
+impl SignallableFallibleServiceFactory for StuckFactory
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <SignallableServiceHandle <ExitStatus>>
+	{
+		Construction::Built (stuck ())
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn robust_service_shutdown_timeout ()
+{
+	let mut robust_handle = StuckFactory . into_robust_service_with_config
+	(
+		RobustServiceConfig::new ()
+			. with_shutdown_timeout (Duration::from_millis (50))
+	);
+
+	sleep (Duration::from_millis (10)) . await;
+	robust_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), robust_handle)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_clean ());
+}
@@ -0,0 +1,91 @@
+use compute_graph::{event_loop, service};
+use compute_graph::exit_status::ExitStatus;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::shutdown::ShutdownToken;
+use tokio::sync::oneshot;
+use tokio::time::{Duration, interval, sleep, timeout};
+
+#[service (shutdown = shutdown, parent = parent)]
+async fn subscription (parent: ShutdownToken) -> ExitStatus
+{
+	let mut heartbeat = interval (Duration::from_millis (5));
+
+	event_loop!
+	{
+		?&mut shutdown,
+		_ = heartbeat . tick () => ()
+	};
+
+	ExitStatus::Clean
+}
+
+// Never forwards its shutdown signal to its children by hand.
+#[service (shutdown = shutdown, token)]
+async fn feed () -> ExitStatus
+{
+	let btc = subscription (shutdown . clone ());
+	let eth = subscription (shutdown . clone ());
+
+	shutdown . await;
+
+	btc . await . and (eth . await)
+}
+
+#[tokio::main]
+#[test]
+async fn cancelling_parent_reaches_children ()
+{
+	let mut feed_handle = feed ();
+
+	sleep (Duration::from_millis (20)) . await;
+	feed_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), feed_handle)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_clean ());
+}
+
+#[tokio::main]
+#[test]
+async fn children_shut_down_independently ()
+{
+	let root = ShutdownToken::new ();
+
+	let mut btc = subscription (root . clone ());
+	let mut eth = subscription (root . clone ());
+
+	btc . shutdown ();
+
+	timeout (Duration::from_secs (1), &mut btc) . await . unwrap ();
+	assert! (! root . is_cancelled ());
+	assert! (timeout (Duration::from_millis (20), &mut eth) . await . is_err ());
+
+	root . cancel ();
+
+	timeout (Duration::from_secs (1), eth) . await . unwrap ();
+}
+
+#[service (shutdown = shutdown, token)]
+async fn watcher (shut_down: oneshot::Sender <()>) -> ExitStatus
+{
+	shutdown . await;
+	let _ = shut_down . send (());
+
+	ExitStatus::Clean
+}
+
+#[tokio::main]
+#[test]
+async fn dropping_handle_cancels_token ()
+{
+	let (shut_down_sender, shut_down_receiver) = oneshot::channel ();
+
+	drop (watcher (shut_down_sender));
+
+	timeout (Duration::from_secs (1), shut_down_receiver)
+		. await
+		. unwrap ()
+		. unwrap ();
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use compute_graph::{service, task};
+use compute_graph::exit_status::{ExitStatus, FailureKind};
+use compute_graph::robust_service::{
+	Construction,
+	RestartPolicy,
+	SignallableFallibleServiceFactory,
+	Strategy,
+	Supervisor
+};
+use compute_graph::service_handle::{CancellableServiceHandle, ServiceHandle};
+use tokio::time::{Duration, sleep, timeout};
+
+#[service]
+async fn crash () -> ExitStatus
+{
+	sleep (Duration::from_millis (20)) . await;
+	ExitStatus::spurious (FailureKind::Other, "crashed")
+}
+
+#[service]
+async fn idle () -> ExitStatus
+{
+	sleep (Duration::from_secs (60)) . await;
+	ExitStatus::Clean
+}
+
+// Crashes the first time it is constructed, if crashing is set.
+struct Factory
+{
+	constructions: Arc <AtomicU32>,
+	crashing: bool
+}
+
+impl Factory
+{
+	fn new (crashing: bool) -> (Self, Arc <AtomicU32>)
+	{
+		let constructions = Arc::new (AtomicU32::new (0));
+		(Self {constructions: constructions . clone (), crashing}, constructions)
+	}
+}
+
+impl SignallableFallibleServiceFactory for Factory
+{
+	#[task]
+	async fn construct (&mut self)
+	-> Construction <CancellableServiceHandle <ExitStatus>>
+	{
+		let construction = self . constructions . fetch_add (1, Ordering::SeqCst);
+
+		match self . crashing && construction == 0
+		{
+			true => Construction::Built (crash ()),
+			false => Construction::Built (idle ())
+		}
+	}
+}
+
+async fn restart_counts (strategy: Strategy) -> [u32; 3]
+{
+	let (feed, feed_constructions) = Factory::new (false);
+	let (decoder, decoder_constructions) = Factory::new (true);
+	let (persister, persister_constructions) = Factory::new (false);
+
+	let supervisor = Supervisor::builder (strategy)
+		. with_signallable_child ("feed", feed)
+		. with_signallable_child ("decoder", decoder)
+		. with_signallable_child ("persister", persister)
+		. build ();
+
+	let mut supervisor_handle = supervisor . start ();
+
+	sleep (Duration::from_millis (100)) . await;
+
+	supervisor_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), supervisor_handle)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_clean ());
+
+	[
+		feed_constructions . load (Ordering::SeqCst),
+		decoder_constructions . load (Ordering::SeqCst),
+		persister_constructions . load (Ordering::SeqCst)
+	]
+}
+
+#[tokio::main]
+#[test]
+async fn one_for_one ()
+{
+	assert_eq! (restart_counts (Strategy::OneForOne) . await, [1, 2, 1]);
+}
+
+#[tokio::main]
+#[test]
+async fn one_for_all ()
+{
+	assert_eq! (restart_counts (Strategy::OneForAll) . await, [2, 2, 2]);
+}
+
+#[tokio::main]
+#[test]
+async fn rest_for_one ()
+{
+	assert_eq! (restart_counts (Strategy::RestForOne) . await, [1, 2, 2]);
+}
+
+#[tokio::main]
+#[test]
+async fn nested_supervisor_is_restarted ()
+{
+	let (feed, feed_constructions) = Factory::new (false);
+	let (decoder, decoder_constructions) = Factory::new (true);
+
+	// The inner supervisor gives up on its first crash, so the outer
+	// supervisor has to restart it.
+	let inner = Supervisor::builder (Strategy::OneForOne)
+		. with_restart_policy
+		(
+			RestartPolicy::new () . with_budget (0, Duration::from_secs (60))
+		)
+		. with_signallable_child ("decoder", decoder)
+		. build ();
+
+	let outer = Supervisor::builder (Strategy::OneForOne)
+		. with_signallable_child ("feed", feed)
+		. with_signallable_child ("pipeline", inner)
+		. build ();
+
+	let mut supervisor_handle = outer . start ();
+
+	sleep (Duration::from_millis (100)) . await;
+
+	supervisor_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), supervisor_handle)
+		. await
+		. unwrap ();
+
+	assert! (exit_status . is_clean ());
+	assert_eq! (feed_constructions . load (Ordering::SeqCst), 1);
+	assert_eq! (decoder_constructions . load (Ordering::SeqCst), 2);
+}
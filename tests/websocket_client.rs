@@ -0,0 +1,794 @@
+use std::net::SocketAddr;
+
+use compute_graph::backoff::{BackoffPolicy, Jitter};
+use compute_graph::exit_status::{ExitStatus, Failure, FailureKind};
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::{ServiceHandle, SignallableServiceHandle};
+use compute_graph::service_state::ServiceStatus;
+use compute_graph::stream::{SharedStream, mpsc};
+use compute_graph::websocket::client::{
+	ClientStream,
+	ActiveEndpoint,
+	ConnectionConfig,
+	EndpointSelection,
+	OutboxConfig,
+	OverflowPolicy,
+	QuarantinePolicy,
+	PingConfig,
+	ReconnectPolicy,
+	WebSocketClientNode,
+	WebSocketClientSinkWithPings
+};
+use compute_graph::websocket::PeerClosed;
+use compute_graph::websocket::io_format::Text;
+use futures::{SinkExt, Stream, StreamExt};
+use futures::stream::pending;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{Sender, channel};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, sleep, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::{WebSocketStream, accept_async, accept_hdr_async};
+use tungstenite::{Message, Utf8Bytes};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::HeaderValue;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::protocol::frame::coding::CloseCode;
+
+fn shared_input (buffer: usize)
+-> (Sender <Utf8Bytes>, SharedStream <ReceiverStream <Utf8Bytes>>)
+{
+	let (sender, receiver) = channel (buffer);
+
+	(sender, SharedStream::new (ReceiverStream::new (receiver)))
+}
+
+fn fast_reconnects () -> ReconnectPolicy
+{
+	ReconnectPolicy::new
+	(
+		BackoffPolicy::new (Duration::from_millis (1), Duration::from_millis (10))
+			. with_jitter (Jitter::None)
+	)
+}
+
+async fn authenticate (websocket: &mut ClientStream) -> Result <(), Failure>
+{
+	let connection_failure =
+		|ws_error| Failure::from_error (FailureKind::Connection, ws_error);
+
+	websocket
+		. send (Message::Text ("auth" . into ()))
+		. await
+		. map_err (connection_failure)?;
+
+	match timeout (Duration::from_millis (100), websocket . next ()) . await
+	{
+		Ok (Some (Ok (Message::Text (text)))) if text . as_str () == "ack" => (),
+		_ => return Err (Failure::new (FailureKind::Protocol, "no ack"))
+	}
+
+	websocket
+		. send (Message::Text ("subscribe" . into ()))
+		. await
+		. map_err (connection_failure)
+}
+
+async fn expect_text <S> (websocket: &mut S, expected: &str)
+where S: Stream <Item = Result <Message, tungstenite::Error>> + Unpin
+{
+	match websocket . next () . await
+	{
+		Some (Ok (Message::Text (text))) => assert_eq! (text . as_str (), expected),
+		other => panic! ("unexpected message: {:?}", other)
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn reconnect_gives_up ()
+{
+	// Bind and immediately drop a listener so that we have a port that
+	// refuses connections.
+	let address = TcpListener::bind ("127.0.0.1:0")
+		. await
+		. unwrap ()
+		. local_addr ()
+		. unwrap ();
+
+	let reconnect_policy = fast_reconnects () . with_max_attempts (3);
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (reconnect_policy);
+
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink
+	)
+		. into_robust_service ();
+
+	timeout (Duration::from_secs (5), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn silent_server_times_out_handshake ()
+{
+	// Accepts connections but never answers the handshake.
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_handshake_timeout (Duration::from_millis (50))
+		. with_reconnect_policy (fast_reconnects () . with_max_attempts (2));
+
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink
+	)
+		. into_robust_service ();
+
+	let exit_status = timeout (Duration::from_secs (5), robust_handle)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+
+	assert_eq! (failure . kind (), FailureKind::Timeout);
+	assert_eq! (failure . message (), "websocket handshake timed out");
+	drop (listener);
+}
+
+#[tokio::main]
+#[test]
+async fn on_connect_runs_after_every_reconnect ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	// Drops each connection after a single tick, forcing the client to
+	// reconnect and subscribe again.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			for tick in ["tick 1", "tick 2"]
+			{
+				let (tcp_stream, _) = listener . accept () . await . unwrap ();
+				let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+				expect_text (&mut websocket, "auth") . await;
+				websocket . send (Message::Text ("ack" . into ())) . await . unwrap ();
+				expect_text (&mut websocket, "subscribe") . await;
+				websocket . send (Message::Text (tick . into ())) . await . unwrap ();
+			}
+		}
+	);
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (fast_reconnects ())
+		. with_on_connect_fn (|websocket| Box::pin (authenticate (websocket)));
+
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink
+	)
+		. into_robust_service ();
+
+	for tick in ["tick 1", "tick 2"]
+	{
+		let received = timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		assert_eq! (received . as_str (), tick);
+	}
+
+	server . await . unwrap ();
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn missing_ack_fails_connection_attempt ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	// Accepts websocket connections but never acknowledges anything.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let mut websockets = Vec::new ();
+
+			loop
+			{
+				let (tcp_stream, _) = listener . accept () . await . unwrap ();
+				websockets . push (accept_async (tcp_stream) . await . unwrap ());
+			}
+		}
+	);
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (fast_reconnects () . with_max_attempts (2))
+		. with_on_connect_fn (|websocket| Box::pin (authenticate (websocket)));
+
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink
+	)
+		. into_robust_service ();
+
+	let exit_status = timeout (Duration::from_secs (5), robust_handle)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+
+	assert_eq! (failure . kind (), FailureKind::Protocol);
+	assert_eq! (failure . message (), "no ack");
+
+	server . abort ();
+}
+
+// Starts a client against an address nobody is listening on yet, so that its
+// input piles up in the outbox.
+async fn client_with_outbox (outbox_config: OutboxConfig)
+-> (
+	SocketAddr,
+	Sender <Utf8Bytes>,
+	SignallableServiceHandle <ExitStatus>
+)
+{
+	let address = TcpListener::bind ("127.0.0.1:0")
+		. await
+		. unwrap ()
+		. local_addr ()
+		. unwrap ();
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (fast_reconnects ());
+
+	let (input_sender, input) = shared_input (1);
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		input,
+		output_sink
+	)
+		. with_outbox (outbox_config)
+		. into_robust_service ();
+
+	(address, input_sender, robust_handle)
+}
+
+async fn accept_websocket (address: SocketAddr)
+-> WebSocketStream <TcpStream>
+{
+	let listener = TcpListener::bind (address) . await . unwrap ();
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+
+	accept_async (tcp_stream) . await . unwrap ()
+}
+
+#[tokio::main]
+#[test]
+async fn outbox_drops_oldest_while_disconnected ()
+{
+	let (address, input_sender, mut robust_handle) = client_with_outbox
+	(
+		OutboxConfig::new (2) . with_overflow_policy (OverflowPolicy::DropOldest)
+	)
+		. await;
+
+	for order in ["1", "2", "3", "4"]
+	{
+		timeout (Duration::from_secs (1), input_sender . send (order . into ()))
+			. await
+			. unwrap ()
+			. unwrap ();
+	}
+
+	// Gives the outbox a chance to pull the last order off the input.
+	sleep (Duration::from_millis (20)) . await;
+
+	let mut websocket = accept_websocket (address) . await;
+
+	expect_text (&mut websocket, "3") . await;
+	expect_text (&mut websocket, "4") . await;
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn blocking_outbox_keeps_every_message ()
+{
+	let (address, input_sender, mut robust_handle) =
+		client_with_outbox (OutboxConfig::new (2)) . await;
+
+	// Two orders fit in the outbox and one more in the input channel.
+	for order in ["1", "2", "3"]
+	{
+		timeout (Duration::from_secs (1), input_sender . send (order . into ()))
+			. await
+			. unwrap ()
+			. unwrap ();
+	}
+
+	let blocked_send = input_sender . send ("4" . into ());
+	assert! (timeout (Duration::from_millis (50), blocked_send) . await . is_err ());
+
+	let mut websocket = accept_websocket (address) . await;
+
+	input_sender . send ("4" . into ()) . await . unwrap ();
+
+	for order in ["1", "2", "3", "4"]
+	{
+		expect_text (&mut websocket, order) . await;
+	}
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn zero_capacity_outbox_still_sends ()
+{
+	let (address, input_sender, mut robust_handle) =
+		client_with_outbox (OutboxConfig::new (0)) . await;
+
+	let mut websocket = accept_websocket (address) . await;
+
+	for order in ["1", "2", "3"]
+	{
+		timeout (Duration::from_secs (1), input_sender . send (order . into ()))
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		timeout (Duration::from_secs (1), expect_text (&mut websocket, order))
+			. await
+			. unwrap ();
+	}
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+// Connections overlap while one replaces the other, and both share the outbox.
+#[tokio::main]
+#[test]
+async fn outbox_survives_preemptive_replacement ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+	let (received_sender, mut received) = channel::<String> (64);
+
+	let server = tokio::spawn
+	(
+		async move
+		{
+			loop
+			{
+				let (tcp_stream, _) = listener . accept () . await . unwrap ();
+				let received_sender = received_sender . clone ();
+
+				tokio::spawn
+				(
+					async move
+					{
+						let mut websocket =
+							accept_async (tcp_stream) . await . unwrap ();
+
+						while let Some (Ok (message)) =
+							websocket . next () . await
+						{
+							if let Message::Text (text) = message
+							{
+								let _ = received_sender
+									. send (text . to_string ())
+									. await;
+							}
+						}
+					}
+				);
+			}
+		}
+	);
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (fast_reconnects ());
+
+	let (input_sender, input) = shared_input (1);
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		input,
+		output_sink
+	)
+		. with_outbox (OutboxConfig::new (4))
+		. into_robust_service_with_preemptive_replacement
+		(
+			Duration::from_millis (5)
+		);
+
+	let orders: Vec <String> = (0..200) . map (|i| i . to_string ()) . collect ();
+
+	for order in &orders
+	{
+		input_sender . send (order . as_str () . into ()) . await . unwrap ();
+	}
+
+	let mut delivered = Vec::new ();
+
+	while delivered . len () < orders . len ()
+	{
+		let order = timeout (Duration::from_secs (2), received . recv ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		delivered . push (order);
+	}
+
+	// Nothing is sent twice either.
+	let extra = timeout (Duration::from_millis (50), received . recv ()) . await;
+	assert! (extra . is_err ());
+
+	delivered . sort_by_key (|order| order . parse::<u32> () . unwrap ());
+	assert_eq! (delivered, orders);
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+	server . abort ();
+}
+
+// Connects to a primary endpoint nobody is listening on, with a DR endpoint to
+// fail over to.
+async fn fail_over (connection_config: ConnectionConfig <String>)
+-> Option <ActiveEndpoint>
+{
+	let (endpoint_sender, mut endpoint_receiver) = watch::channel (None);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config . with_endpoint_reporting (endpoint_sender),
+		pending::<Utf8Bytes> (),
+		mpsc::<Utf8Bytes> (1) . 0
+	)
+		. into_robust_service ();
+
+	let active_endpoint = timeout
+	(
+		Duration::from_secs (1),
+		endpoint_receiver . wait_for (Option::is_some)
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. clone ();
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+
+	active_endpoint
+}
+
+async fn primary_and_dr ()
+-> (String, String, JoinHandle <WebSocketStream <TcpStream>>)
+{
+	let primary_address = TcpListener::bind ("127.0.0.1:0")
+		. await
+		. unwrap ()
+		. local_addr ()
+		. unwrap ();
+
+	let dr_listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let dr_address = dr_listener . local_addr () . unwrap ();
+
+	let dr_server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = dr_listener . accept () . await . unwrap ();
+			accept_async (tcp_stream) . await . unwrap ()
+		}
+	);
+
+	(
+		format! ("ws://{}", primary_address),
+		format! ("ws://{}", dr_address),
+		dr_server
+	)
+}
+
+#[tokio::main]
+#[test]
+async fn priority_fails_over_after_quarantine ()
+{
+	let (primary, dr, dr_server) = primary_and_dr () . await;
+
+	let active_endpoint = fail_over
+	(
+		ConnectionConfig::new (primary)
+			. with_failover (dr)
+			. with_reconnect_policy (fast_reconnects ())
+			. with_quarantine_policy
+			(
+				QuarantinePolicy
+				{
+					after_failures: 2,
+					duration: Duration::from_secs (60)
+				}
+			)
+	)
+		. await
+		. unwrap ();
+
+	assert_eq! (active_endpoint . index, 1);
+	dr_server . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn round_robin_moves_to_next_endpoint ()
+{
+	let (primary, dr, dr_server) = primary_and_dr () . await;
+
+	let active_endpoint = fail_over
+	(
+		ConnectionConfig::new (primary)
+			. with_failover (dr . clone ())
+			. with_endpoint_selection (EndpointSelection::RoundRobin)
+			. with_reconnect_policy (fast_reconnects () . with_max_attempts (2))
+	)
+		. await
+		. unwrap ();
+
+	assert_eq! (active_endpoint . index, 1);
+	let authority = active_endpoint . uri . authority () . unwrap ();
+	assert! (dr . ends_with (authority . as_str ()));
+	dr_server . await . unwrap ();
+}
+
+// The error type is dictated by tungstenite.
+#[allow (clippy::result_large_err)]
+fn add_session_id (_: &Request, mut response: Response)
+-> Result <Response, ErrorResponse>
+{
+	response
+		. headers_mut ()
+		. insert ("x-session-id", HeaderValue::from_static ("42"));
+
+	Ok (response)
+}
+
+#[tokio::main]
+#[test]
+async fn on_upgrade_sees_response_headers ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let (session_sender, mut session_receiver) = watch::channel (None);
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_on_upgrade
+		(
+			move |response|
+			{
+				let session_id = response
+					. headers ()
+					. get ("x-session-id")
+					. and_then (|session_id| session_id . to_str () . ok ())
+					. map (str::to_string);
+
+				session_sender . send_replace (session_id);
+				Ok (())
+			}
+		);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		mpsc::<Utf8Bytes> (1) . 0
+	)
+		. into_robust_service ();
+
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+
+	let _websocket = accept_hdr_async (tcp_stream, add_session_id)
+		. await
+		. unwrap ();
+
+	let session_id = timeout
+	(
+		Duration::from_secs (1),
+		session_receiver . wait_for (Option::is_some)
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. clone ();
+
+	assert_eq! (session_id . as_deref (), Some ("42"));
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn try_again_later_delays_reconnect ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let reconnect_policy = fast_reconnects ()
+		. with_close_delay (CloseCode::Again, Duration::from_millis (200));
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (reconnect_policy);
+
+	let (status_sender, mut status_receiver) =
+		watch::channel (ServiceStatus::default ());
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		mpsc::<Utf8Bytes> (1) . 0
+	)
+		. into_robust_service_with_status_reporting (status_sender);
+
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+	let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+	let close_frame =
+		CloseFrame {code: CloseCode::Again, reason: "busy" . into ()};
+
+	websocket . close (Some (close_frame)) . await . unwrap ();
+	let closed_at = Instant::now ();
+
+	let last_exit_status = timeout
+	(
+		Duration::from_secs (1),
+		status_receiver
+			. wait_for (|status| status . last_exit_status . is_some ())
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. last_exit_status
+		. clone ()
+		. unwrap ();
+
+	assert_eq!
+	(
+		PeerClosed::from_exit_status (&last_exit_status),
+		Some (&PeerClosed {code: CloseCode::Again, reason: "busy" . into ()})
+	);
+
+	timeout (Duration::from_secs (1), listener . accept ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert! (closed_at . elapsed () >= Duration::from_millis (200));
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn sink_with_pings_sees_peer_close ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_reconnect_policy (fast_reconnects ());
+
+	let ping_config = PingConfig
+	{
+		ping_interval: Duration::from_secs (10),
+		ping_timeout: Duration::from_secs (10)
+	};
+
+	let (status_sender, mut status_receiver) =
+		watch::channel (ServiceStatus::default ());
+
+	let mut robust_handle = WebSocketClientSinkWithPings::new
+	(
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		ping_config
+	)
+		. into_robust_service_with_status_reporting (status_sender);
+
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+	let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+	// Closing while the first ping is still unanswered, so that the close
+	// can't race it.
+	sleep (Duration::from_millis (100)) . await;
+
+	let close_frame =
+		CloseFrame {code: CloseCode::Away, reason: "restarting" . into ()};
+
+	websocket . close (Some (close_frame)) . await . unwrap ();
+
+	let last_exit_status = timeout
+	(
+		Duration::from_secs (1),
+		status_receiver
+			. wait_for (|status| status . last_exit_status . is_some ())
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. last_exit_status
+		. clone ()
+		. unwrap ();
+
+	assert_eq!
+	(
+		PeerClosed::from_exit_status (&last_exit_status),
+		Some
+		(
+			&PeerClosed {code: CloseCode::Away, reason: "restarting" . into ()}
+		)
+	);
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
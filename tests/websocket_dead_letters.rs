@@ -0,0 +1,173 @@
+use bytes::Bytes;
+use compute_graph::exit_status::FailureKind;
+use compute_graph::json::{JSON, json_deserialize};
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::connection::websocket_node;
+use compute_graph::websocket::io_format::{
+	DeadLetter,
+	DeadLetters,
+	FailureLimit,
+	Text
+};
+use futures::{SinkExt, StreamExt};
+use futures::stream::{iter, pending};
+use serde_json::{Value, json};
+use tokio::io::{DuplexStream, duplex};
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::{Message, Utf8Bytes};
+use tungstenite::protocol::Role;
+
+async fn websocket_pair ()
+-> (WebSocketStream <DuplexStream>, WebSocketStream <DuplexStream>)
+{
+	let (client_io, server_io) = duplex (4096);
+
+	(
+		WebSocketStream::from_raw_socket (client_io, Role::Client, None) . await,
+		WebSocketStream::from_raw_socket (server_io, Role::Server, None) . await
+	)
+}
+
+#[tokio::main]
+#[test]
+async fn dead_letters_are_output_as_errors ()
+{
+	let (client_websocket, mut server_websocket) = websocket_pair () . await;
+	let (output_sink, mut output_stream) =
+		mpsc::<Result <Value, DeadLetter>> (1);
+
+	let mut node_handle = websocket_node
+	(
+		Text,
+		DeadLetters (JSON::<Value>::default ()),
+		pending::<Utf8Bytes> (),
+		output_sink,
+		client_websocket
+	);
+
+	for message in
+	[
+		Message::text (r#"{"price":1}"#),
+		Message::text ("{\"price\":"),
+		Message::binary (Bytes::from_static (b"\x01\x02"))
+	]
+	{
+		server_websocket . send (message) . await . unwrap ();
+	}
+
+	let mut next_output = async ||
+	{
+		timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ()
+	};
+
+	assert_eq! (next_output () . await, Ok (json! ({"price": 1})));
+
+	let dead_letter = next_output () . await . unwrap_err ();
+	assert_eq! (dead_letter . message, Message::text ("{\"price\":"));
+	assert_eq! (dead_letter . failure . kind (), FailureKind::Decode);
+
+	let dead_letter = next_output () . await . unwrap_err ();
+	assert! (dead_letter . message . is_binary ());
+
+	// Dead letters don't fail the connection by themselves.
+	let still_up =
+		timeout (Duration::from_millis (50), node_handle . exit_status ())
+			. await;
+	assert! (still_up . is_err ());
+
+	node_handle . shutdown ();
+	timeout (Duration::from_secs (1), node_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn consecutive_failures_fail_the_connection ()
+{
+	let (client_websocket, mut server_websocket) = websocket_pair () . await;
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut node_handle = websocket_node
+	(
+		Text,
+		FailureLimit::new (Text, 2),
+		pending::<Utf8Bytes> (),
+		output_sink,
+		client_websocket
+	);
+
+	let binary = || Message::binary (Bytes::from_static (b"\x00"));
+
+	// A message that converts resets the count.
+	for message in [binary (), Message::text ("ok"), binary ()]
+	{
+		server_websocket . send (message) . await . unwrap ();
+	}
+
+	let output = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (output . as_str (), "ok");
+
+	let still_up =
+		timeout (Duration::from_millis (50), node_handle . exit_status ())
+			. await;
+	assert! (still_up . is_err ());
+
+	server_websocket . send (binary ()) . await . unwrap ();
+
+	let exit_status = timeout (Duration::from_secs (1), node_handle)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+	assert_eq! (failure . kind (), FailureKind::Decode);
+}
+
+#[tokio::main]
+#[test]
+async fn json_deserialize_has_dead_letters_and_failure_limits ()
+{
+	let (output_sink, output_stream) = mpsc::<Result <Value, DeadLetter>> (8);
+
+	let strings = iter
+	([
+		r#"{"price":1}"#,
+		"{",
+		r#"{"price":2}"#,
+		"{",
+		"{",
+		r#"{"price":3}"#
+	]);
+
+	let exit_status = timeout
+	(
+		Duration::from_secs (1),
+		json_deserialize
+		(
+			FailureLimit::new (DeadLetters (JSON::<Value>::default ()), 2),
+			strings,
+			output_sink
+		)
+	)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+	assert_eq! (failure . kind (), FailureKind::Decode);
+
+	let outputs: Vec <_> = output_stream . collect () . await;
+
+	assert_eq! (outputs . len (), 5);
+	assert_eq! (outputs [0], Ok (json! ({"price": 1})));
+	let dead_letter = outputs [1] . as_ref () . unwrap_err ();
+	assert_eq! (dead_letter . message, Message::text ("{"));
+	assert_eq! (outputs [2], Ok (json! ({"price": 2})));
+	assert! (outputs [3 ..] . iter () . all (Result::is_err));
+}
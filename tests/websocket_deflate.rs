@@ -0,0 +1,503 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use compute_graph::backoff::{BackoffPolicy, Jitter};
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::{SharedStream, mpsc};
+use compute_graph::websocket::DeflateConfig;
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	ReconnectPolicy,
+	WebSocketClientNode
+};
+use compute_graph::websocket::io_format::Text;
+use compute_graph::websocket::server::{
+	ServerConfig,
+	ServerConnection,
+	websocket_server_node
+};
+use flate2::{Decompress, FlushDecompress};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::channel;
+use tokio::time::{Duration, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::{accept_async, client_async};
+use tungstenite::{Message, Utf8Bytes};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::http::HeaderValue;
+use tungstenite::http::header::SEC_WEBSOCKET_EXTENSIONS;
+
+// Compresses to a small fraction of its size.
+fn ticker_text () -> String
+{
+	"BTC-USD 64000.5 0.25 bid\n" . repeat (400)
+}
+
+// Echoes every message on every connection, accepting permessage-deflate if
+// it's given a config.
+async fn echo_server (deflate_config: Option <DeflateConfig>) -> SocketAddr
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let server_config = match deflate_config
+	{
+		Some (deflate_config) =>
+			ServerConfig::new () . with_deflate (deflate_config),
+		None => ServerConfig::new ()
+	};
+
+	let (connection_sink, mut connection_stream) = mpsc (1);
+
+	let server_handle = websocket_server_node
+	(
+		Text,
+		Text,
+		listener,
+		server_config,
+		connection_sink
+	);
+
+	tokio::spawn
+	(
+		async move
+		{
+			let _server_handle = server_handle;
+
+			while let Some (connection) = connection_stream . next () . await
+			{
+				tokio::spawn (echo (connection));
+			}
+		}
+	);
+
+	address
+}
+
+async fn echo (mut connection: ServerConnection <Utf8Bytes, Utf8Bytes>)
+{
+	while let Some (text) = connection . output . next () . await
+	{
+		if connection . input . send (text) . await . is_err ()
+		{
+			break;
+		}
+	}
+}
+
+async fn pump <R, W> (mut reader: R, mut writer: W, count: Arc <AtomicUsize>)
+where
+	R: AsyncRead + Unpin,
+	W: AsyncWrite + Unpin
+{
+	let mut buffer = [0; 4096];
+
+	while let Ok (length @ 1 ..) = reader . read (&mut buffer) . await
+	{
+		count . fetch_add (length, Ordering::Relaxed);
+
+		if writer . write_all (&buffer [.. length]) . await . is_err ()
+		{
+			break;
+		}
+	}
+}
+
+// Passes a single connection through to the target, counting the bytes that
+// go each way.
+async fn counting_relay (target: SocketAddr)
+-> (SocketAddr, Arc <AtomicUsize>, Arc <AtomicUsize>)
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+	let sent = Arc::new (AtomicUsize::new (0));
+	let received = Arc::new (AtomicUsize::new (0));
+
+	let (sent_count, received_count) = (sent . clone (), received . clone ());
+
+	tokio::spawn
+	(
+		async move
+		{
+			let (client, _) = listener . accept () . await . unwrap ();
+			let server = TcpStream::connect (target) . await . unwrap ();
+
+			let (client_reader, client_writer) = client . into_split ();
+			let (server_reader, server_writer) = server . into_split ();
+
+			tokio::join!
+			(
+				pump (client_reader, server_writer, sent_count),
+				pump (server_reader, client_writer, received_count)
+			);
+		}
+	);
+
+	(address, sent, received)
+}
+
+fn single_attempt () -> ReconnectPolicy
+{
+	ReconnectPolicy::new
+	(
+		BackoffPolicy::new
+		(
+			Duration::from_millis (1),
+			Duration::from_millis (1)
+		)
+			. with_jitter (Jitter::None)
+	)
+		. with_max_attempts (1)
+}
+
+// Sends the texts through a client and waits for each to come back, returning
+// the extensions the server agreed to.
+async fn echo_through
+(
+	connection_config: ConnectionConfig <String>,
+	texts: &[String]
+)
+-> Option <String>
+{
+	let extensions = Arc::new (Mutex::new (None));
+	let agreed_extensions = extensions . clone ();
+
+	let connection_config = connection_config
+		. with_reconnect_policy (single_attempt ())
+		. with_on_upgrade
+	(
+		move |response|
+		{
+			*agreed_extensions . lock () . unwrap () = response
+				. headers ()
+				. get (SEC_WEBSOCKET_EXTENSIONS)
+				. map (|value| value . to_str () . unwrap () . to_string ());
+
+			Ok (())
+		}
+	);
+
+	let (input_sender, input_receiver) = channel::<Utf8Bytes> (1);
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		SharedStream::new (ReceiverStream::new (input_receiver)),
+		output_sink
+	)
+		. into_robust_service ();
+
+	for text in texts
+	{
+		input_sender . send (text . as_str () . into ()) . await . unwrap ();
+
+		let echo = timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		assert_eq! (echo . as_str (), text);
+	}
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+
+	let extensions = extensions . lock () . unwrap () . take ();
+	extensions
+}
+
+#[tokio::main]
+#[test]
+async fn compresses_both_ways ()
+{
+	let server_address = echo_server (Some (DeflateConfig::new ())) . await;
+	let (relay_address, sent, received) =
+		counting_relay (server_address) . await;
+
+	let texts = vec! [ticker_text (); 3];
+
+	let extensions = echo_through
+	(
+		ConnectionConfig::new (format! ("ws://{}", relay_address))
+			. with_deflate (DeflateConfig::new ()),
+		&texts
+	)
+		. await;
+
+	assert_eq! (extensions . as_deref (), Some ("permessage-deflate"));
+
+	// The handshake takes a few hundred bytes of its own.
+	let uncompressed = texts . iter () . map (String::len) . sum::<usize> ();
+	assert! (sent . load (Ordering::Relaxed) < uncompressed / 4);
+	assert! (received . load (Ordering::Relaxed) < uncompressed / 4);
+}
+
+#[tokio::main]
+#[test]
+async fn negotiates_window_bits_and_context_takeover ()
+{
+	let server_address = echo_server
+	(
+		Some
+		(
+			DeflateConfig::new ()
+				. with_server_max_window_bits (12)
+				. with_client_no_context_takeover ()
+		)
+	)
+		. await;
+
+	let texts: Vec <String> = (0 .. 5)
+		. map (|index| format! ("{}{}", index, ticker_text ()))
+		. collect ();
+
+	let extensions = echo_through
+	(
+		ConnectionConfig::new (format! ("ws://{}", server_address))
+			. with_deflate
+			(
+				DeflateConfig::new ()
+					. with_client_max_window_bits (10)
+					. with_server_no_context_takeover ()
+			),
+		&texts
+	)
+		. await;
+
+	assert_eq!
+	(
+		extensions . as_deref (),
+		Some
+		(
+			"permessage-deflate; server_max_window_bits=12; \
+				client_max_window_bits=10; server_no_context_takeover; \
+				client_no_context_takeover"
+		)
+	);
+}
+
+#[tokio::main]
+#[test]
+async fn falls_back_without_server_support ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			while let Some (Ok (message)) = websocket . next () . await
+			{
+				if message . is_text ()
+				{
+					websocket . send (message) . await . unwrap ();
+				}
+			}
+		}
+	);
+
+	let extensions = echo_through
+	(
+		ConnectionConfig::new (format! ("ws://{}", address))
+			. with_deflate (DeflateConfig::new ()),
+		&[ticker_text ()]
+	)
+		. await;
+
+	assert_eq! (extensions, None);
+}
+
+// Offers the extension from a client that can't actually decompress, to see
+// what the server makes of the offer.
+async fn answer_to (address: SocketAddr, offer: &'static str)
+-> (Option <String>, tokio_tungstenite::WebSocketStream <TcpStream>)
+{
+	let mut request = format! ("ws://{}", address)
+		. into_client_request ()
+		. unwrap ();
+
+	request
+		. headers_mut ()
+		. insert (SEC_WEBSOCKET_EXTENSIONS, HeaderValue::from_static (offer));
+
+	let tcp_stream = TcpStream::connect (address) . await . unwrap ();
+	let (websocket, response) = client_async (request, tcp_stream)
+		. await
+		. unwrap ();
+
+	let answer = response
+		. headers ()
+		. get (SEC_WEBSOCKET_EXTENSIONS)
+		. map (|value| value . to_str () . unwrap () . to_string ());
+
+	(answer, websocket)
+}
+
+#[tokio::main]
+#[test]
+async fn declines_offers_it_cannot_honour ()
+{
+	let address = echo_server (Some (DeflateConfig::new ())) . await;
+
+	let (answer, mut websocket) =
+		answer_to (address, "permessage-deflate; server_max_window_bits=8")
+			. await;
+
+	assert_eq! (answer, None);
+
+	// Without the extension, messages go back and forth uncompressed.
+	websocket . send (Message::Text ("ping" . into ())) . await . unwrap ();
+
+	match timeout (Duration::from_secs (1), websocket . next ()) . await
+	{
+		Ok (Some (Ok (Message::Text (text)))) =>
+			assert_eq! (text . as_str (), "ping"),
+		other => panic! ("unexpected message: {:?}", other)
+	}
+
+	let (answer, _) =
+		answer_to (address, "permessage-deflate; mystery") . await;
+	assert_eq! (answer, None);
+
+	let (answer, _) = answer_to
+	(
+		address,
+		"permessage-deflate; server_max_window_bits=8, \
+			permessage-deflate; client_max_window_bits=9"
+	)
+		. await;
+
+	assert_eq!
+	(
+		answer . as_deref (),
+		Some ("permessage-deflate; client_max_window_bits=9")
+	);
+}
+
+// Plays the server by hand, using the examples from RFC 7692.
+#[tokio::main]
+#[test]
+async fn speaks_the_rfc_wire_format ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (mut tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut request = Vec::new ();
+
+			while ! request . ends_with (b"\r\n\r\n")
+			{
+				request . push (tcp_stream . read_u8 () . await . unwrap ());
+			}
+
+			let request = String::from_utf8 (request) . unwrap ();
+			assert! (request . contains ("permessage-deflate"));
+
+			let key = request
+				. lines ()
+				. find_map (|line| line . strip_prefix ("Sec-WebSocket-Key: "))
+				. unwrap ();
+
+			let mut reply = format!
+			(
+				"HTTP/1.1 101 Switching Protocols\r\n\
+					Upgrade: websocket\r\n\
+					Connection: Upgrade\r\n\
+					Sec-WebSocket-Accept: {}\r\n\
+					Sec-WebSocket-Extensions: permessage-deflate\r\n\r\n",
+				derive_accept_key (key . as_bytes ())
+			)
+				. into_bytes ();
+
+			// "Hello" compressed in one frame, then again split over two.
+			reply . extend
+			(
+				[0xc1, 0x07, 0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00]
+			);
+			reply . extend ([0x41, 0x03, 0xf2, 0x48, 0xcd]);
+			reply . extend ([0x80, 0x04, 0xc9, 0xc9, 0x07, 0x00]);
+
+			tcp_stream . write_all (&reply) . await . unwrap ();
+
+			let mut header = [0; 2];
+			tcp_stream . read_exact (&mut header) . await . unwrap ();
+
+			// Final, compressed, text and masked.
+			assert_eq! (header [0], 0xc1);
+			assert_eq! (header [1] & 0x80, 0x80);
+
+			let mut mask = [0; 4];
+			tcp_stream . read_exact (&mut mask) . await . unwrap ();
+
+			let mut payload = vec! [0; usize::from (header [1] & 0x7f)];
+			tcp_stream . read_exact (&mut payload) . await . unwrap ();
+
+			for (index, byte) in payload . iter_mut () . enumerate ()
+			{
+				*byte ^= mask [index % 4];
+			}
+
+			payload . extend ([0, 0, 0xff, 0xff]);
+
+			let mut text = Vec::with_capacity (64);
+			Decompress::new (false)
+				. decompress_vec (&payload, &mut text, FlushDecompress::Sync)
+				. unwrap ();
+
+			String::from_utf8 (text) . unwrap ()
+		}
+	);
+
+	let (input_sender, input_receiver) = channel::<Utf8Bytes> (1);
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		ConnectionConfig::new (format! ("ws://{}", address))
+			. with_deflate (DeflateConfig::new ())
+			. with_reconnect_policy (single_attempt ()),
+		SharedStream::new (ReceiverStream::new (input_receiver)),
+		output_sink
+	)
+		. into_robust_service ();
+
+	for _ in 0 .. 2
+	{
+		let text = timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		assert_eq! (text . as_str (), "Hello");
+	}
+
+	input_sender . send ("Hello again" . into ()) . await . unwrap ();
+
+	let received = timeout (Duration::from_secs (1), server)
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (received, "Hello again");
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
@@ -0,0 +1,230 @@
+use compute_graph::exit_status::FailureKind;
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::service_state::ServiceStatus;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::{IdleTimeout, JsonHeartbeat, PingConfig};
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	WebSocketClientNodeWithPings
+};
+use compute_graph::websocket::connection;
+use compute_graph::websocket::io_format::Text;
+use futures::{SinkExt, StreamExt};
+use futures::sink::drain;
+use futures::stream::pending;
+use serde_json::json;
+use tokio::io::duplex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::{Duration, sleep, timeout};
+use tokio_tungstenite::{WebSocketStream, accept_async};
+use tungstenite::{Message, Utf8Bytes};
+use tungstenite::protocol::Role;
+
+async fn listen () -> (TcpListener, ConnectionConfig <String>)
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	(listener, ConnectionConfig::new (format! ("ws://{}", address)))
+}
+
+async fn accept_websocket (listener: &TcpListener) -> WebSocketStream <TcpStream>
+{
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+	accept_async (tcp_stream) . await . unwrap ()
+}
+
+async fn next_text (websocket: &mut WebSocketStream <TcpStream>) -> String
+{
+	match timeout (Duration::from_secs (1), websocket . next ()) . await
+	{
+		Ok (Some (Ok (Message::Text (text)))) => text . to_string (),
+		other => panic! ("unexpected message: {:?}", other)
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn json_heartbeat_replies_are_kept_from_output ()
+{
+	let (listener, connection_config) = listen () . await;
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (8);
+
+	let heartbeat = JsonHeartbeat::new
+	(
+		json! ({"op": "ping"}),
+		json! ({"op": "pong"}),
+		Duration::from_millis (20),
+		Duration::from_millis (200)
+	);
+
+	let mut robust_handle = WebSocketClientNodeWithPings::with_heartbeat
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink,
+		heartbeat
+	)
+		. into_robust_service ();
+
+	let mut websocket = accept_websocket (&listener) . await;
+
+	for tick in ["tick 1", "tick 2"]
+	{
+		assert_eq! (next_text (&mut websocket) . await, r#"{"op":"ping"}"#);
+
+		let pong = Message::text (r#"{"op":"pong","ts":1700000000}"#);
+		websocket . send (pong) . await . unwrap ();
+		websocket . send (Message::text (tick)) . await . unwrap ();
+	}
+
+	for tick in ["tick 1", "tick 2"]
+	{
+		let output = timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		assert_eq! (output . as_str (), tick);
+	}
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn idle_timeout_gives_up_on_quiet_connection ()
+{
+	let (listener, connection_config) = listen () . await;
+	let (status_sender, mut status_receiver) =
+		watch::channel (ServiceStatus::default ());
+
+	let mut robust_handle = WebSocketClientNodeWithPings::with_heartbeat
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		mpsc::<Utf8Bytes> (1) . 0,
+		IdleTimeout::new (Duration::from_millis (50))
+	)
+		. into_robust_service_with_status_reporting (status_sender);
+
+	let _quiet = accept_websocket (&listener) . await;
+
+	let status = timeout
+	(
+		Duration::from_secs (1),
+		status_receiver
+			. wait_for (|status| status . last_exit_status . is_some ())
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. clone ();
+
+	let last_exit_status = status . last_exit_status . unwrap ();
+	let failure = last_exit_status . failure () . unwrap ();
+	assert_eq! (failure . kind (), FailureKind::Timeout);
+
+	// The client reconnects after giving up.
+	let _reconnected = accept_websocket (&listener) . await;
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn answered_pings_keep_connection_up ()
+{
+	let (listener, connection_config) = listen () . await;
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+	let (status_sender, status_receiver) =
+		watch::channel (ServiceStatus::default ());
+
+	let mut robust_handle = WebSocketClientNodeWithPings::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink,
+		PingConfig
+		{
+			ping_interval: Duration::from_millis (10),
+			ping_timeout: Duration::from_millis (50)
+		}
+	)
+		. into_robust_service_with_status_reporting (status_sender);
+
+	// Reading answers the client's pings.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let mut websocket = accept_websocket (&listener) . await;
+			websocket . send (Message::text ("hello")) . await . unwrap ();
+
+			while let Some (Ok (_)) = websocket . next () . await {}
+		}
+	);
+
+	let output = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (output . as_str (), "hello");
+
+	sleep (Duration::from_millis (200)) . await;
+
+	let status = status_receiver . borrow () . clone ();
+	assert! (status . is_up ());
+	assert_eq! (status . restart_count, 0);
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+	server . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+#[allow (deprecated)]
+async fn ping_nodes_still_ping ()
+{
+	let (client_io, server_io) = duplex (4096);
+	let client_websocket =
+		WebSocketStream::from_raw_socket (client_io, Role::Client, None);
+	let server_websocket =
+		WebSocketStream::from_raw_socket (server_io, Role::Server, None);
+	let client_websocket = client_websocket . await;
+	let mut server_websocket = server_websocket . await;
+
+	let mut node_handle = connection::websocket_node_with_pings
+	(
+		Text,
+		Text,
+		pending::<Utf8Bytes> (),
+		drain (),
+		client_websocket,
+		Duration::from_millis (10),
+		Duration::from_secs (1)
+	);
+
+	let message = timeout (Duration::from_secs (1), server_websocket . next ())
+		. await
+		. unwrap ()
+		. unwrap ()
+		. unwrap ();
+
+	assert! (message . is_ping ());
+
+	node_handle . shutdown ();
+	timeout (Duration::from_secs (1), node_handle) . await . unwrap ();
+}
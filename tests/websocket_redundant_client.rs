@@ -0,0 +1,238 @@
+use std::net::SocketAddr;
+
+use compute_graph::backoff::{BackoffPolicy, Jitter};
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::{SharedStream, mpsc};
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	ReconnectPolicy,
+	WebSocketRedundantClientNode
+};
+use compute_graph::websocket::io_format::Text;
+use futures::{SinkExt, StreamExt};
+use futures::stream::pending;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::channel;
+use tokio::sync::watch;
+use tokio::time::{Duration, sleep, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+use tungstenite::{Message, Utf8Bytes};
+
+async fn listen () -> (TcpListener, String)
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address: SocketAddr = listener . local_addr () . unwrap ();
+
+	(listener, format! ("ws://{}", address))
+}
+
+async fn accept_websocket (listener: &TcpListener) -> WebSocketStream <TcpStream>
+{
+	let (tcp_stream, _) = listener . accept () . await . unwrap ();
+	accept_async (tcp_stream) . await . unwrap ()
+}
+
+fn connection_config (primary: String, standby: String)
+-> ConnectionConfig <String>
+{
+	ConnectionConfig::new (primary)
+		. with_standby (standby)
+		. with_reconnect_policy
+		(
+			ReconnectPolicy::new
+			(
+				BackoffPolicy::new
+				(
+					Duration::from_millis (10),
+					Duration::from_millis (10)
+				)
+					. with_jitter (Jitter::None)
+			)
+		)
+}
+
+async fn send_texts (websocket: &mut WebSocketStream <TcpStream>, texts: &[&str])
+{
+	for text in texts
+	{
+		websocket . send (Message::Text ((*text) . into ())) . await . unwrap ();
+	}
+}
+
+#[tokio::main]
+#[test]
+async fn losing_a_feed_is_invisible_downstream ()
+{
+	let (listener_a, url_a) = listen () . await;
+	let (listener_b, url_b) = listen () . await;
+
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (8);
+
+	let mut redundant_handle = WebSocketRedundantClientNode::new
+	(
+		Text,
+		Text,
+		connection_config (url_a, url_b),
+		pending::<Utf8Bytes> (),
+		output_sink,
+		Utf8Bytes::clone
+	)
+		. into_robust_service ();
+
+	let mut feed_a = accept_websocket (&listener_a) . await;
+	let mut feed_b = accept_websocket (&listener_b) . await;
+
+	send_texts (&mut feed_a, &["1", "2"]) . await;
+	sleep (Duration::from_millis (20)) . await;
+	drop (feed_a);
+
+	send_texts (&mut feed_b, &["1", "2", "3"]) . await;
+
+	for expected in ["1", "2", "3"]
+	{
+		let output = timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+
+		assert_eq! (output . as_str (), expected);
+	}
+
+	let duplicate = timeout (Duration::from_millis (50), output_stream . next ());
+	assert! (duplicate . await . is_err ());
+
+	redundant_handle . shutdown ();
+	timeout (Duration::from_secs (1), redundant_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn input_moves_to_surviving_connection ()
+{
+	let (listener_a, url_a) = listen () . await;
+	let (listener_b, url_b) = listen () . await;
+
+	let (input_sender, input_receiver) = channel::<Utf8Bytes> (1);
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut redundant_handle = WebSocketRedundantClientNode::new
+	(
+		Text,
+		Text,
+		connection_config (url_a, url_b),
+		SharedStream::new (ReceiverStream::new (input_receiver)),
+		output_sink,
+		Utf8Bytes::clone
+	)
+		. into_robust_service ();
+
+	let mut feed_a = accept_websocket (&listener_a) . await;
+	let mut feed_b = accept_websocket (&listener_b) . await;
+
+	// Lets both connections report that they're up.
+	sleep (Duration::from_millis (20)) . await;
+	input_sender . send ("order 1" . into ()) . await . unwrap ();
+
+	let (mut survivor, dropped) = tokio::select!
+	{
+		message = feed_a . next () =>
+		{
+			assert_eq! (message . unwrap () . unwrap (), Message::text ("order 1"));
+			(feed_b, feed_a)
+		},
+		message = feed_b . next () =>
+		{
+			assert_eq! (message . unwrap () . unwrap (), Message::text ("order 1"));
+			(feed_a, feed_b)
+		}
+	};
+
+	drop (dropped);
+	sleep (Duration::from_millis (50)) . await;
+	input_sender . send ("order 2" . into ()) . await . unwrap ();
+
+	let message = timeout (Duration::from_secs (1), survivor . next ())
+		. await
+		. unwrap ()
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (message, Message::text ("order 2"));
+
+	redundant_handle . shutdown ();
+	timeout (Duration::from_secs (1), redundant_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn endpoint_follows_input_route ()
+{
+	let (listener_a, url_a) = listen () . await;
+	let (listener_b, url_b) = listen () . await;
+
+	let (endpoint_sender, mut endpoint) = watch::channel (None);
+	let (output_sink, _output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut redundant_handle = WebSocketRedundantClientNode::new
+	(
+		Text,
+		Text,
+		connection_config (url_a . clone (), url_b . clone ())
+			. with_endpoint_reporting (endpoint_sender),
+		pending::<Utf8Bytes> (),
+		output_sink,
+		Utf8Bytes::clone
+	)
+		. into_robust_service ();
+
+	let feed_a = accept_websocket (&listener_a) . await;
+	let feed_b = accept_websocket (&listener_b) . await;
+
+	let routed = timeout
+	(
+		Duration::from_secs (1),
+		endpoint . wait_for (Option::is_some)
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. clone ()
+		. unwrap ();
+
+	let (survivor_url, survivor_index) = match routed . index
+	{
+		0 =>
+		{
+			drop (feed_a);
+			(url_b, 1)
+		},
+		_ =>
+		{
+			drop (feed_b);
+			(url_a, 0)
+		}
+	};
+
+	let rerouted = timeout
+	(
+		Duration::from_secs (1),
+		endpoint . wait_for
+		(
+			|endpoint| endpoint
+				. as_ref ()
+				. is_some_and (|endpoint| endpoint . index == survivor_index)
+		)
+	)
+		. await
+		. unwrap ()
+		. unwrap ()
+		. clone ()
+		. unwrap ();
+
+	assert_eq! (format! ("{}", rerouted . uri), format! ("{}/", survivor_url));
+
+	redundant_handle . shutdown ();
+	timeout (Duration::from_secs (1), redundant_handle) . await . unwrap ();
+}
@@ -0,0 +1,180 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use compute_graph::backoff::{BackoffPolicy, Jitter};
+use compute_graph::exit_status::FailureKind;
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::{SharedStream, mpsc};
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	ReconnectPolicy,
+	Resolver,
+	StaticResolver,
+	WebSocketClientNode
+};
+use compute_graph::websocket::io_format::Text;
+use futures::future::{BoxFuture, pending};
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::channel;
+use tokio::time::{Duration, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::accept_async;
+use tungstenite::Utf8Bytes;
+
+// Never answers.
+struct StalledResolver;
+
+impl Resolver for StalledResolver
+{
+	fn resolve <'a> (&'a self, _host: &'a str, _port: u16)
+	-> BoxFuture <'a, std::io::Result <Vec <SocketAddr>>>
+	{
+		Box::pin (pending ())
+	}
+}
+
+// Knows no hosts at all.
+struct FailingResolver;
+
+impl Resolver for FailingResolver
+{
+	fn resolve <'a> (&'a self, host: &'a str, _port: u16)
+	-> BoxFuture <'a, std::io::Result <Vec <SocketAddr>>>
+	{
+		let resolve_error =
+			Error::new (ErrorKind::NotFound, format! ("unknown host {}", host));
+
+		Box::pin (async move {Err (resolve_error)})
+	}
+}
+
+fn single_attempt () -> ReconnectPolicy
+{
+	ReconnectPolicy::new
+	(
+		BackoffPolicy::new (Duration::from_millis (1), Duration::from_millis (1))
+			. with_jitter (Jitter::None)
+	)
+		. with_max_attempts (1)
+}
+
+async fn attempt_fails
+(
+	connection_config: ConnectionConfig <String>,
+	kind: FailureKind,
+	message: &str
+)
+{
+	let robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config . with_reconnect_policy (single_attempt ()),
+		futures::stream::pending::<Utf8Bytes> (),
+		mpsc::<Utf8Bytes> (1) . 0
+	)
+		. into_robust_service ();
+
+	let exit_status = timeout (Duration::from_secs (1), robust_handle)
+		. await
+		. unwrap ();
+
+	let failure = exit_status . failure () . unwrap ();
+
+	assert_eq! (failure . kind (), kind);
+	assert_eq! (failure . message (), message);
+}
+
+#[tokio::main]
+#[test]
+async fn races_past_black_holed_address ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let port = listener . local_addr () . unwrap () . port ();
+
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+			let message = websocket . next () . await . unwrap () . unwrap ();
+			websocket . send (message) . await . unwrap ();
+		}
+	);
+
+	// 100::/64 is reserved for discarding traffic, so connections to it
+	// either stall or fail outright.
+	let resolver = StaticResolver::new () . with_override
+	(
+		"feed.test",
+		[
+			IpAddr::V6 (Ipv6Addr::new (0x100, 0, 0, 0, 0, 0, 0, 1)),
+			IpAddr::V4 (Ipv4Addr::LOCALHOST)
+		]
+	);
+
+	let connection_config =
+		ConnectionConfig::new (format! ("ws://feed.test:{}", port))
+			. with_resolver (resolver)
+			. with_connection_attempt_delay (Duration::from_millis (20))
+			. with_connect_timeout (Duration::from_secs (30))
+			. with_reconnect_policy (single_attempt ());
+
+	let (input_sender, input_receiver) = channel::<Utf8Bytes> (1);
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		SharedStream::new (ReceiverStream::new (input_receiver)),
+		output_sink
+	)
+		. into_robust_service ();
+
+	input_sender . send ("hello" . into ()) . await . unwrap ();
+
+	let echo = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (echo . as_str (), "hello");
+	server . await . unwrap ();
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn stalled_resolution_times_out ()
+{
+	attempt_fails
+	(
+		ConnectionConfig::new ("ws://feed.test:9000" . to_string ())
+			. with_resolver (StalledResolver)
+			. with_resolve_timeout (Duration::from_millis (50)),
+		FailureKind::Resolution,
+		"resolving feed.test timed out"
+	)
+		. await;
+}
+
+#[tokio::main]
+#[test]
+async fn failed_resolution_fails_the_attempt ()
+{
+	attempt_fails
+	(
+		ConnectionConfig::new ("ws://feed.test:9000" . to_string ())
+			. with_resolver (FailingResolver),
+		FailureKind::Resolution,
+		"unknown host feed.test"
+	)
+		. await;
+}
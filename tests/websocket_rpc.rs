@@ -0,0 +1,175 @@
+use compute_graph::exit_status::FailureKind;
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	WebSocketRpcClientNode
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::accept_async;
+use tungstenite::Message;
+
+async fn listen () -> (TcpListener, ConnectionConfig <String>)
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	(listener, ConnectionConfig::new (format! ("ws://{}", address)))
+}
+
+#[tokio::main]
+#[test]
+async fn replies_are_matched_to_calls ()
+{
+	let (listener, connection_config) = listen () . await;
+
+	// Announces itself, then answers requests in reverse order.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			let hello = json! ({"event": "hello"}) . to_string ();
+			websocket . send (Message::text (hello)) . await . unwrap ();
+
+			let mut requests = Vec::new ();
+
+			while requests . len () < 2
+			{
+				let message = websocket . next () . await . unwrap ();
+				let message = message . unwrap ();
+				let text = message . to_text () . unwrap ();
+				let request: Value = serde_json::from_str (text) . unwrap ();
+
+				requests . push (request);
+			}
+
+			for request in requests . iter () . rev ()
+			{
+				let reply = json!
+				({
+					"id": request ["id"],
+					"result": request ["params"] . as_i64 () . unwrap () * 2
+				});
+
+				websocket
+					. send (Message::text (reply . to_string ()))
+					. await
+					. unwrap ();
+			}
+		}
+	);
+
+	let (output_sink, mut output_stream) = mpsc::<Value> (1);
+	let rpc_node = WebSocketRpcClientNode::new (connection_config, output_sink);
+	let rpc_client = rpc_node . client ();
+	let mut robust_handle = rpc_node . into_robust_service ();
+
+	let (first, second) = tokio::join!
+	(
+		rpc_client . call::<_, Value> (json! ({"method": "x2", "params": 1})),
+		rpc_client . call::<_, Value> (json! ({"method": "x2", "params": 21}))
+	);
+
+	assert_eq! (first . unwrap () ["result"], 2);
+	assert_eq! (second . unwrap () ["result"], 42);
+
+	let hello = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (hello, json! ({"event": "hello"}));
+
+	server . await . unwrap ();
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn pending_calls_fail_when_connection_drops ()
+{
+	let (listener, connection_config) = listen () . await;
+
+	// Hangs up as soon as the first request arrives.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			websocket . next () . await . unwrap () . unwrap ();
+		}
+	);
+
+	let rpc_node = WebSocketRpcClientNode::<_, Value, _>::new
+	(
+		connection_config,
+		mpsc::<Value> (1) . 0
+	);
+
+	let rpc_client = rpc_node . client ();
+	let mut robust_handle = rpc_node . into_robust_service ();
+
+	let failure = timeout
+	(
+		Duration::from_secs (1),
+		rpc_client . call::<_, Value> (json! ({"method": "hang up"}))
+	)
+		. await
+		. unwrap ()
+		. unwrap_err ();
+
+	assert_eq! (failure . kind (), FailureKind::Connection);
+
+	server . await . unwrap ();
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn unanswered_calls_time_out ()
+{
+	let (listener, connection_config) = listen () . await;
+
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			while let Some (Ok (_)) = websocket . next () . await {}
+		}
+	);
+
+	let rpc_node = WebSocketRpcClientNode::<_, Value, _>::new
+	(
+		connection_config,
+		mpsc::<Value> (1) . 0
+	)
+		. with_call_timeout (Duration::from_millis (50));
+
+	let rpc_client = rpc_node . client ();
+	let mut robust_handle = rpc_node . into_robust_service ();
+
+	let failure = rpc_client
+		. call::<_, Value> (json! ({"method": "ignored"}))
+		. await
+		. unwrap_err ();
+
+	assert_eq! (failure . kind (), FailureKind::Timeout);
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+	server . abort ();
+}
@@ -0,0 +1,67 @@
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::mpsc;
+use compute_graph::websocket::io_format::Text;
+use compute_graph::websocket::server::{ServerConfig, websocket_server_node};
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio::time::{Duration, timeout};
+use tokio_tungstenite::connect_async;
+use tungstenite::Message;
+
+#[tokio::main]
+#[test]
+async fn echo_connection ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	let (connection_sink, mut connection_stream) = mpsc (1);
+
+	let mut server_handle = websocket_server_node
+	(
+		Text,
+		Text,
+		listener,
+		ServerConfig::new (),
+		connection_sink
+	);
+
+	let (mut client, _) = connect_async (format! ("ws://{}", address))
+		. await
+		. unwrap ();
+
+	let mut connection = timeout
+	(
+		Duration::from_secs (1),
+		connection_stream . next ()
+	)
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	client . send (Message::Text ("ping" . into ())) . await . unwrap ();
+
+	let received = connection . output . next () . await . unwrap ();
+	assert_eq! (received . as_str (), "ping");
+
+	connection . input . send ("pong" . into ()) . await . unwrap ();
+
+	match client . next () . await
+	{
+		Some (Ok (Message::Text (text))) => assert_eq! (text . as_str (), "pong"),
+		other => panic! ("unexpected message: {:?}", other)
+	}
+
+	server_handle . shutdown ();
+
+	let exit_status = timeout (Duration::from_secs (1), server_handle)
+		. await
+		. unwrap ();
+	assert! (exit_status . is_clean ());
+
+	match client . next () . await
+	{
+		Some (Ok (Message::Close (_))) => (),
+		other => panic! ("expected close frame, got {:?}", other)
+	}
+}
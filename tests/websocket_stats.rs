@@ -0,0 +1,167 @@
+use compute_graph::robust_service::SignallableRobustService;
+use compute_graph::service_handle::ServiceHandle;
+use compute_graph::stream::{SharedStream, mpsc};
+use compute_graph::websocket::{ConnectionStats, PingConfig};
+use compute_graph::websocket::client::{
+	ConnectionConfig,
+	WebSocketClientNode,
+	WebSocketClientNodeWithPings
+};
+use compute_graph::websocket::io_format::Text;
+use futures::{SinkExt, StreamExt};
+use futures::stream::pending;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::channel;
+use tokio::sync::watch;
+use tokio::time::{Duration, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::accept_async;
+use tungstenite::Utf8Bytes;
+
+#[tokio::main]
+#[test]
+async fn stats_track_traffic_and_round_trips ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	// Echoes text messages, answering pings as it reads.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			while let Some (Ok (message)) = websocket . next () . await
+			{
+				if message . is_text ()
+				{
+					websocket . send (message) . await . unwrap ();
+				}
+			}
+		}
+	);
+
+	let (stats_sender, mut stats_receiver) =
+		watch::channel (ConnectionStats::default ());
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_stats_reporting (stats_sender);
+
+	let (input_sender, input_receiver) = channel::<Utf8Bytes> (1);
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (1);
+
+	let mut robust_handle = WebSocketClientNodeWithPings::new
+	(
+		Text,
+		Text,
+		connection_config,
+		SharedStream::new (ReceiverStream::new (input_receiver)),
+		output_sink,
+		PingConfig
+		{
+			ping_interval: Duration::from_millis (10),
+			ping_timeout: Duration::from_millis (500)
+		}
+	)
+		. into_robust_service ();
+
+	input_sender . send ("hello" . into ()) . await . unwrap ();
+
+	let echo = timeout (Duration::from_secs (1), output_stream . next ())
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (echo . as_str (), "hello");
+
+	let stats = *timeout
+	(
+		Duration::from_secs (1),
+		stats_receiver . wait_for
+		(
+			|stats| stats . messages_received == 1
+				&& stats . round_trip_time . is_some ()
+		)
+	)
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (stats . messages_sent, 1);
+	assert_eq! (stats . missed_pongs, 0);
+	assert! (stats . bytes_sent >= 5);
+	assert! (stats . bytes_received >= 5);
+	assert! (stats . uptime () . is_some ());
+
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+	server . await . unwrap ();
+}
+
+#[tokio::main]
+#[test]
+async fn stats_catch_up_when_connection_ends ()
+{
+	let listener = TcpListener::bind ("127.0.0.1:0") . await . unwrap ();
+	let address = listener . local_addr () . unwrap ();
+
+	// Sends a burst faster than stats are published, then hangs up.
+	let server = tokio::spawn
+	(
+		async move
+		{
+			let (tcp_stream, _) = listener . accept () . await . unwrap ();
+			let mut websocket = accept_async (tcp_stream) . await . unwrap ();
+
+			for i in 0..20
+			{
+				websocket . feed (i . to_string () . into ()) . await . unwrap ();
+			}
+
+			websocket . close (None) . await . unwrap ();
+		}
+	);
+
+	let (stats_sender, mut stats_receiver) =
+		watch::channel (ConnectionStats::default ());
+
+	let connection_config = ConnectionConfig::new (format! ("ws://{}", address))
+		. with_stats_reporting (stats_sender);
+
+	let (output_sink, mut output_stream) = mpsc::<Utf8Bytes> (32);
+
+	let mut robust_handle = WebSocketClientNode::new
+	(
+		Text,
+		Text,
+		connection_config,
+		pending::<Utf8Bytes> (),
+		output_sink
+	)
+		. into_robust_service ();
+
+	for _ in 0..20
+	{
+		timeout (Duration::from_secs (1), output_stream . next ())
+			. await
+			. unwrap ()
+			. unwrap ();
+	}
+
+	let stats = *timeout
+	(
+		Duration::from_secs (1),
+		stats_receiver . wait_for (|stats| stats . messages_received == 20)
+	)
+		. await
+		. unwrap ()
+		. unwrap ();
+
+	assert_eq! (stats . messages_sent, 0);
+
+	server . await . unwrap ();
+	robust_handle . shutdown ();
+	timeout (Duration::from_secs (1), robust_handle) . await . unwrap ();
+}